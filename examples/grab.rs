@@ -1,14 +1,13 @@
-#[cfg(target_os = "linux")]
 use core::time::Duration;
-use rdev::Event;
-use rdev::EventType;
-#[cfg(target_os = "linux")]
-use rdev::{disable_grab, enable_grab, exit_grab_listen, start_grab_listen};
-#[cfg(target_os = "linux")]
+use rdev::{Event, EventType, Key, start_grab};
 use std::thread;
 
 fn callback(event: Event) -> Option<Event> {
     match event.event_type {
+        EventType::KeyPress(Key::CapsLock) | EventType::KeyRelease(Key::CapsLock) => {
+            println!("Consuming and cancelling CapsLock");
+            None
+        }
         EventType::KeyPress(_key) | EventType::KeyRelease(_key) => {
             /*  */
             println!(
@@ -21,35 +20,30 @@ fn callback(event: Event) -> Option<Event> {
     }
 }
 
-#[cfg(target_os = "linux")]
 fn main() {
     let delay = Duration::from_secs(5);
 
-    println!("[*] starting grab listen...");
-    if let Err(err) = start_grab_listen(callback) {
-        eprintln!("start grab listen error: {:?}", err);
-        return;
+    println!("[*] starting grab...");
+    let handle = match start_grab(callback) {
+        Ok(handle) => handle,
+        Err(err) => {
+            eprintln!("start grab error: {:?}", err);
+            return;
+        }
     };
 
-    println!("[*] grab keys(5s), try to press Ctrl+C, won't work on other applications");
-    enable_grab();
+    println!("[*] grab keys(5s), try to press CapsLock, won't work on other applications");
     thread::sleep(delay);
 
-    println!("[*] ungrab keys(5s), try to press Ctrl+C");
-    disable_grab();
+    println!("[*] ungrab keys(5s), try to press CapsLock");
+    handle.pause();
     thread::sleep(delay);
 
-    println!("[*] grab keys(5s), try to press Ctrl+C, won't work on other applications");
-    enable_grab();
+    println!("[*] grab keys(5s), try to press CapsLock, won't work on other applications");
+    handle.resume();
     thread::sleep(delay);
 
-    exit_grab_listen();
-}
-
-#[cfg(any(target_os = "windows", target_os = "macos"))]
-fn main() {
-    // This will block.
-    if let Err(error) = rdev::grab(callback) {
-        println!("Error: {:?}", error)
+    if let Err(err) = handle.stop() {
+        eprintln!("stop grab error: {:?}", err);
     }
 }
@@ -1,7 +1,9 @@
 use parking_lot::Mutex;
 #[cfg(target_os = "windows")]
 use rdev::get_win_key;
-use rdev::{Event, EventType::*, Key as RdevKey, Keyboard as RdevKeyboard, KeyboardState};
+use rdev::{
+    Event, EventType::*, Key as RdevKey, Keyboard as RdevKeyboard, KeyboardState, ListenerBuilder,
+};
 use std::collections::HashMap;
 use std::sync::LazyLock;
 
@@ -23,10 +25,6 @@ static KEYBOARD: LazyLock<Mutex<RdevKeyboard>> =
     LazyLock::new(|| Mutex::new(RdevKeyboard::new().expect("Failed to create keyboard")));
 
 fn main() {
-    // This will block.
-    // SAFETY: This is single-threaded example code, no race conditions possible
-    unsafe { std::env::set_var("KEYBOARD_ONLY", "y") };
-
     let func = |evt: Event| {
         let (_key, _down) = match evt.event_type {
             KeyPress(k) => {
@@ -86,8 +84,13 @@ fn main() {
 
         println!("--------------");
     };
-    if let Err(error) = rdev::listen(func) {
-        // rdev::listen
+    // This will block.
+    let keyboard_only = ListenerBuilder::new()
+        .buttons(false)
+        .motion(false)
+        .wheel(false)
+        .raw(false);
+    if let Err(error) = keyboard_only.listen(func) {
         dbg!("{:?}", error);
     }
 }
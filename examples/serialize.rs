@@ -1,4 +1,4 @@
-use rdev::{Event, EventType, Key, UnicodeInfo};
+use rdev::{Event, EventType, Key, Modifiers, UnicodeInfo};
 use std::time::SystemTime;
 
 fn main() {
@@ -15,6 +15,7 @@ fn main() {
         usb_hid: 0,
         extra_data: 0 as _,
         is_synthetic: false,
+        modifiers: Modifiers::empty(),
     };
 
     let serialized = serde_json::to_string(&event).unwrap();
@@ -1,6 +1,6 @@
 #[cfg(target_os = "linux")]
 use core::time;
-use rdev::{Event, EventType, GrabError, Key as RdevKey};
+use rdev::{Event, EventType, GrabError, Key as RdevKey, Modifiers};
 #[cfg(target_os = "linux")]
 use rdev::{key_from_code, linux_keycode_from_key, simulate};
 #[cfg(target_os = "linux")]
@@ -42,6 +42,7 @@ fn convert_event(key: RdevKey, is_press: bool) -> Event {
         position_code: linux_keycode_from_key(key).unwrap_or_default() as _,
         usb_hid: 0,
         is_synthetic: true,
+        modifiers: Modifiers::empty(),
     }
 }
 
@@ -1,22 +1,18 @@
-use rdev::listen;
-use std::thread;
-use tokio::sync::mpsc;
+use rdev::{EventType, Key, listen_stream, simulate_async};
 
 #[tokio::main]
 async fn main() {
-    // spawn new thread because listen blocks
-    let (schan, mut rchan) = mpsc::unbounded_channel();
-    let _listener = thread::spawn(move || {
-        listen(move |event| {
-            schan
-                .send(event)
-                .unwrap_or_else(|e| println!("Could not send event {:?}", e));
-        })
-        .expect("Could not listen");
-    });
+    // The listener runs in the background until the stream is dropped.
+    let mut events = listen_stream().expect("Could not listen");
 
-    loop {
-        let event = rchan.recv().await;
+    simulate_async(&EventType::KeyPress(Key::ShiftLeft))
+        .await
+        .unwrap_or_else(|e| println!("Could not simulate {:?}", e));
+    simulate_async(&EventType::KeyRelease(Key::ShiftLeft))
+        .await
+        .unwrap_or_else(|e| println!("Could not simulate {:?}", e));
+
+    while let Some(event) = events.recv().await {
         println!("Received {:?}", event);
     }
 }
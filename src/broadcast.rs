@@ -0,0 +1,269 @@
+use crate::listen::{ListenHandle, start_listen};
+use crate::rdev::{Event, ListenError};
+use crossbeam_channel::{
+    Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError, TrySendError, bounded,
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+pub(crate) type EventFilter = Box<dyn Fn(&Event) -> bool + Send>;
+
+/// Where the events of a subscriber go.
+pub(crate) enum Sink {
+    Channel(Sender<Event>),
+    #[cfg(feature = "tokio")]
+    Stream(tokio::sync::mpsc::Sender<Event>),
+}
+
+impl Sink {
+    /// Returns false when the event had to be discarded.
+    fn try_send(&self, event: Event) -> bool {
+        match self {
+            Sink::Channel(sender) => !matches!(sender.try_send(event), Err(TrySendError::Full(_))),
+            #[cfg(feature = "tokio")]
+            Sink::Stream(sender) => !matches!(
+                sender.try_send(event),
+                Err(tokio::sync::mpsc::error::TrySendError::Full(_))
+            ),
+        }
+    }
+}
+
+struct Subscriber {
+    id: u64,
+    filter: EventFilter,
+    sink: Sink,
+    dropped: Arc<AtomicU64>,
+}
+
+// Locked in this order. The listen callback only takes SUBSCRIBERS, so the
+// listener can be stopped while holding LISTENER.
+static LISTENER: Mutex<Option<ListenHandle>> = Mutex::new(None);
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Detaches its subscriber when dropped, stopping the listener with the last one.
+pub(crate) struct Attachment {
+    id: u64,
+}
+
+impl Drop for Attachment {
+    fn drop(&mut self) {
+        let mut listener = LISTENER.lock();
+        let is_last = {
+            let mut subscribers = SUBSCRIBERS.lock();
+            subscribers.retain(|subscriber| subscriber.id != self.id);
+            subscribers.is_empty()
+        };
+        if is_last && let Some(handle) = listener.take() {
+            let _ = handle.stop();
+        }
+    }
+}
+
+/// A subscription to the global input events, see [`subscribe`](crate::subscribe).
+///
+/// Dropping it detaches the subscriber.
+pub struct Subscription {
+    receiver: Receiver<Event>,
+    dropped: Arc<AtomicU64>,
+    _attachment: Attachment,
+}
+
+impl Subscription {
+    pub fn receiver(&self) -> &Receiver<Event> {
+        &self.receiver
+    }
+
+    pub fn recv(&self) -> Result<Event, RecvError> {
+        self.receiver.recv()
+    }
+
+    pub fn try_recv(&self) -> Result<Event, TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Event, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+
+    /// Number of events that were discarded because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Subscribes to the global input events. Every subscriber gets its own copy
+/// of the events `filter` accepts, in a queue holding up to `capacity` events:
+/// when it is full, new events are discarded for that subscriber only. A
+/// `capacity` of 0 is taken as 1, the queue has to hold the event until it
+/// is received.
+///
+/// The OS listener is shared: it is started with the first subscriber and
+/// stopped when the last one is dropped. It cannot be combined with `listen`
+/// or `start_listen`, which fail with `ListenError::AlreadyListening` while
+/// there are subscribers, and the other way round.
+///
+/// ```no_run
+/// use rdev::{subscribe, EventType};
+///
+/// let keys = subscribe(
+///     |event| matches!(event.event_type, EventType::KeyPress(_)),
+///     64,
+/// )
+/// .unwrap();
+/// let everything = subscribe(|_| true, 1024).unwrap();
+/// for event in keys.receiver().iter() {
+///     println!("{:?}", event.event_type);
+/// }
+/// ```
+pub fn subscribe<F>(filter: F, capacity: usize) -> Result<Subscription, ListenError>
+where
+    F: Fn(&Event) -> bool + Send + 'static,
+{
+    let (sender, receiver) = queue(capacity);
+    let dropped = Arc::new(AtomicU64::new(0));
+    let attachment = attach(Box::new(filter), Sink::Channel(sender), dropped.clone())?;
+    Ok(Subscription {
+        receiver,
+        dropped,
+        _attachment: attachment,
+    })
+}
+
+/// A queue of at least one event: the listener never waits for a receiver,
+/// so a rendezvous channel would discard everything.
+fn queue(capacity: usize) -> (Sender<Event>, Receiver<Event>) {
+    bounded(capacity.max(1))
+}
+
+/// Adds a subscriber, starting the listener if it is the first one.
+pub(crate) fn attach(
+    filter: EventFilter,
+    sink: Sink,
+    dropped: Arc<AtomicU64>,
+) -> Result<Attachment, ListenError> {
+    let mut listener = LISTENER.lock();
+    let attachment = add_subscriber(filter, sink, dropped);
+    if listener.is_none() {
+        match start_listen(dispatch) {
+            Ok(handle) => *listener = Some(handle),
+            Err(err) => {
+                // Detaching takes the lock again.
+                drop(listener);
+                drop(attachment);
+                return Err(err);
+            }
+        }
+    }
+    Ok(attachment)
+}
+
+fn add_subscriber(filter: EventFilter, sink: Sink, dropped: Arc<AtomicU64>) -> Attachment {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    SUBSCRIBERS.lock().push(Subscriber {
+        id,
+        filter,
+        sink,
+        dropped,
+    });
+    Attachment { id }
+}
+
+fn dispatch(event: Event) {
+    for subscriber in SUBSCRIBERS.lock().iter() {
+        if (subscriber.filter)(&event) && !subscriber.sink.try_send(event.clone()) {
+            subscriber.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdev::{EventType, Key};
+
+    fn key_press(key: Key) -> Event {
+        Event::at(EventType::KeyPress(key), 0)
+    }
+
+    fn only(key: Key) -> EventFilter {
+        Box::new(move |event| event.event_type == EventType::KeyPress(key))
+    }
+
+    fn add_subscriber(filter: EventFilter, capacity: usize) -> Subscription {
+        let (sender, receiver) = queue(capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let attachment = super::add_subscriber(filter, Sink::Channel(sender), dropped.clone());
+        Subscription {
+            receiver,
+            dropped,
+            _attachment: attachment,
+        }
+    }
+
+    #[test]
+    fn test_filters() {
+        let f1 = add_subscriber(only(Key::F1), 8);
+        let f2 = add_subscriber(only(Key::F2), 8);
+        dispatch(key_press(Key::F1));
+        dispatch(key_press(Key::F2));
+        dispatch(key_press(Key::F1));
+        assert_eq!(f1.receiver().len(), 2);
+        assert_eq!(
+            f2.try_recv().map(|event| event.event_type),
+            Ok(EventType::KeyPress(Key::F2))
+        );
+        assert!(f2.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_full_queue() {
+        let subscription = add_subscriber(only(Key::F3), 2);
+        for _ in 0..5 {
+            dispatch(key_press(Key::F3));
+        }
+        assert_eq!(subscription.receiver().len(), 2);
+        assert_eq!(subscription.dropped(), 3);
+    }
+
+    #[test]
+    fn test_zero_capacity() {
+        let subscription = add_subscriber(only(Key::F5), 0);
+        dispatch(key_press(Key::F5));
+        dispatch(key_press(Key::F5));
+        assert_eq!(subscription.receiver().len(), 1);
+        assert_eq!(subscription.dropped(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "tokio")]
+    fn test_full_stream() {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let _attachment =
+            super::add_subscriber(only(Key::F6), Sink::Stream(sender), dropped.clone());
+        dispatch(key_press(Key::F6));
+        dispatch(key_press(Key::F6));
+        assert!(receiver.try_recv().is_ok());
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(dropped.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_detach() {
+        let subscription = add_subscriber(only(Key::F4), 2);
+        let id = subscription._attachment.id;
+        drop(subscription);
+        assert!(
+            SUBSCRIBERS
+                .lock()
+                .iter()
+                .all(|subscriber| subscriber.id != id)
+        );
+        // Nothing left to deliver to.
+        dispatch(key_press(Key::F4));
+    }
+}
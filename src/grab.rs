@@ -0,0 +1,139 @@
+use crate::rdev::{Event, GrabError};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+#[cfg(not(all(target_os = "linux", not(feature = "unstable_grab"))))]
+use std::time::Duration;
+
+/// A grab started with [`start_grab`](crate::start_grab).
+///
+/// Dropping the handle stops the grab. Neither `stop` nor dropping the
+/// handle may happen from inside the grab callback.
+#[derive(Debug)]
+pub struct GrabHandle {
+    paused: Arc<AtomicBool>,
+    // None with the X11 grab, which runs on its own threads.
+    thread: Option<JoinHandle<Result<(), GrabError>>>,
+    stopped: bool,
+}
+
+impl GrabHandle {
+    /// Lets every event through, without calling the callback, until `resume`.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+        #[cfg(all(target_os = "linux", not(feature = "unstable_grab")))]
+        crate::linux::set_grab(false);
+    }
+
+    pub fn resume(&self) {
+        #[cfg(all(target_os = "linux", not(feature = "unstable_grab")))]
+        crate::linux::set_grab(true);
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Stops the grab and waits for it to end, returning the error it ended
+    /// with, if any.
+    pub fn stop(mut self) -> Result<(), GrabError> {
+        self.stop_()
+    }
+
+    fn stop_(&mut self) -> Result<(), GrabError> {
+        if self.stopped {
+            return Ok(());
+        }
+        self.stopped = true;
+        crate::exit_grab()?;
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(payload)) => std::panic::resume_unwind(payload),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for GrabHandle {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            let _ = self.stop_();
+        }
+    }
+}
+
+/// Starts grabbing global events in the background and returns once the grab
+/// is in place. The callback behaves as in [`grab`](crate::grab): returning
+/// `None` swallows the event, returning the event lets it pass.
+///
+/// Only one grab can run at a time, starting a second one fails with
+/// `GrabError::AlreadyGrabbing`.
+///
+/// ```no_run
+/// use rdev::{start_grab, EventType, Key};
+///
+/// let handle = start_grab(|event| match event.event_type {
+///     EventType::KeyPress(Key::CapsLock) | EventType::KeyRelease(Key::CapsLock) => None,
+///     _ => Some(event),
+/// })
+/// .unwrap();
+/// // CapsLock works again while paused.
+/// handle.pause();
+/// handle.resume();
+/// handle.stop().unwrap();
+/// ```
+pub fn start_grab<T>(mut callback: T) -> Result<GrabHandle, GrabError>
+where
+    T: FnMut(Event) -> Option<Event> + Send + 'static,
+{
+    let paused = Arc::new(AtomicBool::new(false));
+    let is_paused = paused.clone();
+    let callback = move |event: Event| {
+        if is_paused.load(Ordering::SeqCst) {
+            Some(event)
+        } else {
+            callback(event)
+        }
+    };
+    Ok(GrabHandle {
+        paused,
+        thread: spawn_grab(callback)?,
+        stopped: false,
+    })
+}
+
+#[cfg(all(target_os = "linux", not(feature = "unstable_grab")))]
+fn spawn_grab<T>(callback: T) -> Result<Option<JoinHandle<Result<(), GrabError>>>, GrabError>
+where
+    T: FnMut(Event) -> Option<Event> + Send + 'static,
+{
+    crate::linux::start_grab(callback)?;
+    crate::linux::set_grab(true);
+    Ok(None)
+}
+
+#[cfg(not(all(target_os = "linux", not(feature = "unstable_grab"))))]
+fn spawn_grab<T>(callback: T) -> Result<Option<JoinHandle<Result<(), GrabError>>>, GrabError>
+where
+    T: FnMut(Event) -> Option<Event> + Send + 'static,
+{
+    if crate::is_grabbed() {
+        return Err(GrabError::AlreadyGrabbing);
+    }
+    let thread = thread::spawn(move || crate::grab(callback));
+    loop {
+        if crate::is_grabbed() {
+            return Ok(Some(thread));
+        }
+        if thread.is_finished() {
+            return match thread.join() {
+                Ok(Err(err)) => Err(err),
+                // grab() returns right away when another grab is running.
+                Ok(Ok(())) => Err(GrabError::AlreadyGrabbing),
+                Err(payload) => std::panic::resume_unwind(payload),
+            };
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,407 @@
+use crate::rdev::{Event, EventType, Key, Modifiers, ParseAcceleratorError};
+use std::fmt;
+use std::str::FromStr;
+
+// Left and right keys of each modifier, in the order accelerators list them.
+const MODIFIERS: [(Key, Key); 4] = [
+    (Key::ControlLeft, Key::ControlRight),
+    (Key::Alt, Key::AltGr),
+    (Key::ShiftLeft, Key::ShiftRight),
+    (Key::MetaLeft, Key::MetaRight),
+];
+
+pub(crate) fn is_modifier(key: Key) -> bool {
+    MODIFIERS
+        .iter()
+        .any(|(left, right)| key == *left || key == *right)
+}
+
+/// The left key for both sides of a modifier, or the key itself.
+fn either_side(key: Key) -> Key {
+    MODIFIERS
+        .iter()
+        .find(|(_, right)| key == *right)
+        .map_or(key, |(left, _)| *left)
+}
+
+fn modifier_rank(key: Key) -> usize {
+    MODIFIERS
+        .iter()
+        .flat_map(|(left, right)| [*left, *right])
+        .position(|modifier| modifier == key)
+        .unwrap_or(usize::MAX)
+}
+
+fn normalized(modifiers: impl IntoIterator<Item = Key>) -> Vec<Key> {
+    let mut modifiers: Vec<Key> = modifiers.into_iter().collect();
+    modifiers.sort_by_key(|key| modifier_rank(*key));
+    modifiers.dedup();
+    modifiers
+}
+
+/// A key along with the modifiers that must be held, like `Ctrl+Alt+Delete`.
+///
+/// By default the left and right keys of a modifier are the same: `Ctrl+C`
+/// also matches with the right Control key, and `ControlRight+C` with the
+/// left one. [`Accelerator::distinct_sides`] tells them apart.
+///
+/// ```
+/// use rdev::{Accelerator, Key};
+///
+/// let accelerator: Accelerator = "Meta+Shift+Num4".parse().unwrap();
+/// assert_eq!(accelerator.key(), Key::Num4);
+/// assert_eq!(accelerator.modifiers(), [Key::ShiftLeft, Key::MetaLeft]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Accelerator {
+    key: Key,
+    modifiers: Vec<Key>,
+    distinct_sides: bool,
+}
+
+impl Accelerator {
+    /// Any key of `modifiers` that is not a modifier is ignored.
+    pub fn new(key: Key, modifiers: &[Key]) -> Accelerator {
+        Accelerator {
+            key,
+            modifiers: normalized(modifiers.iter().copied().filter(|key| is_modifier(*key))),
+            distinct_sides: false,
+        }
+    }
+
+    /// Whether `ControlLeft` only matches the left Control key, and so on.
+    /// `Ctrl`, `Shift`, `Alt` and `Meta` are the left keys then.
+    pub fn distinct_sides(mut self, distinct: bool) -> Accelerator {
+        self.distinct_sides = distinct;
+        self
+    }
+
+    pub fn key(&self) -> Key {
+        self.key
+    }
+
+    pub fn modifiers(&self) -> &[Key] {
+        &self.modifiers
+    }
+
+    /// Whether pressing `key` while exactly the `held` modifiers are down
+    /// triggers the accelerator. Other keys in `held` are ignored.
+    pub fn matches(&self, held: &[Key], key: Key) -> bool {
+        let held = held
+            .iter()
+            .copied()
+            .filter(|modifier| is_modifier(*modifier) && *modifier != key);
+        if self.distinct_sides {
+            key == self.key && normalized(held) == self.modifiers
+        } else {
+            either_side(key) == either_side(self.key)
+                && normalized(held.map(either_side))
+                    == normalized(self.modifiers.iter().copied().map(either_side))
+        }
+    }
+}
+
+impl fmt::Display for Accelerator {
+    /// Writes the canonical key names, `ControlLeft+AltLeft+Delete`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{modifier}+")?;
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+impl FromStr for Accelerator {
+    type Err = ParseAcceleratorError;
+
+    /// Reads key names separated by `+`, the modifiers first. The names are
+    /// the ones of [`Key::from_str`], aliases included.
+    fn from_str(s: &str) -> Result<Accelerator, ParseAcceleratorError> {
+        if s.trim().is_empty() {
+            return Err(ParseAcceleratorError::Empty);
+        }
+        let mut keys = s
+            .split('+')
+            .map(str::parse::<Key>)
+            .collect::<Result<Vec<_>, _>>()?;
+        let key = keys.pop().ok_or(ParseAcceleratorError::Empty)?;
+        if let Some(key) = keys.iter().find(|key| !is_modifier(**key)) {
+            return Err(ParseAcceleratorError::NotAModifier(*key));
+        }
+        Ok(Accelerator::new(key, &keys))
+    }
+}
+
+/// The modifiers a held key shows up as in [`Event::modifiers`], the right
+/// Alt key is Alt or AltGr depending on the layout.
+fn shows_as(key: Key) -> Modifiers {
+    match key {
+        Key::Alt | Key::AltGr => Modifiers::ALT | Modifiers::ALT_GR,
+        key => Modifiers::from_key(key),
+    }
+}
+
+/// The modifiers currently down, as far as the events tell.
+#[derive(Debug, Default)]
+pub(crate) struct HeldModifiers {
+    keys: Vec<Key>,
+}
+
+impl HeldModifiers {
+    /// Forgets the keys missing from the modifiers of an event, their
+    /// release was missed when the focus moved while they were held.
+    pub(crate) fn sync(&mut self, modifiers: Modifiers) {
+        self.keys.retain(|key| modifiers.intersects(shows_as(*key)));
+    }
+
+    pub(crate) fn update(&mut self, event_type: &EventType) {
+        match event_type {
+            EventType::KeyPress(key) if is_modifier(*key) && !self.keys.contains(key) => {
+                self.keys.push(*key)
+            }
+            EventType::KeyRelease(key) => self.keys.retain(|held| held != key),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn keys(&self) -> &[Key] {
+        &self.keys
+    }
+}
+
+/// Identifies a hotkey of a [`HotkeyManager`], to unregister it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HotkeyId(u64);
+
+struct Hotkey {
+    id: HotkeyId,
+    accelerator: Accelerator,
+    callback: Box<dyn FnMut() + Send>,
+}
+
+/// Calls the callbacks of the accelerators pressed in the events it is fed.
+///
+/// Feed it every event from [`listen`](crate::listen), or from a grab
+/// callback to swallow the hotkeys:
+///
+/// ```no_run
+/// use rdev::{start_grab, HotkeyManager};
+///
+/// let mut hotkeys = HotkeyManager::new();
+/// hotkeys.register("Ctrl+Alt+K".parse().unwrap(), || println!("Ctrl+Alt+K"));
+/// let handle = start_grab(move |event| {
+///     if hotkeys.handle(&event) {
+///         None
+///     } else {
+///         Some(event)
+///     }
+/// })
+/// .unwrap();
+/// ```
+#[derive(Default)]
+pub struct HotkeyManager {
+    hotkeys: Vec<Hotkey>,
+    next_id: u64,
+    held: HeldModifiers,
+    // The keys whose press triggered a hotkey, until they are released.
+    triggered: Vec<Key>,
+}
+
+impl HotkeyManager {
+    pub fn new() -> HotkeyManager {
+        HotkeyManager::default()
+    }
+
+    /// Calls `callback` every time `accelerator` is pressed, key repeats
+    /// included. Several callbacks can share an accelerator.
+    pub fn register<F>(&mut self, accelerator: Accelerator, callback: F) -> HotkeyId
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let id = HotkeyId(self.next_id);
+        self.next_id += 1;
+        self.hotkeys.push(Hotkey {
+            id,
+            accelerator,
+            callback: Box::new(callback),
+        });
+        id
+    }
+
+    /// Returns false if the hotkey was already unregistered.
+    pub fn unregister(&mut self, id: HotkeyId) -> bool {
+        let len = self.hotkeys.len();
+        self.hotkeys.retain(|hotkey| hotkey.id != id);
+        self.hotkeys.len() != len
+    }
+
+    /// Calls the callbacks the event triggers, and returns whether it belongs
+    /// to a hotkey: the press of its key, the repeats and the release. Those
+    /// are the events to swallow in a grab, the modifiers have already gone
+    /// through.
+    pub fn handle(&mut self, event: &Event) -> bool {
+        self.held.sync(event.modifiers);
+        let handled = match event.event_type {
+            EventType::KeyPress(key) => {
+                let mut triggered = false;
+                for hotkey in &mut self.hotkeys {
+                    if hotkey.accelerator.matches(self.held.keys(), key) {
+                        (hotkey.callback)();
+                        triggered = true;
+                    }
+                }
+                let repeated = self.triggered.contains(&key);
+                if triggered && !repeated {
+                    self.triggered.push(key);
+                }
+                triggered || repeated
+            }
+            EventType::KeyRelease(key) => {
+                let len = self.triggered.len();
+                self.triggered.retain(|triggered| *triggered != key);
+                self.triggered.len() != len
+            }
+            _ => false,
+        };
+        self.held.update(&event.event_type);
+        handled
+    }
+}
+
+impl fmt::Debug for HotkeyManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HotkeyManager")
+            .field(
+                "hotkeys",
+                &self
+                    .hotkeys
+                    .iter()
+                    .map(|hotkey| &hotkey.accelerator)
+                    .collect::<Vec<_>>(),
+            )
+            .field("held", &self.held)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_state::InputState;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn counter(hotkeys: &mut HotkeyManager, accelerator: &str) -> Arc<AtomicUsize> {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counted = count.clone();
+        hotkeys.register(accelerator.parse().unwrap(), move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+        });
+        count
+    }
+
+    /// What `handle` returned for each event, given the modifiers of the
+    /// events before it.
+    fn feed(hotkeys: &mut HotkeyManager, events: &[EventType]) -> Vec<bool> {
+        let mut state = InputState::new();
+        events
+            .iter()
+            .map(|event_type| {
+                let mut event = Event::at(*event_type, 0);
+                event.modifiers = state.modifiers();
+                state.update(&event);
+                hotkeys.handle(&event)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let accelerator: Accelerator = "Ctrl+Alt+Delete".parse().unwrap();
+        assert_eq!(accelerator.key(), Key::Delete);
+        assert_eq!(accelerator.modifiers(), [Key::ControlLeft, Key::Alt]);
+        assert_eq!(accelerator.to_string(), "ControlLeft+AltLeft+Delete");
+        assert_eq!(accelerator.to_string().parse(), Ok(accelerator));
+        assert_eq!(
+            "shift + cmd + 4".parse(),
+            Ok(Accelerator::new(
+                Key::Num4,
+                &[Key::MetaLeft, Key::ShiftLeft]
+            ))
+        );
+        assert_eq!("".parse::<Accelerator>(), Err(ParseAcceleratorError::Empty));
+        assert_eq!(
+            "A+B".parse::<Accelerator>(),
+            Err(ParseAcceleratorError::NotAModifier(Key::KeyA))
+        );
+        assert!(matches!(
+            "Ctrl+Hyper".parse::<Accelerator>(),
+            Err(ParseAcceleratorError::UnknownName(_))
+        ));
+    }
+
+    #[test]
+    fn test_matches() {
+        let accelerator: Accelerator = "Ctrl+Shift+K".parse().unwrap();
+        let held = [Key::ShiftRight, Key::ControlLeft, Key::KeyJ];
+        assert!(accelerator.matches(&held, Key::KeyK));
+        assert!(!accelerator.matches(&held[..1], Key::KeyK));
+        assert!(!accelerator.matches(&[Key::Alt, Key::ShiftLeft, Key::ControlLeft], Key::KeyK));
+        let accelerator = accelerator.distinct_sides(true);
+        assert!(!accelerator.matches(&held, Key::KeyK));
+        assert!(accelerator.matches(&[Key::ShiftLeft, Key::ControlLeft], Key::KeyK));
+        let accelerator: Accelerator = "Shift+Ctrl".parse().unwrap();
+        assert!(accelerator.matches(&[Key::ShiftLeft], Key::ControlRight));
+    }
+
+    #[test]
+    fn test_manager() {
+        let mut hotkeys = HotkeyManager::new();
+        let count = counter(&mut hotkeys, "Ctrl+K");
+        let handled = feed(
+            &mut hotkeys,
+            &[
+                EventType::KeyPress(Key::ControlRight),
+                EventType::KeyPress(Key::KeyK),
+                EventType::KeyPress(Key::KeyK),
+                EventType::KeyRelease(Key::KeyK),
+                EventType::KeyRelease(Key::ControlRight),
+                EventType::KeyPress(Key::KeyK),
+                EventType::KeyRelease(Key::KeyK),
+            ],
+        );
+        assert_eq!(handled, [false, true, true, true, false, false, false]);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_missed_release() {
+        let mut hotkeys = HotkeyManager::new();
+        let count = counter(&mut hotkeys, "Ctrl+K");
+        hotkeys.handle(&Event::at(EventType::KeyPress(Key::ControlLeft), 0));
+        // Control went up while another window had the focus.
+        assert!(!hotkeys.handle(&Event::at(EventType::KeyPress(Key::KeyK), 0)));
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        // The right Alt key shows up as Alt or AltGr.
+        let count = counter(&mut hotkeys, "Alt+K");
+        hotkeys.handle(&Event::at(EventType::KeyPress(Key::AltGr), 0));
+        let mut event = Event::at(EventType::KeyPress(Key::KeyK), 0);
+        event.modifiers = Modifiers::ALT;
+        assert!(hotkeys.handle(&event));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_unregister() {
+        let mut hotkeys = HotkeyManager::new();
+        let count = Arc::new(AtomicUsize::new(0));
+        let counted = count.clone();
+        let id = hotkeys.register(Accelerator::new(Key::F5, &[]), move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(hotkeys.unregister(id));
+        assert!(!hotkeys.unregister(id));
+        assert_eq!(feed(&mut hotkeys, &[EventType::KeyPress(Key::F5)]), [false]);
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::rdev::{Button, Event, EventType, Key, Modifiers};
+use std::time::{Duration, SystemTime};
+
+/// The keys and buttons held, the modifiers and the pointer position, as far
+/// as the events it is fed tell. Caps Lock and Num Lock follow the
+/// [`Event::modifiers`] of the events, Scroll Lock only its key presses.
+///
+/// A release can go missing, when the focus moves while a key is held or
+/// while the listener was not running. [`InputState::stale_after`] forgets
+/// the keys and buttons that did not show up for a while, and
+/// [`InputState::release_all`] forgets them all, on focus loss for instance.
+///
+/// ```
+/// use rdev::{InputState, Modifiers};
+///
+/// let mut state = InputState::new();
+/// // for event in events {
+/// //     state.update(&event);
+/// // }
+/// if state.modifiers().contains(Modifiers::SHIFT) {
+///     println!("Shift is down");
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    // Along with when they were pressed, or repeated, last.
+    keys: Vec<(Key, SystemTime)>,
+    buttons: Vec<(Button, SystemTime)>,
+    position: Option<(f64, f64)>,
+    caps_lock: bool,
+    num_lock: bool,
+    scroll_lock: bool,
+    stale_after: Option<Duration>,
+}
+
+impl InputState {
+    pub fn new() -> InputState {
+        InputState::default()
+    }
+
+    /// Forgets the keys and buttons pressed longer than `timeout` before the
+    /// last event, never by default. Held keys repeat, but held modifiers and
+    /// buttons do not: keep it longer than they are held.
+    pub fn stale_after(mut self, timeout: Duration) -> InputState {
+        self.stale_after = Some(timeout);
+        self
+    }
+
+    pub fn update(&mut self, event: &Event) {
+        self.expire(event.time);
+        // The locks before the event, which catches up with the toggles
+        // missed.
+        self.caps_lock = event.modifiers.contains(Modifiers::CAPS_LOCK);
+        self.num_lock = event.modifiers.contains(Modifiers::NUM_LOCK);
+        match event.event_type {
+            EventType::KeyPress(key) => {
+                let repeated = self.is_key_pressed(key);
+                press(&mut self.keys, key, event.time);
+                if !repeated {
+                    match key {
+                        Key::CapsLock => self.caps_lock = !self.caps_lock,
+                        Key::NumLock => self.num_lock = !self.num_lock,
+                        Key::ScrollLock => self.scroll_lock = !self.scroll_lock,
+                        _ => {}
+                    }
+                }
+            }
+            EventType::KeyRelease(key) => self.keys.retain(|(held, _)| *held != key),
+            EventType::ButtonPress(button) => press(&mut self.buttons, button, event.time),
+            EventType::ButtonRelease(button) => self.buttons.retain(|(held, _)| *held != button),
+            EventType::MouseMove { x, y } => self.position = Some((x, y)),
+            _ => {}
+        }
+    }
+
+    /// Forgets the keys and buttons gone stale by `now`, when waiting for
+    /// the next event would take too long.
+    pub fn expire(&mut self, now: SystemTime) {
+        if let Some(timeout) = self.stale_after {
+            let fresh =
+                |pressed: SystemTime| now.duration_since(pressed).unwrap_or_default() <= timeout;
+            self.keys.retain(|(_, pressed)| fresh(*pressed));
+            self.buttons.retain(|(_, pressed)| fresh(*pressed));
+        }
+    }
+
+    /// Forgets every key and button held, keeping the lock states.
+    pub fn release_all(&mut self) {
+        self.keys.clear();
+        self.buttons.clear();
+    }
+
+    pub fn is_key_pressed(&self, key: Key) -> bool {
+        self.keys.iter().any(|(held, _)| *held == key)
+    }
+
+    /// In the order they were pressed.
+    pub fn pressed_keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.keys.iter().map(|(key, _)| *key)
+    }
+
+    pub fn is_button_pressed(&self, button: Button) -> bool {
+        self.buttons.iter().any(|(held, _)| *held == button)
+    }
+
+    pub fn pressed_buttons(&self) -> impl Iterator<Item = Button> + '_ {
+        self.buttons.iter().map(|(button, _)| *button)
+    }
+
+    /// The modifiers held, along with the lock keys on.
+    pub fn modifiers(&self) -> Modifiers {
+        let mut modifiers = self
+            .pressed_keys()
+            .map(Modifiers::from_key)
+            .fold(Modifiers::empty(), |all, modifier| all | modifier);
+        modifiers.set(Modifiers::CAPS_LOCK, self.caps_lock);
+        modifiers.set(Modifiers::NUM_LOCK, self.num_lock);
+        modifiers
+    }
+
+    /// The last position the pointer moved to, none before it moved.
+    pub fn position(&self) -> Option<(f64, f64)> {
+        self.position
+    }
+
+    pub fn caps_lock(&self) -> bool {
+        self.caps_lock
+    }
+
+    pub fn num_lock(&self) -> bool {
+        self.num_lock
+    }
+
+    pub fn scroll_lock(&self) -> bool {
+        self.scroll_lock
+    }
+
+    /// The lock keys start off until the first event, and Scroll Lock until
+    /// its key is pressed. Sets them from the OS state to start right.
+    pub fn set_locks(&mut self, caps_lock: bool, num_lock: bool, scroll_lock: bool) {
+        self.caps_lock = caps_lock;
+        self.num_lock = num_lock;
+        self.scroll_lock = scroll_lock;
+    }
+}
+
+fn press<T: PartialEq>(pressed: &mut Vec<(T, SystemTime)>, input: T, time: SystemTime) {
+    match pressed.iter_mut().find(|(held, _)| *held == input) {
+        Some((_, last)) => *last = time,
+        None => pressed.push((input, time)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// With the modifiers the state tells, as the evdev listener does.
+    fn feed(state: &mut InputState, events: &[EventType]) {
+        for event_type in events {
+            let mut event = Event::at(*event_type, 0);
+            event.modifiers = state.modifiers();
+            state.update(&event);
+        }
+    }
+
+    #[test]
+    fn test_pressed() {
+        let mut state = InputState::new();
+        feed(
+            &mut state,
+            &[
+                EventType::KeyPress(Key::ControlRight),
+                EventType::KeyPress(Key::KeyC),
+                EventType::KeyPress(Key::KeyC),
+                EventType::ButtonPress(Button::Left),
+                EventType::MouseMove { x: 10.0, y: 20.0 },
+            ],
+        );
+        assert_eq!(
+            state.pressed_keys().collect::<Vec<_>>(),
+            [Key::ControlRight, Key::KeyC]
+        );
+        assert!(state.is_button_pressed(Button::Left));
+        assert_eq!(state.modifiers(), Modifiers::CTRL);
+        assert_eq!(state.position(), Some((10.0, 20.0)));
+        feed(
+            &mut state,
+            &[
+                EventType::KeyRelease(Key::KeyC),
+                EventType::KeyRelease(Key::ControlRight),
+                EventType::ButtonRelease(Button::Left),
+            ],
+        );
+        assert_eq!(state.pressed_keys().count(), 0);
+        assert_eq!(state.pressed_buttons().count(), 0);
+        assert_eq!(state.modifiers(), Modifiers::empty());
+    }
+
+    #[test]
+    fn test_locks() {
+        let mut state = InputState::new();
+        feed(
+            &mut state,
+            &[
+                EventType::KeyPress(Key::CapsLock),
+                EventType::KeyPress(Key::CapsLock),
+                EventType::KeyRelease(Key::CapsLock),
+                EventType::KeyPress(Key::ShiftLeft),
+            ],
+        );
+        assert!(state.caps_lock());
+        assert_eq!(state.modifiers(), Modifiers::SHIFT | Modifiers::CAPS_LOCK);
+        feed(
+            &mut state,
+            &[
+                EventType::KeyPress(Key::CapsLock),
+                EventType::KeyPress(Key::ScrollLock),
+            ],
+        );
+        assert!(!state.caps_lock());
+        assert!(state.scroll_lock());
+    }
+
+    #[test]
+    fn test_locks_from_events() {
+        // Caps Lock was turned on before the events started.
+        let mut state = InputState::new();
+        let mut event = Event::at(EventType::KeyPress(Key::KeyA), 0);
+        event.modifiers = Modifiers::CAPS_LOCK;
+        state.update(&event);
+        assert!(state.caps_lock());
+        let mut event = Event::at(EventType::KeyPress(Key::CapsLock), 0);
+        event.modifiers = Modifiers::CAPS_LOCK | Modifiers::NUM_LOCK;
+        state.update(&event);
+        assert!(!state.caps_lock());
+        assert!(state.num_lock());
+    }
+
+    #[test]
+    fn test_stale() {
+        let mut state = InputState::new().stale_after(Duration::from_secs(1));
+        state.update(&Event::at(EventType::KeyPress(Key::ShiftLeft), 0));
+        state.update(&Event::at(EventType::KeyPress(Key::KeyA), 500));
+        state.update(&Event::at(EventType::KeyPress(Key::KeyA), 1200));
+        assert_eq!(state.pressed_keys().collect::<Vec<_>>(), [Key::KeyA]);
+        state.expire(SystemTime::UNIX_EPOCH + Duration::from_millis(2500));
+        assert_eq!(state.pressed_keys().count(), 0);
+        state.update(&Event::at(EventType::ButtonPress(Button::Right), 3000));
+        state.release_all();
+        assert_eq!(state.pressed_buttons().count(), 0);
+    }
+}
@@ -155,6 +155,13 @@ decl_keycodes! {
     Lang2, "Convert", // "Convert" (was "" prior to Chrome 48)
     Lang3, "Lang3", // "Lang3" (was "" prior to Chrome 48)
     Lang4, "Lang4", // "Lang4" (was "" prior to Chrome 48)
+    MediaNextTrack, "MediaTrackNext",
+    MediaPrevTrack, "MediaTrackPrevious",
+    MediaPlayPause, "MediaPlayPause",
+    BrowserSearch, "BrowserSearch",
+    LaunchMail, "LaunchMail",
+    LaunchApp2, "LaunchApp2",
+    Function, "Fn",
     Lang5, "Lang5", // "Lang5" (was "" prior to Chrome 48) (⚠️ Not the same on Firefox) Lang5 in https://download.microsoft.com/download/1/6/1/161ba512-40e2-4cc9-843a-923143f3456c/translate.pdf is 0x0075, while is "Lang5" in https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/code is 0x005D
     Cancel, "",
     Clear, "",
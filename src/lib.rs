@@ -25,6 +25,46 @@
 //! }
 //! ```
 //!
+//! `listen` blocks until `exit_listen` is called. `start_listen` runs the listener in the background
+//! instead, and returns a `ListenHandle` to stop it. A new listener can be started once the previous one
+//! is stopped:
+//!
+//! ```no_run
+//! use rdev::start_listen;
+//!
+//! let handle = start_listen(|event| println!("{:?}", event.event_type)).unwrap();
+//! std::thread::sleep(std::time::Duration::from_secs(5));
+//! handle.stop().unwrap();
+//! ```
+//!
+//! `ListenerBuilder` chooses what to listen to. Disabled event classes are not requested from the OS
+//! where it allows it, `unicode(false)` skips the keyboard state tracking, and `coalesce_motion` limits
+//! the rate of mouse moves. It replaces the `KEYBOARD_ONLY` environment variable, which now only sets
+//! the builder's defaults:
+//!
+//! ```no_run
+//! use rdev::ListenerBuilder;
+//! use std::time::Duration;
+//!
+//! let handle = ListenerBuilder::new()
+//!     .keys(false)
+//!     .coalesce_motion(Duration::from_millis(16))
+//!     .start(|event| println!("{:?}", event.event_type))
+//!     .unwrap();
+//! ```
+//!
+//! Several parts of an application can share the listener with `subscribe`. Each subscriber gets
+//! the events its filter accepts in its own bounded queue, and the listener stops with the last one:
+//!
+//! ```no_run
+//! use rdev::{subscribe, EventType};
+//!
+//! let keys = subscribe(|event| matches!(event.event_type, EventType::KeyPress(_)), 64).unwrap();
+//! for event in keys.receiver().iter() {
+//!     println!("{:?}", event.event_type);
+//! }
+//! ```
+//!
 //! ## OS Caveats:
 //! When using the `listen` function, the following caveats apply:
 //!
@@ -37,7 +77,13 @@
 //! `listen` calleback and will not trigger it with events. No error will be generated.
 //!
 //! ## Linux
-//! The `listen` function uses X11 APIs, and so will not work in Wayland or in the linux kernel virtual console
+//! The `listen` function uses X11 APIs, and so will not work in Wayland or in the linux kernel virtual console.
+//! Its raw events (`MouseMoveRaw`, `KeyPressRaw`, ...) come from XInput2 and are missing when the
+//! X server does not support it.
+//! `listen_evdev` reads the kernel input devices (/dev/input/event*) instead and works in both,
+//! but it needs read access to those devices (usually by being in the `input` group).
+//! Since the kernel has no notion of a cursor, the `MouseMove` positions it reports are
+//! accumulated from relative motion, starting at (0, 0), and `unicode` is always `None`.
 //!
 //! # Sending some events
 //!
@@ -69,6 +115,39 @@
 //!     delta_y: 1.0,
 //! });
 //! ```
+//!
+//! ## Reusing a connection
+//! `simulate` sets up everything it needs for each event, which on Linux means a new connection to
+//! the X server. `Simulator` keeps it open, and `Simulator::simulate_all` sends many events at once:
+//!
+//! ```no_run
+//! use rdev::{EventType, Simulator};
+//!
+//! let simulator = Simulator::new().unwrap();
+//! simulator.simulate(&EventType::MouseMove { x: 0.0, y: 0.0 }).unwrap();
+//! ```
+//!
+//! ## Typing text
+//! `simulate_text` types a string, one grapheme cluster at a time. It presses the keys of the
+//! active layout, modifiers and dead keys included, and injects the characters no key produces.
+//! `TextBuilder` chooses between the two strategies and adds a delay between characters.
+//! The characters that could not be typed are reported in the error:
+//!
+//! ```no_run
+//! use rdev::simulate_text;
+//!
+//! if let Err(error) = simulate_text("Grüße\n") {
+//!     println!("Could not type {:?}", error.untyped);
+//! }
+//! ```
+//!
+//! ## Linux without X11
+//! `simulate` goes through XTest and needs an X server. With the `unstable_wayland` feature,
+//! `simulate_uinput` (or a `VirtualDevice` you own) injects events through a `/dev/uinput`
+//! virtual keyboard and mouse instead, which works under Wayland and on the console too,
+//! and also supports horizontal and high-resolution scrolling. The user needs write access
+//! to `/dev/uinput`.
+//!
 //! # Main structs
 //! ## Event
 //!
@@ -95,7 +174,11 @@
 //! Be careful, Event::name, might be None, but also String::from(""), and might contain
 //! not displayable unicode characters. We send exactly what the OS sends us so do some sanity checking
 //! before using it.
-//! Caveat: Dead keys don't function yet on Linux
+//! Caveat: On Linux, the listener does not apply dead keys to `Event::unicode`, feed the events to an
+//! `XkbKeyboard` for them.
+//!
+//! `Event.modifiers` holds the `Modifiers` held and the lock keys on when the event happened, to tell
+//! Ctrl+Click from a plain click without tracking the keys.
 //!
 //! ## EventType
 //!
@@ -154,7 +237,7 @@
 //! layout for now !
 //! Caveat : This is layout dependent. If your app needs to support
 //! layout switching don't use this !
-//! Caveat: On Linux, the dead keys mechanism is not implemented.
+//! Caveat: On Linux, the dead keys mechanism is not implemented, use `XkbKeyboard` for it.
 //! Caveat: Only shift and dead keys are implemented, Alt+unicode code on windows
 //! won't work.
 //!
@@ -166,22 +249,41 @@
 //! // string == Some("s")
 //! ```
 //!
-//! # Grabbing global events. (Requires `unstable_grab` feature)
+//! On Linux, `XkbKeyboard` computes the same from an XKB keymap with libxkbcommon, loaded at runtime.
+//! It tracks the modifiers and the layout group from the events it is given instead of asking
+//! the X server, so it also works without a display, and it applies dead keys and compose sequences.
+//! `from_layout` builds the keymap from a layout name and variant, independently of the current layout:
 //!
-//! Installing this library with the `unstable_grab` feature adds the `grab` function
-//! which hooks into the global input device event stream.
+//! ```no_run
+//! # #[cfg(target_os = "linux")] {
+//! use rdev::{XkbKeyboard, EventType, Key, KeyboardState};
+//!
+//! let mut keyboard = XkbKeyboard::from_layout("us", Some("intl")).unwrap();
+//! keyboard.add(&EventType::KeyPress(Key::Quote)); // dead key
+//! let string = keyboard.add(&EventType::KeyPress(Key::KeyE));
+//! // string == Some("é")
+//! # }
+//! ```
+//!
+//! `XkbKeyboard::events_for_char` goes the other way and gives the key events typing a character,
+//! modifiers and dead keys included. `simulate_char` uses it on Linux with the layout of the X server.
+//!
+//! `InputState` keeps track of the keys and buttons held, the `Modifiers` and lock keys, and the
+//! pointer position, from the events it is fed. `stale_after` forgets the keys whose release went missing.
+//!
+//! # Grabbing global events.
+//!
+//! The `grab` function hooks into the global input device event stream.
 //! by suppling this function with a callback, you can intercept
 //! all keyboard and mouse events before they are delivered to applications / window managers.
 //! In the callback, returning None ignores the event and returning the event let's it pass.
-//! There is no modification of the event possible here (yet).
-//!
-//! Note: the use of the word `unstable` here refers specifically to the fact that the `grab` API is unstable and subject to change
+//! The default X11 grab on Linux lets the returned event through, so the key or the button of an
+//! event can be changed there. The other grabs let the original event through, modifying it has no
+//! effect. Mouse moves cannot be blocked or modified with the X11 grab.
 //!
 //! ```no_run
-//! #[cfg(feature = "unstable_grab")]
 //! use rdev::{grab, Event, EventType, Key};
 //!
-//! #[cfg(feature = "unstable_grab")]
 //! let callback = |event: Event| -> Option<Event> {
 //!     if let EventType::KeyPress(Key::CapsLock) = event.event_type {
 //!         println!("Consuming and cancelling CapsLock");
@@ -190,12 +292,44 @@
 //!     else { Some(event) }
 //! };
 //! // This will block.
-//! #[cfg(feature = "unstable_grab")]
 //! if let Err(error) = grab(callback) {
 //!     println!("Error: {:?}", error)
 //! }
 //! ```
 //!
+//! `grab` blocks until `exit_grab` is called. `start_grab` runs the grab in the background
+//! instead, and returns a `GrabHandle` to pause, resume and stop it:
+//!
+//! ```no_run
+//! use rdev::{start_grab, EventType, Key};
+//!
+//! let handle = start_grab(|event| match event.event_type {
+//!     EventType::KeyPress(Key::CapsLock) | EventType::KeyRelease(Key::CapsLock) => None,
+//!     _ => Some(event),
+//! })
+//! .unwrap();
+//! // CapsLock works again while paused.
+//! handle.pause();
+//! handle.resume();
+//! handle.stop().unwrap();
+//! ```
+//!
+//! ## Hotkeys
+//!
+//! `HotkeyManager` calls a callback when an `Accelerator` like `"Ctrl+Alt+K"` is pressed. Fed from a
+//! grab callback, it tells which events to swallow:
+//!
+//! ```no_run
+//! use rdev::{start_grab, HotkeyManager};
+//!
+//! let mut hotkeys = HotkeyManager::new();
+//! hotkeys.register("Ctrl+Alt+K".parse().unwrap(), || println!("Ctrl+Alt+K"));
+//! let handle = start_grab(move |event| (!hotkeys.handle(&event)).then_some(event)).unwrap();
+//! ```
+//!
+//! `SequenceRecognizer` goes further, with sequences like `"Ctrl+X Ctrl+S"`, leader keys, double
+//! taps like `"Shift Shift"` and chords of keys pressed together, timed with the `time` of the events.
+//!
 //! ## OS Caveats:
 //! When using the `listen` and/or `grab` functions, the following caveats apply:
 //!
@@ -208,8 +342,9 @@
 //! EventTapError (at least in MacOS 10.15, possibly other versions as well)
 //!
 //! ### Linux
-//! The `grab` function use the `evdev` library to intercept events, so they will work with both X11 and Wayland
-//! In order for this to work, the process runnign the `listen` or `grab` loop needs to either run as root (not recommended),
+//! By default, the `grab` function grabs the keyboard and the pointer through X11.
+//! With the `unstable_grab` feature, it uses evdev and uinput instead, so it will work with both X11 and Wayland.
+//! In order for this to work, the process runnign the `grab` loop needs to either run as root (not recommended),
 //! or run as a user who's a member of the `input` group (recommended)
 //! Note: on some distros, the group name for evdev access is called `plugdev`, and on some systems, both groups can exist.
 //! When in doubt, add your user to both groups if they exist.
@@ -218,12 +353,31 @@
 //!
 //! Event data returned by the `listen` and `grab` functions can be serialized and de-serialized with
 //! Serde if you install this library with the `serialize` feature.
+//!
+//! `Key` and `Button` also have stable names, the W3C `KeyboardEvent.code` ones where
+//! they exist, for key bindings in config files: `Key::ControlLeft.to_string()` is
+//! `"ControlLeft"`, and `"Ctrl"`, `"Cmd"` or `"Super"` parse too. Use
+//! `#[serde(with = "rdev::as_name")]` to serialize a field with its name.
 mod rdev;
 pub use crate::rdev::{
     Button, DisplayError, Event, EventType, GrabCallback, GrabError, Key, KeyCode, KeyboardState,
-    ListenError, RawKey, SimulateError,
+    ListenError, Modifiers, ParseAcceleratorError, ParseNameError, RawKey, SimulateError,
+    TextError,
 };
 
+mod hotkey;
+pub use crate::hotkey::{Accelerator, HotkeyId, HotkeyManager};
+
+mod input_state;
+pub use crate::input_state::InputState;
+
+mod sequence;
+pub use crate::sequence::{Sequence, SequenceId, SequenceRecognizer, Stroke};
+
+mod names;
+#[cfg(feature = "serialize")]
+pub use crate::names::as_name;
+
 mod keycodes;
 #[cfg(target_os = "linux")]
 mod linux;
@@ -236,6 +390,29 @@ mod codes_conv;
 
 pub use crate::codes_conv::*;
 
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod broadcast;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub use crate::broadcast::{Subscription, subscribe};
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod grab;
+#[cfg(all(feature = "tokio", not(any(target_os = "android", target_os = "ios"))))]
+mod stream;
+#[cfg(all(feature = "tokio", not(any(target_os = "android", target_os = "ios"))))]
+pub use crate::stream::{EventStream, STREAM_CAPACITY, listen_stream, simulate_async};
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub use crate::grab::{GrabHandle, start_grab};
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod listen;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod text;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub use crate::text::{TextBuilder, TextStrategy, simulate_text};
+#[cfg(target_os = "linux")]
+pub use crate::listen::start_listen_evdev;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub use crate::listen::{Backend, ListenHandle, ListenerBuilder, start_listen};
+
 pub use keycodes::android::{
     code_from_key as android_keycode_from_key, key_from_code as android_key_from_code,
 };
@@ -260,7 +437,7 @@ pub use keycodes::windows::{
 #[cfg(target_os = "macos")]
 pub use crate::keycodes::macos::{code_from_key, key_from_code, virtual_keycodes::*};
 #[cfg(target_os = "macos")]
-pub use crate::macos::{Keyboard, VirtualInput};
+pub use crate::macos::{Keyboard, Simulator, VirtualInput, exit_listen, is_listening};
 #[cfg(target_os = "macos")]
 use crate::macos::{display_size as _display_size, listen as _listen, simulate as _simulate};
 #[cfg(target_os = "macos")]
@@ -269,22 +446,28 @@ pub use objc2_core_graphics::{CGEventSourceStateID, CGEventTapLocation};
 #[cfg(any(target_os = "android", target_os = "linux"))]
 pub use crate::keycodes::linux::{code_from_key, key_from_code};
 #[cfg(target_os = "linux")]
-pub use crate::linux::{Keyboard, simulate_char, simulate_unicode};
+pub use crate::linux::{
+    Keyboard, Simulator, XkbKeyboard, exit_listen, is_listening, simulate_char, simulate_unicode,
+};
+#[cfg(all(target_os = "linux", feature = "unstable_wayland"))]
+pub use crate::linux::{VirtualDevice, simulate_uinput};
 #[cfg(target_os = "linux")]
-use crate::linux::{display_size as _display_size, listen as _listen, simulate as _simulate};
+use crate::linux::{display_size as _display_size, simulate as _simulate};
 
 #[cfg(target_os = "windows")]
 pub use crate::keycodes::windows::key_from_scancode;
 #[cfg(target_os = "windows")]
 pub use crate::windows::{
-    Keyboard, display_size as _display_size, get_modifier, listen as _listen, set_modifier,
-    simulate as _simulate, simulate_char, simulate_code, simulate_key_unicode, simulate_unicode,
-    simulate_unistr, vk_to_scancode,
+    Keyboard, Simulator, display_size as _display_size, exit_listen, get_modifier, is_listening,
+    listen as _listen, set_modifier, simulate as _simulate, simulate_char, simulate_code,
+    simulate_key_unicode, simulate_unicode, simulate_unistr, vk_to_scancode,
 };
 
 pub use crate::rdev::UnicodeInfo;
 
-/// Listening to global events. Caveat: On MacOS, you require the listen
+/// Listening to global events. This blocks until `exit_listen` is called,
+/// see `start_listen` for a listener running in the background.
+/// Caveat: On MacOS, you require the listen
 /// loop needs to be the primary app (no fork before) and need to have accessibility
 /// settings enabled.
 ///
@@ -310,7 +493,21 @@ pub fn listen<T>(callback: T) -> Result<(), ListenError>
 where
     T: FnMut(Event) + Send + 'static,
 {
-    _listen(callback)
+    ListenerBuilder::new().listen(callback)
+}
+
+/// Listens to the kernel input devices directly instead of going through
+/// the X server, so it also works under Wayland or on a bare console.
+/// The user needs read access to /dev/input/event*, which usually means
+/// being a member of the `input` group.
+#[cfg(target_os = "linux")]
+pub fn listen_evdev<T>(callback: T) -> Result<(), ListenError>
+where
+    T: FnMut(Event) + Send + 'static,
+{
+    ListenerBuilder::new()
+        .backend(Backend::Evdev)
+        .listen(callback)
 }
 
 /// Sending some events
@@ -365,31 +562,45 @@ pub fn display_size() -> Result<(u64, u64), DisplayError> {
 }
 
 #[cfg(target_os = "linux")]
-pub use crate::linux::{
-    disable_grab, enable_grab, exit_grab_listen, is_grabbed, start_grab_listen,
-};
+#[allow(deprecated)]
+pub use crate::linux::{disable_grab, enable_grab, exit_grab_listen, start_grab_listen};
+#[cfg(target_os = "linux")]
+use crate::linux::grab as _grab;
+#[cfg(target_os = "linux")]
+pub use crate::linux::{exit_grab, is_grabbed};
+#[cfg(target_os = "linux")]
+pub use crate::linux::{set_keyboard_extra_info, set_mouse_extra_info};
 #[cfg(target_os = "macos")]
 pub use crate::macos::set_keyboard_extra_info;
 #[cfg(target_os = "macos")]
 pub use crate::macos::set_mouse_extra_info;
 #[cfg(target_os = "macos")]
-pub use crate::macos::{exit_grab, grab as _grab, is_grabbed};
+use crate::macos::grab as _grab;
+#[cfg(target_os = "macos")]
+pub use crate::macos::{exit_grab, is_grabbed};
 #[cfg(target_os = "windows")]
 pub use crate::windows::set_keyboard_extra_info;
 #[cfg(target_os = "windows")]
 pub use crate::windows::set_mouse_extra_info;
 #[cfg(target_os = "windows")]
-pub use crate::windows::{exit_grab, grab as _grab, is_grabbed};
+use crate::windows::grab as _grab;
+#[cfg(target_os = "windows")]
+pub use crate::windows::{exit_grab, is_grabbed};
 #[cfg(target_os = "windows")]
 pub use crate::windows::{set_event_popup, set_get_key_unicode};
 
 /// Grabbing global events. In the callback, returning None ignores the event
-/// and returning the event let's it pass. There is no modification of the event
-/// possible here.
+/// and returning the event let's it pass. Only the default X11 grab on Linux lets the returned
+/// event through, so changing the key or the button of an event only has an effect there, other
+/// grabs let the original event through. This blocks until `exit_grab` is called, see `start_grab`
+/// for a grab running in the background.
 /// Caveat: On MacOS, you require the grab
 /// loop needs to be the primary app (no fork before) and need to have accessibility
 /// settings enabled.
-/// On Linux, you need rw access to evdev devices in /etc/input/ (usually group membership in `input` group is enough)
+/// On Linux, the keyboard and the pointer are grabbed through X11. With the `unstable_grab`
+/// feature, the evdev devices are grabbed instead, which also works under Wayland: you need read
+/// access to the evdev devices in /dev/input/ and write access to /dev/uinput (usually group
+/// membership in `input` group is enough).
 ///
 /// ```no_run
 /// use rdev::{grab, Event, EventType, Key};
@@ -408,10 +619,10 @@ pub use crate::windows::{set_event_popup, set_get_key_unicode};
 ///     }
 /// }
 /// ```
-#[cfg(not(any(target_os = "android", target_os = "ios", target_os = "linux")))]
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
 pub fn grab<T>(callback: T) -> Result<(), GrabError>
 where
-    T: Fn(Event) -> Option<Event> + Send + 'static,
+    T: FnMut(Event) -> Option<Event> + Send + 'static,
 {
     _grab(callback)
 }
@@ -423,58 +634,57 @@ pub(crate) fn keyboard_only() -> bool {
         .is_empty()
 }
 
-#[cfg(test)]
+#[cfg(all(test, target_os = "linux"))]
 mod tests {
-    // use super::*;
+    use super::*;
 
     #[test]
     fn test_keyboard_state() {
-        // // S
-        // let mut keyboard = Keyboard::new();
-        // let char_s = keyboard
-        //     .add(&EventType::KeyPress(Key::KeyS))
-        //     .unwrap()
-        //     .name
-        //     .unwrap();
-        // assert_eq!(
-        //     char_s,
-        //     "s".to_string(),
-        //     "This test should pass only on Qwerty layout !"
-        // );
-        // let n = keyboard.add(&EventType::KeyRelease(Key::KeyS));
-        // assert_eq!(n, None);
+        // S
+        let Some(mut keyboard) = XkbKeyboard::fixture("us") else {
+            return;
+        };
+        let char_s = keyboard
+            .add(&EventType::KeyPress(Key::KeyS))
+            .unwrap()
+            .name
+            .unwrap();
+        assert_eq!(char_s, "s".to_string());
+        let n = keyboard.add(&EventType::KeyRelease(Key::KeyS));
+        assert_eq!(n, None);
 
-        // // Shift + S
-        // keyboard.add(&EventType::KeyPress(Key::ShiftLeft));
-        // let char_s = keyboard
-        //     .add(&EventType::KeyPress(Key::KeyS))
-        //     .unwrap()
-        //     .name
-        //     .unwrap();
-        // assert_eq!(char_s, "S".to_string());
-        // let n = keyboard.add(&EventType::KeyRelease(Key::KeyS));
-        // assert_eq!(n, None);
-        // keyboard.add(&EventType::KeyRelease(Key::ShiftLeft));
+        // Shift + S
+        keyboard.add(&EventType::KeyPress(Key::ShiftLeft));
+        let char_s = keyboard
+            .add(&EventType::KeyPress(Key::KeyS))
+            .unwrap()
+            .name
+            .unwrap();
+        assert_eq!(char_s, "S".to_string());
+        let n = keyboard.add(&EventType::KeyRelease(Key::KeyS));
+        assert_eq!(n, None);
+        keyboard.add(&EventType::KeyRelease(Key::ShiftLeft));
 
-        // // Reset
-        // keyboard.add(&EventType::KeyPress(Key::ShiftLeft));
-        // let char_s = keyboard
-        //     .add(&EventType::KeyPress(Key::KeyS))
-        //     .unwrap()
-        //     .name
-        //     .unwrap();
-        // assert_eq!(char_s, "s".to_string());
-        // let n = keyboard.add(&EventType::KeyRelease(Key::KeyS));
-        // assert_eq!(n, None);
-        // keyboard.add(&EventType::KeyRelease(Key::ShiftLeft));
+        // Released shift
+        let char_s = keyboard
+            .add(&EventType::KeyPress(Key::KeyS))
+            .unwrap()
+            .name
+            .unwrap();
+        assert_eq!(char_s, "s".to_string());
+        keyboard.add(&EventType::KeyRelease(Key::KeyS));
 
-        // UsIntl layout required
-        // let n = keyboard.add(&EventType::KeyPress(Key::Quote));
-        // assert_eq!(n, Some("".to_string()));
-        // let m = keyboard.add(&EventType::KeyRelease(Key::Quote));
-        // assert_eq!(m, None);
-        // let e = keyboard.add(&EventType::KeyPress(Key::KeyE)).unwrap();
-        // assert_eq!(e, "é".to_string());
-        // keyboard.add(&EventType::KeyRelease(Key::KeyE));
+        // UsIntl dead keys
+        let mut keyboard = XkbKeyboard::fixture("us_intl").unwrap();
+        if !keyboard.has_compose() {
+            return;
+        }
+        let n = keyboard.add(&EventType::KeyPress(Key::Quote)).unwrap();
+        assert!(n.is_dead);
+        let m = keyboard.add(&EventType::KeyRelease(Key::Quote));
+        assert_eq!(m, None);
+        let e = keyboard.add(&EventType::KeyPress(Key::KeyE)).unwrap();
+        assert_eq!(e.name, Some("é".to_string()));
+        keyboard.add(&EventType::KeyRelease(Key::KeyE));
     }
 }
@@ -1,6 +1,6 @@
 use crate::keycodes::linux::key_from_code;
 use crate::linux::keyboard::Keyboard;
-use crate::rdev::{Button, Event, EventType, KeyboardState};
+use crate::rdev::{Button, Event, EventType, KeyboardState, Modifiers};
 use std::convert::TryInto;
 use std::os::raw::{c_int, c_uchar, c_uint};
 use std::ptr::null;
@@ -23,8 +23,7 @@ pub fn convert_event(code: c_uchar, type_: c_int, x: f64, y: f64) -> Option<Even
             let key = key_from_code(code.into());
             Some(EventType::KeyRelease(key))
         }
-        // Xlib does not implement wheel events left and right afaik.
-        // But MacOS does, so we need to acknowledge the larger event space.
+        // The wheel comes as buttons 4 and 5, 6 and 7 horizontally.
         xlib::ButtonPress => match code {
             1 => Some(EventType::ButtonPress(Button::Left)),
             2 => Some(EventType::ButtonPress(Button::Middle)),
@@ -37,6 +36,16 @@ pub fn convert_event(code: c_uchar, type_: c_int, x: f64, y: f64) -> Option<Even
                 delta_y: -1.0,
                 delta_x: 0.0,
             }),
+            6 => Some(EventType::Wheel {
+                delta_y: 0.0,
+                delta_x: -1.0,
+            }),
+            7 => Some(EventType::Wheel {
+                delta_y: 0.0,
+                delta_x: 1.0,
+            }),
+            8 => Some(EventType::ButtonPress(Button::Back)),
+            9 => Some(EventType::ButtonPress(Button::Forward)),
             #[allow(clippy::useless_conversion)]
             code => Some(EventType::ButtonPress(Button::Unknown(code))),
         },
@@ -44,7 +53,9 @@ pub fn convert_event(code: c_uchar, type_: c_int, x: f64, y: f64) -> Option<Even
             1 => Some(EventType::ButtonRelease(Button::Left)),
             2 => Some(EventType::ButtonRelease(Button::Middle)),
             3 => Some(EventType::ButtonRelease(Button::Right)),
-            4 | 5 => None,
+            4..=7 => None,
+            8 => Some(EventType::ButtonRelease(Button::Back)),
+            9 => Some(EventType::ButtonRelease(Button::Forward)),
             #[allow(clippy::useless_conversion)]
             _ => Some(EventType::ButtonRelease(Button::Unknown(code))),
         },
@@ -53,16 +64,38 @@ pub fn convert_event(code: c_uchar, type_: c_int, x: f64, y: f64) -> Option<Even
     }
 }
 
+/// The modifiers of the `state` mask of core events, with the modifier
+/// mapping of most keymaps.
+pub fn modifiers_from_state(state: c_uint) -> Modifiers {
+    let masks = [
+        (xlib::ShiftMask, Modifiers::SHIFT),
+        (xlib::LockMask, Modifiers::CAPS_LOCK),
+        (xlib::ControlMask, Modifiers::CTRL),
+        (xlib::Mod1Mask, Modifiers::ALT),
+        (xlib::Mod2Mask, Modifiers::NUM_LOCK),
+        (xlib::Mod4Mask, Modifiers::META),
+        (xlib::Mod5Mask, Modifiers::ALT_GR),
+    ];
+    masks
+        .into_iter()
+        .filter(|(mask, _)| state & mask != 0)
+        .fold(Modifiers::empty(), |modifiers, (_, modifier)| {
+            modifiers | modifier
+        })
+}
+
 pub fn convert(
     keyboard: &mut Option<Keyboard>,
     code: c_uint,
+    state: c_uint,
     type_: c_int,
     x: f64,
     y: f64,
+    is_synthetic: bool,
 ) -> Option<Event> {
     let event_type = convert_event(code as c_uchar, type_, x, y)?;
-    let kb: &mut Keyboard = (*keyboard).as_mut()?;
-    let unicode = kb.add(&event_type);
+    // No keyboard when the listener does not want unicode.
+    let unicode = keyboard.as_mut().and_then(|kb| kb.add(&event_type));
     Some(Event {
         event_type,
         time: SystemTime::now(),
@@ -71,9 +104,8 @@ pub fn convert(
         position_code: code as _,
         usb_hid: 0,
         extra_data: 0,
-        // Linux does not have an API to detect synthetic events
-        // Default to false (assume hardware input)
-        is_synthetic: false,
+        is_synthetic,
+        modifiers: modifiers_from_state(state),
     })
 }
 
@@ -139,3 +171,43 @@ impl Drop for Display {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extra_buttons() {
+        let convert = |code, type_| convert_event(code, type_, 0.0, 0.0);
+        assert_eq!(
+            convert(6, xlib::ButtonPress),
+            Some(EventType::Wheel {
+                delta_x: -1.0,
+                delta_y: 0.0
+            })
+        );
+        assert_eq!(convert(7, xlib::ButtonRelease), None);
+        assert_eq!(
+            convert(8, xlib::ButtonPress),
+            Some(EventType::ButtonPress(Button::Back))
+        );
+        assert_eq!(
+            convert(9, xlib::ButtonRelease),
+            Some(EventType::ButtonRelease(Button::Forward))
+        );
+    }
+
+    #[test]
+    fn test_modifiers_from_state() {
+        assert_eq!(modifiers_from_state(0), Modifiers::empty());
+        assert_eq!(
+            modifiers_from_state(xlib::ShiftMask | xlib::ControlMask | xlib::Mod2Mask),
+            Modifiers::SHIFT | Modifiers::CTRL | Modifiers::NUM_LOCK
+        );
+        // Buttons and the layout group are not modifiers.
+        assert_eq!(
+            modifiers_from_state(xlib::Mod4Mask | xlib::Button1Mask | 1 << 13),
+            Modifiers::META
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use crate::input_state::InputState;
+use crate::linux::evdev::uinput::{
+    DEVICE_NAME, REL_HWHEEL_HI_RES, REL_WHEEL_HI_RES, VirtualDevice,
+};
+use crate::linux::evdev::{
+    Converter, Device, DeviceMonitor, EV_KEY, EV_REL, EV_SYN, FORWARD_DEVICE_NAME, Position,
+    REL_HWHEEL, REL_WHEEL, REL_X, REL_Y, SYN_REPORT, set_modifiers,
+};
+use crate::rdev::{Event, EventType, GrabError};
+use mio::Waker;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{debug, error};
+
+const EV_MSC: u16 = 0x04;
+
+// Set for the whole call to `grab`, `IS_GRABBED` only once devices are grabbed.
+static IS_STARTED: AtomicBool = AtomicBool::new(false);
+static IS_GRABBED: AtomicBool = AtomicBool::new(false);
+static WAKER: Mutex<Option<Waker>> = Mutex::new(None);
+
+fn accept(device: &Device) -> bool {
+    // Never grab our own output, or nothing would ever get through.
+    (device.is_keyboard() || device.is_mouse())
+        && device.name() != DEVICE_NAME
+        && device.name() != FORWARD_DEVICE_NAME
+}
+
+/// Per device state: a frame is only complete once its SYN_REPORT arrived.
+#[derive(Default)]
+struct Pending {
+    converter: Converter,
+    frame: Vec<libc::input_event>,
+}
+
+/// Runs the callback on every event of a frame and returns the part of the
+/// frame that should be forwarded.
+fn filter_frame<F>(
+    pending: &mut Pending,
+    position: &mut Position,
+    state: &mut InputState,
+    is_synthetic: bool,
+    callback: &mut F,
+) -> Vec<libc::input_event>
+where
+    F: FnMut(Event) -> Option<Event>,
+{
+    let frame = std::mem::take(&mut pending.frame);
+    let mut events = Vec::new();
+    let mut owners = Vec::with_capacity(frame.len());
+    for input_event in &frame {
+        let start = events.len();
+        pending
+            .converter
+            .feed(input_event, is_synthetic, position, &mut events);
+        owners.push(start..events.len());
+    }
+    set_modifiers(state, &mut events);
+
+    let passed: Vec<bool> = events
+        .iter()
+        .map(|event| callback(event.clone()).is_some())
+        .collect();
+    if passed.iter().all(|pass| *pass) {
+        return frame;
+    }
+    let all_passed = |filter: fn(&EventType) -> bool| {
+        events
+            .iter()
+            .zip(&passed)
+            .filter(|(event, _)| filter(&event.event_type))
+            .all(|(_, pass)| *pass)
+    };
+    let motion = all_passed(|event_type| {
+        matches!(
+            event_type,
+            EventType::MouseMove { .. } | EventType::MouseMoveRaw { .. }
+        )
+    });
+    let wheel = all_passed(|event_type| matches!(event_type, EventType::Wheel { .. }));
+
+    let mut forwarded: Vec<libc::input_event> = frame
+        .iter()
+        .zip(owners)
+        .filter(
+            |(input_event, owner)| match (input_event.type_, input_event.code) {
+                (EV_KEY, _) => passed[owner.clone()].iter().all(|pass| *pass),
+                (EV_REL, REL_X | REL_Y) => motion,
+                (EV_REL, REL_WHEEL | REL_HWHEEL | REL_WHEEL_HI_RES | REL_HWHEEL_HI_RES) => wheel,
+                (EV_REL, _) => true,
+                // Scan codes describe the key they come with, they are
+                // meaningless on their own.
+                (EV_MSC, _) => false,
+                // Only reported if anything else is.
+                (EV_SYN, _) => false,
+                _ => true,
+            },
+        )
+        .map(|(input_event, _)| *input_event)
+        .collect();
+    if !forwarded.is_empty() {
+        if let Some(report) = frame
+            .iter()
+            .find(|input_event| input_event.type_ == EV_SYN && input_event.code == SYN_REPORT)
+        {
+            forwarded.push(*report);
+        }
+    }
+    forwarded
+}
+
+/// Exclusively grabs every keyboard and mouse (EVIOCGRAB) and forwards the
+/// events the callback lets through on a uinput device. Unlike the X11 grab,
+/// this suppresses individual events, works under Wayland and on the console,
+/// but needs read access to /dev/input/event* and write access to /dev/uinput.
+///
+/// Returned events are forwarded as they were received: modifying them in the
+/// callback has no effect.
+pub fn grab<T>(mut callback: T) -> Result<(), GrabError>
+where
+    T: FnMut(Event) -> Option<Event> + Send + 'static,
+{
+    if IS_STARTED
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        error!("grab() called multiple times - only one grab allowed");
+        return Err(GrabError::AlreadyGrabbing);
+    }
+    let result = run(&mut callback);
+    *WAKER.lock() = None;
+    IS_GRABBED.store(false, Ordering::SeqCst);
+    IS_STARTED.store(false, Ordering::SeqCst);
+    result
+}
+
+#[inline]
+pub fn is_grabbed() -> bool {
+    IS_GRABBED.load(Ordering::SeqCst)
+}
+
+fn run<T>(callback: &mut T) -> Result<(), GrabError>
+where
+    T: FnMut(Event) -> Option<Event>,
+{
+    // The output device has to exist before anything is grabbed, otherwise
+    // the user is left without a keyboard if it cannot be created.
+    let output = VirtualDevice::without_absolute().map_err(GrabError::IoError)?;
+    let mut monitor = DeviceMonitor::new(accept, true).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            GrabError::MissingDeviceError
+        } else {
+            GrabError::IoError(e)
+        }
+    })?;
+    *WAKER.lock() = Some(monitor.waker().map_err(GrabError::IoError)?);
+    IS_GRABBED.store(true, Ordering::SeqCst);
+
+    let mut pending: HashMap<PathBuf, Pending> = HashMap::new();
+    // Shared by the devices, unlike the frames.
+    let mut position = Position::default();
+    let mut input_state = InputState::new();
+    let mut forwarded = Vec::new();
+    loop {
+        let woken = monitor
+            .dispatch(None, |device, input_events| {
+                let is_synthetic = device.is_synthetic();
+                let state = pending.entry(device.path().to_path_buf()).or_default();
+                for input_event in input_events {
+                    state.frame.push(*input_event);
+                    if input_event.type_ == EV_SYN && input_event.code == SYN_REPORT {
+                        forwarded.extend(filter_frame(
+                            state,
+                            &mut position,
+                            &mut input_state,
+                            is_synthetic,
+                            callback,
+                        ));
+                    }
+                }
+            })
+            .map_err(GrabError::IoError)?;
+        if !forwarded.is_empty() {
+            if let Err(e) = output.emit(&forwarded) {
+                debug!("Could not forward grabbed events: {}", e);
+            }
+            forwarded.clear();
+        }
+        if woken {
+            return Ok(());
+        }
+    }
+}
+
+/// Stops a running evdev `grab`, releasing every device.
+pub fn exit_grab() -> Result<(), GrabError> {
+    if let Some(waker) = WAKER.lock().as_ref() {
+        waker
+            .wake()
+            .map_err(|e| GrabError::ExitGrabError(e.to_string()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::evdev::BTN_LEFT;
+    use crate::rdev::{Button, Key};
+
+    fn input(type_: u16, code: u16, value: i32) -> libc::input_event {
+        libc::input_event {
+            time: libc::timeval {
+                tv_sec: 1,
+                tv_usec: 0,
+            },
+            type_,
+            code,
+            value,
+        }
+    }
+
+    fn filter<F>(frame: &[libc::input_event], mut callback: F) -> Vec<(u16, u16, i32)>
+    where
+        F: FnMut(Event) -> Option<Event>,
+    {
+        let mut pending = Pending {
+            frame: frame.to_vec(),
+            ..Default::default()
+        };
+        filter_frame(
+            &mut pending,
+            &mut Position::default(),
+            &mut InputState::new(),
+            false,
+            &mut callback,
+        )
+        .into_iter()
+        .map(|event| (event.type_, event.code, event.value))
+        .collect()
+    }
+
+    #[test]
+    fn test_pass_through() {
+        // KEY_TAB = 15
+        let frame = [
+            input(EV_MSC, 4, 0x7002b),
+            input(EV_KEY, 15, 1),
+            input(EV_SYN, SYN_REPORT, 0),
+        ];
+        assert_eq!(
+            filter(&frame, Some),
+            vec![
+                (EV_MSC, 4, 0x7002b),
+                (EV_KEY, 15, 1),
+                (EV_SYN, SYN_REPORT, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_suppress_key() {
+        let frame = [
+            input(EV_MSC, 4, 0x7002b),
+            input(EV_KEY, 15, 1),
+            input(EV_SYN, SYN_REPORT, 0),
+        ];
+        let filtered = filter(&frame, |event| match event.event_type {
+            EventType::KeyPress(Key::Tab) => None,
+            _ => Some(event),
+        });
+        assert_eq!(filtered, vec![]);
+    }
+
+    #[test]
+    fn test_suppress_part_of_frame() {
+        let frame = [
+            input(EV_REL, REL_X, 2),
+            input(EV_KEY, BTN_LEFT, 1),
+            input(EV_REL, REL_WHEEL, 1),
+            input(EV_REL, REL_WHEEL_HI_RES, 120),
+            input(EV_SYN, SYN_REPORT, 0),
+        ];
+        let filtered = filter(&frame, |event| match event.event_type {
+            EventType::ButtonPress(Button::Left) | EventType::Wheel { .. } => None,
+            _ => Some(event),
+        });
+        assert_eq!(filtered, vec![(EV_REL, REL_X, 2), (EV_SYN, SYN_REPORT, 0)]);
+    }
+}
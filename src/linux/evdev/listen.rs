@@ -0,0 +1,287 @@
+use crate::input_state::InputState;
+use crate::linux::evdev::{Converter, Device, DeviceMonitor, Position, set_modifiers};
+use crate::listen::ListenerBuilder;
+use crate::rdev::{Event, ListenError};
+use mio::Waker;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::error;
+
+// Set for the whole call to `listen`, `WAKER` only once devices are opened.
+static IS_STARTED: AtomicBool = AtomicBool::new(false);
+static WAKER: Mutex<Option<Waker>> = Mutex::new(None);
+
+fn accept_keyboard(device: &Device) -> bool {
+    device.is_keyboard()
+}
+
+fn accept_mouse(device: &Device) -> bool {
+    device.is_mouse()
+}
+
+fn accept_any(device: &Device) -> bool {
+    device.is_keyboard() || device.is_mouse()
+}
+
+/// A converter per device, as each one has its own reports. Motion is
+/// accumulated across every pointer, like the X server does with its core
+/// pointer.
+#[derive(Default)]
+struct Converters {
+    devices: HashMap<PathBuf, Converter>,
+    position: Position,
+}
+
+impl Converters {
+    fn feed(
+        &mut self,
+        path: &Path,
+        is_synthetic: bool,
+        input_events: &[libc::input_event],
+        out: &mut Vec<Event>,
+    ) {
+        let converter = self.devices.entry(path.to_path_buf()).or_default();
+        for input_event in input_events {
+            converter.feed(input_event, is_synthetic, &mut self.position, out);
+        }
+    }
+}
+
+pub fn listen<T>(config: &ListenerBuilder, mut callback: T) -> Result<(), ListenError>
+where
+    T: FnMut(Event) + Send + 'static,
+{
+    if IS_STARTED
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        error!(
+            "listen_evdev() called multiple times - this is not allowed. Only one listener can be active at a time."
+        );
+        return Err(ListenError::AlreadyListening);
+    }
+    let result = run(config, &mut callback);
+    *WAKER.lock() = None;
+    IS_STARTED.store(false, Ordering::SeqCst);
+    result
+}
+
+fn run<T>(config: &ListenerBuilder, callback: &mut T) -> Result<(), ListenError>
+where
+    T: FnMut(Event),
+{
+    // Only open the devices that can produce the enabled events.
+    let accept = match (config.keys, config.mouse()) {
+        (true, false) => accept_keyboard,
+        (false, true) => accept_mouse,
+        _ => accept_any,
+    };
+    let mut monitor = DeviceMonitor::new(accept, false).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            ListenError::MissingDeviceError
+        } else {
+            ListenError::IoError(e)
+        }
+    })?;
+    *WAKER.lock() = Some(monitor.waker().map_err(ListenError::IoError)?);
+
+    let mut converters = Converters::default();
+    let mut state = InputState::new();
+    let mut events = Vec::new();
+    loop {
+        let woken = monitor
+            .dispatch(None, |device, input_events| {
+                converters.feed(
+                    device.path(),
+                    device.is_synthetic(),
+                    input_events,
+                    &mut events,
+                );
+            })
+            .map_err(ListenError::IoError)?;
+        set_modifiers(&mut state, &mut events);
+        for event in events.drain(..) {
+            callback(event);
+        }
+        if woken {
+            return Ok(());
+        }
+    }
+}
+
+#[inline]
+pub fn is_listening() -> bool {
+    WAKER.lock().is_some()
+}
+
+/// Stops a running `listen_evdev`, which then returns `Ok(())`.
+pub fn exit_listen() -> Result<(), ListenError> {
+    if let Some(waker) = WAKER.lock().as_ref() {
+        waker
+            .wake()
+            .map_err(|e| ListenError::ExitListenError(e.to_string()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_state::InputState;
+    use crate::linux::evdev::{BTN_LEFT, EV_KEY, EV_REL, EV_SYN, REL_X, SYN_DROPPED, SYN_REPORT};
+    use crate::rdev::{Button, EventType, Key, Modifiers};
+
+    const KEYBOARD: &str = "/dev/input/event0";
+    const MOUSE: &str = "/dev/input/event1";
+    const TOUCHPAD: &str = "/dev/input/event2";
+
+    fn input(type_: u16, code: u16, value: i32) -> libc::input_event {
+        libc::input_event {
+            time: libc::timeval {
+                tv_sec: 1,
+                tv_usec: 0,
+            },
+            type_,
+            code,
+            value,
+        }
+    }
+
+    fn feed(
+        converters: &mut Converters,
+        device: &str,
+        is_synthetic: bool,
+        input_events: &[libc::input_event],
+    ) -> Vec<(EventType, bool)> {
+        let mut events = Vec::new();
+        converters.feed(Path::new(device), is_synthetic, input_events, &mut events);
+        events
+            .into_iter()
+            .map(|event| (event.event_type, event.is_synthetic))
+            .collect()
+    }
+
+    #[test]
+    fn test_reports_per_device() {
+        // KEY_S = 31
+        let mut converters = Converters::default();
+        assert_eq!(
+            feed(&mut converters, MOUSE, false, &[input(EV_REL, REL_X, 3)]),
+            []
+        );
+        // A synthetic keyboard reporting does not flush the motion of the mouse.
+        assert_eq!(
+            feed(
+                &mut converters,
+                KEYBOARD,
+                true,
+                &[input(EV_KEY, 31, 1), input(EV_SYN, SYN_REPORT, 0)]
+            ),
+            [(EventType::KeyPress(Key::KeyS), true)]
+        );
+        assert_eq!(
+            feed(
+                &mut converters,
+                MOUSE,
+                false,
+                &[input(EV_SYN, SYN_REPORT, 0)]
+            ),
+            [
+                (
+                    EventType::MouseMoveRaw {
+                        delta_x: 3,
+                        delta_y: 0
+                    },
+                    false
+                ),
+                (EventType::MouseMove { x: 3.0, y: 0.0 }, false)
+            ]
+        );
+        // The position is shared by the pointers.
+        assert_eq!(
+            feed(
+                &mut converters,
+                TOUCHPAD,
+                false,
+                &[input(EV_REL, REL_X, 2), input(EV_SYN, SYN_REPORT, 0)]
+            ),
+            [
+                (
+                    EventType::MouseMoveRaw {
+                        delta_x: 2,
+                        delta_y: 0
+                    },
+                    false
+                ),
+                (EventType::MouseMove { x: 5.0, y: 0.0 }, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_syn_dropped_per_device() {
+        let mut converters = Converters::default();
+        assert_eq!(
+            feed(
+                &mut converters,
+                KEYBOARD,
+                false,
+                &[input(EV_SYN, SYN_DROPPED, 0)]
+            ),
+            []
+        );
+        assert_eq!(
+            feed(
+                &mut converters,
+                MOUSE,
+                false,
+                &[input(EV_KEY, BTN_LEFT, 1), input(EV_SYN, SYN_REPORT, 0)]
+            ),
+            [(EventType::ButtonPress(Button::Left), false)]
+        );
+        // The keyboard still waits for its next report.
+        assert_eq!(
+            feed(
+                &mut converters,
+                KEYBOARD,
+                false,
+                &[input(EV_KEY, 31, 1), input(EV_SYN, SYN_REPORT, 0)]
+            ),
+            []
+        );
+    }
+
+    #[test]
+    fn test_modifiers_across_devices() {
+        // KEY_LEFTCTRL = 29 on the keyboard, a click on the mouse.
+        let mut converters = Converters::default();
+        let mut events = Vec::new();
+        let report = input(EV_SYN, SYN_REPORT, 0);
+        for (device, input_event) in [
+            (KEYBOARD, input(EV_KEY, 29, 1)),
+            (MOUSE, input(EV_KEY, BTN_LEFT, 1)),
+            (KEYBOARD, input(EV_KEY, 29, 0)),
+            (MOUSE, input(EV_KEY, BTN_LEFT, 0)),
+        ] {
+            converters.feed(
+                Path::new(device),
+                false,
+                &[input_event, report],
+                &mut events,
+            );
+        }
+        set_modifiers(&mut InputState::new(), &mut events);
+        let modifiers: Vec<Modifiers> = events.iter().map(|event| event.modifiers).collect();
+        assert_eq!(
+            modifiers,
+            [
+                Modifiers::empty(),
+                Modifiers::CTRL,
+                Modifiers::CTRL,
+                Modifiers::empty()
+            ]
+        );
+    }
+}
@@ -0,0 +1,789 @@
+// Kernel input (evdev) plumbing shared by the X11-free backends.
+// Everything here talks to /dev/input/event* directly through ioctls, so it
+// works on Wayland sessions, TTYs and headless machines alike.
+use crate::input_state::InputState;
+use crate::keycodes::linux::key_from_code;
+use crate::rdev::{Button, Event, EventType, Modifiers};
+use inotify::{Inotify, WatchMask};
+use mio::{Events, Interest, Poll, Token, unix::SourceFd};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::mem::size_of;
+use std::os::fd::AsRawFd;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+#[cfg(feature = "unstable_grab")]
+mod grab;
+mod listen;
+#[cfg(feature = "unstable_wayland")]
+mod uinput;
+
+#[cfg(feature = "unstable_grab")]
+pub use crate::linux::evdev::grab::{exit_grab, grab, is_grabbed};
+pub use crate::linux::evdev::listen::{exit_listen, is_listening, listen};
+#[cfg(feature = "unstable_wayland")]
+pub use crate::linux::evdev::uinput::{VirtualDevice, simulate};
+
+pub const INPUT_DIR: &str = "/dev/input";
+
+// linux/input-event-codes.h
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const SYN_REPORT: u16 = 0x00;
+pub const SYN_DROPPED: u16 = 0x03;
+pub const REL_X: u16 = 0x00;
+pub const REL_Y: u16 = 0x01;
+pub const REL_HWHEEL: u16 = 0x06;
+pub const REL_WHEEL: u16 = 0x08;
+pub const KEY_A: u16 = 30;
+pub const KEY_SPACE: u16 = 57;
+pub const BTN_LEFT: u16 = 0x110;
+pub const BTN_RIGHT: u16 = 0x111;
+pub const BTN_MIDDLE: u16 = 0x112;
+pub const BTN_SIDE: u16 = 0x113;
+pub const BTN_EXTRA: u16 = 0x114;
+pub const BTN_FORWARD: u16 = 0x115;
+pub const BTN_BACK: u16 = 0x116;
+pub const BTN_TASK: u16 = 0x117;
+// Everything in [BTN_MISC, KEY_OK) is a button of some sort (mouse, joystick,
+// digitizer). Keyboard keys live on both sides of that range.
+pub const BTN_MISC: u16 = 0x100;
+pub const KEY_OK: u16 = 0x160;
+pub const BUS_VIRTUAL: u16 = 0x06;
+
+/// Name of the uinput device a grab forwards the user's input on. It is
+/// virtual, but nothing on it is synthetic.
+pub const FORWARD_DEVICE_NAME: &str = "rdev grab forwarding";
+
+/// X11 keycodes are the kernel keycodes shifted by 8, and our keycode tables
+/// are written in X11 keycodes.
+pub const X_KEYCODE_OFFSET: u32 = 8;
+
+pub const IOC_WRITE: u32 = 1;
+pub const IOC_READ: u32 = 2;
+
+pub const fn ioc(dir: u32, ty: u8, nr: u8, size: usize) -> u32 {
+    (dir << 30) | ((size as u32) << 16) | ((ty as u32) << 8) | nr as u32
+}
+
+const fn eviocgbit(ev: u16, len: usize) -> u32 {
+    ioc(IOC_READ, b'E', 0x20 + ev as u8, len)
+}
+
+const fn eviocgname(len: usize) -> u32 {
+    ioc(IOC_READ, b'E', 0x06, len)
+}
+
+const fn eviocgkey(len: usize) -> u32 {
+    ioc(IOC_READ, b'E', 0x18, len)
+}
+
+const EVIOCGID: u32 = ioc(IOC_READ, b'E', 0x02, size_of::<libc::input_id>());
+const EVIOCGRAB: u32 = ioc(IOC_WRITE, b'E', 0x90, size_of::<c_int>());
+
+const KEY_BYTES: usize = libc::KEY_CNT.div_ceil(8);
+const REL_BYTES: usize = libc::REL_CNT.div_ceil(8);
+const EV_BYTES: usize = libc::EV_CNT.div_ceil(8);
+
+#[inline]
+fn test_bit(bits: &[u8], bit: u16) -> bool {
+    bits.get(bit as usize / 8)
+        .is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+}
+
+/// An opened /dev/input/event* node.
+pub struct Device {
+    file: File,
+    path: PathBuf,
+    name: String,
+    id: libc::input_id,
+    ev_bits: [u8; EV_BYTES],
+    key_bits: [u8; KEY_BYTES],
+    rel_bits: [u8; REL_BYTES],
+    grabbed: bool,
+}
+
+impl Device {
+    pub fn open(path: &Path) -> io::Result<Device> {
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open(path)?;
+        let mut device = Device {
+            file,
+            path: path.to_path_buf(),
+            name: String::new(),
+            id: libc::input_id {
+                bustype: 0,
+                vendor: 0,
+                product: 0,
+                version: 0,
+            },
+            ev_bits: [0; EV_BYTES],
+            key_bits: [0; KEY_BYTES],
+            rel_bits: [0; REL_BYTES],
+            grabbed: false,
+        };
+        let fd = device.file.as_raw_fd();
+        let mut name = [0 as c_char; 256];
+        unsafe {
+            if libc::ioctl(fd, eviocgbit(0, EV_BYTES) as _, device.ev_bits.as_mut_ptr()) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Devices without keys or relative axes simply leave these zeroed.
+            libc::ioctl(
+                fd,
+                eviocgbit(EV_KEY, KEY_BYTES) as _,
+                device.key_bits.as_mut_ptr(),
+            );
+            libc::ioctl(
+                fd,
+                eviocgbit(EV_REL, REL_BYTES) as _,
+                device.rel_bits.as_mut_ptr(),
+            );
+            libc::ioctl(fd, EVIOCGID as _, &mut device.id);
+            if libc::ioctl(fd, eviocgname(name.len()) as _, name.as_mut_ptr()) >= 0 {
+                let bytes: Vec<u8> = name
+                    .iter()
+                    .take_while(|c| **c != 0)
+                    .map(|c| *c as u8)
+                    .collect();
+                device.name = String::from_utf8_lossy(&bytes).into_owned();
+            }
+        }
+        Ok(device)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn has_key(&self, code: u16) -> bool {
+        test_bit(&self.ev_bits, EV_KEY) && test_bit(&self.key_bits, code)
+    }
+
+    pub fn has_rel(&self, code: u16) -> bool {
+        test_bit(&self.ev_bits, EV_REL) && test_bit(&self.rel_bits, code)
+    }
+
+    pub fn is_keyboard(&self) -> bool {
+        self.has_key(KEY_A) && self.has_key(KEY_SPACE)
+    }
+
+    pub fn is_mouse(&self) -> bool {
+        self.has_rel(REL_X) && self.has_rel(REL_Y) && self.has_key(BTN_LEFT)
+    }
+
+    /// Whether the events of the device were injected rather than typed, as
+    /// far as evdev tells.
+    pub fn is_synthetic(&self) -> bool {
+        is_synthetic(self.id.bustype, &self.name)
+    }
+
+    /// Whether any key or button of the device is currently held down.
+    pub fn any_key_down(&self) -> bool {
+        let mut state = [0_u8; KEY_BYTES];
+        let res = unsafe {
+            libc::ioctl(
+                self.file.as_raw_fd(),
+                eviocgkey(KEY_BYTES) as _,
+                state.as_mut_ptr(),
+            )
+        };
+        res >= 0 && state.iter().any(|byte| *byte != 0)
+    }
+
+    /// EVIOCGRAB: while grabbed, nobody else (X server, compositor, other
+    /// evdev readers) receives events from this device.
+    pub fn set_grab(&mut self, grab: bool) -> io::Result<()> {
+        if self.grabbed == grab {
+            return Ok(());
+        }
+        let res = unsafe { libc::ioctl(self.file.as_raw_fd(), EVIOCGRAB as _, grab as c_int) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.grabbed = grab;
+        Ok(())
+    }
+
+    /// Reads every pending input_event without blocking.
+    pub fn read_events(&mut self, out: &mut Vec<libc::input_event>) -> io::Result<()> {
+        const BATCH: usize = 64;
+        let mut buf = [0_u8; BATCH * size_of::<libc::input_event>()];
+        loop {
+            match self.file.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(len) => {
+                    for chunk in buf[..len].chunks_exact(size_of::<libc::input_event>()) {
+                        // The kernel only hands out whole events.
+                        #[allow(clippy::cast_ptr_alignment)]
+                        let event = unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const _) };
+                        out.push(event);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        let _ = self.set_grab(false);
+    }
+}
+
+/// Devices created through uinput report a virtual bus, which is the
+/// closest thing evdev has to an "injected" flag. The one a grab forwards on
+/// only carries what the user typed on the grabbed devices.
+fn is_synthetic(bustype: u16, name: &str) -> bool {
+    bustype == BUS_VIRTUAL && name != FORWARD_DEVICE_NAME
+}
+
+pub fn is_event_node(name: &OsStr) -> bool {
+    name.to_str().is_some_and(|name| name.starts_with("event"))
+}
+
+pub fn list_devices() -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(INPUT_DIR) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| is_event_node(&entry.file_name()))
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+const WATCH: Token = Token(0);
+const WAKE: Token = Token(usize::MAX);
+const FIRST_DEVICE: usize = 1;
+
+/// How long we wait for keys to be released before grabbing a device.
+/// Grabbing with a key down means its release never reaches the rest of
+/// the system, and the key stays stuck there.
+const GRAB_SETTLE_TIMEOUT: Duration = Duration::from_secs(1);
+/// How often the devices waiting for their keys to be released are checked.
+const GRAB_SETTLE_POLL: Duration = Duration::from_millis(10);
+
+/// Keeps a set of input devices open, picking up hot-plugged ones through
+/// inotify on /dev/input.
+pub struct DeviceMonitor {
+    poll: Poll,
+    inotify: Inotify,
+    devices: HashMap<Token, Device>,
+    // Opened, but waiting for their keys to be released to be grabbed.
+    settling: Vec<(Device, Instant)>,
+    next_token: usize,
+    accept: fn(&Device) -> bool,
+    grab: bool,
+}
+
+impl DeviceMonitor {
+    /// Opens every device `accept` approves of. When `grab` is set, devices
+    /// are EVIOCGRAB'ed as soon as none of their keys is held, which
+    /// `dispatch` checks for the ones opened with keys down.
+    pub fn new(accept: fn(&Device) -> bool, grab: bool) -> io::Result<DeviceMonitor> {
+        let poll = Poll::new()?;
+        let inotify = Inotify::init()?;
+        // ATTRIB matters: udev usually fixes the node permissions right after
+        // CREATE, so the first open attempt may fail.
+        inotify
+            .watches()
+            .add(INPUT_DIR, WatchMask::CREATE | WatchMask::ATTRIB)?;
+        poll.registry().register(
+            &mut SourceFd(&inotify.as_raw_fd()),
+            WATCH,
+            Interest::READABLE,
+        )?;
+        let mut monitor = DeviceMonitor {
+            poll,
+            inotify,
+            devices: HashMap::new(),
+            settling: Vec::new(),
+            next_token: FIRST_DEVICE,
+            accept,
+            grab,
+        };
+        let mut last_error = None;
+        for path in list_devices() {
+            if let Err(e) = monitor.add(&path) {
+                last_error = Some(e);
+            }
+        }
+        if monitor.devices.is_empty() && monitor.settling.is_empty() {
+            return Err(last_error.unwrap_or_else(|| io::ErrorKind::NotFound.into()));
+        }
+        Ok(monitor)
+    }
+
+    fn add(&mut self, path: &Path) -> io::Result<()> {
+        let mut known = self
+            .devices
+            .values()
+            .chain(self.settling.iter().map(|(device, _)| device));
+        if known.any(|device| device.path() == path) {
+            return Ok(());
+        }
+        let device = Device::open(path)?;
+        if !(self.accept)(&device) {
+            return Ok(());
+        }
+        if self.grab && device.any_key_down() {
+            // Grabbed by a later `dispatch`, the other devices keep going.
+            debug!("Waiting for the keys of {:?} to be released", path);
+            self.settling.push((device, Instant::now()));
+            return Ok(());
+        }
+        self.register(device)
+    }
+
+    fn register(&mut self, mut device: Device) -> io::Result<()> {
+        if self.grab {
+            device.set_grab(true)?;
+        }
+        let token = Token(self.next_token);
+        self.next_token += 1;
+        self.poll.registry().register(
+            &mut SourceFd(&device.file.as_raw_fd()),
+            token,
+            Interest::READABLE,
+        )?;
+        debug!(
+            "Opened input device {:?} ({})",
+            device.path(),
+            device.name()
+        );
+        self.devices.insert(token, device);
+        Ok(())
+    }
+
+    /// Grabs the devices whose keys were released, or held for too long.
+    fn settle(&mut self) {
+        let (ready, settling): (Vec<_>, Vec<_>) = std::mem::take(&mut self.settling)
+            .into_iter()
+            .partition(|(device, since)| {
+                !device.any_key_down() || since.elapsed() >= GRAB_SETTLE_TIMEOUT
+            });
+        self.settling = settling;
+        for (device, _) in ready {
+            let path = device.path().to_path_buf();
+            if let Err(e) = self.register(device) {
+                debug!("Could not grab input device {:?}: {}", path, e);
+            }
+        }
+    }
+
+    fn remove(&mut self, token: Token) {
+        if let Some(device) = self.devices.remove(&token) {
+            debug!("Closed input device {:?}", device.path());
+            let _ = self
+                .poll
+                .registry()
+                .deregister(&mut SourceFd(&device.file.as_raw_fd()));
+        }
+    }
+
+    fn handle_hotplug(&mut self) {
+        let mut buffer = [0_u8; 4096];
+        let mut added = Vec::new();
+        while let Ok(events) = self.inotify.read_events(&mut buffer) {
+            let mut any = false;
+            for event in events {
+                any = true;
+                if let Some(name) = event.name
+                    && is_event_node(name)
+                {
+                    added.push(Path::new(INPUT_DIR).join(name));
+                }
+            }
+            if !any {
+                break;
+            }
+        }
+        for path in added {
+            if let Err(e) = self.add(&path) {
+                debug!("Could not open input device {:?}: {}", path, e);
+            }
+        }
+    }
+
+    /// A waker that interrupts `dispatch` from another thread.
+    pub fn waker(&self) -> io::Result<mio::Waker> {
+        mio::Waker::new(self.poll.registry(), WAKE)
+    }
+
+    /// Waits for input and hands every raw event, together with the device it
+    /// came from, to `handler`. Returns `true` when woken up through `waker`.
+    pub fn dispatch<F>(&mut self, timeout: Option<Duration>, mut handler: F) -> io::Result<bool>
+    where
+        F: FnMut(&Device, &[libc::input_event]),
+    {
+        let timeout = match timeout {
+            _ if self.settling.is_empty() => timeout,
+            Some(timeout) => Some(timeout.min(GRAB_SETTLE_POLL)),
+            None => Some(GRAB_SETTLE_POLL),
+        };
+        let mut events = Events::with_capacity(64);
+        match self.poll.poll(&mut events, timeout) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => return Ok(false),
+            Err(e) => return Err(e),
+        }
+        let mut buf = Vec::new();
+        let mut gone = Vec::new();
+        let mut woken = false;
+        for event in &events {
+            let token = event.token();
+            if token == WAKE {
+                woken = true;
+                continue;
+            }
+            if token == WATCH {
+                self.handle_hotplug();
+                continue;
+            }
+            let Some(device) = self.devices.get_mut(&token) else {
+                continue;
+            };
+            buf.clear();
+            let res = device.read_events(&mut buf);
+            if !buf.is_empty() {
+                handler(device, &buf);
+            }
+            if let Err(e) = res {
+                // ENODEV once the device is unplugged.
+                debug!("Input device {:?} failed: {}", device.path(), e);
+                gone.push(token);
+            }
+        }
+        for token in gone {
+            self.remove(token);
+        }
+        self.settle();
+        Ok(woken)
+    }
+}
+
+pub fn event_time(event: &libc::input_event) -> SystemTime {
+    UNIX_EPOCH
+        + Duration::new(
+            event.time.tv_sec.max(0) as u64,
+            (event.time.tv_usec.max(0) as u32).saturating_mul(1000),
+        )
+}
+
+pub fn button_from_code(code: u16) -> Option<Button> {
+    match code {
+        BTN_LEFT => Some(Button::Left),
+        BTN_RIGHT => Some(Button::Right),
+        BTN_MIDDLE => Some(Button::Middle),
+        BTN_SIDE => Some(Button::Back),
+        BTN_EXTRA => Some(Button::Forward),
+        // Same numbering the X server uses for these buttons.
+        BTN_FORWARD => Some(Button::Unknown(10)),
+        BTN_BACK => Some(Button::Unknown(11)),
+        BTN_TASK => Some(Button::Unknown(12)),
+        _ => None,
+    }
+}
+
+/// The pointer position, moved by the relative motion of every device like
+/// the X server's core pointer.
+#[derive(Debug, Default)]
+pub struct Position {
+    x: f64,
+    y: f64,
+}
+
+/// Folds the kernel input events of one device into rdev events. Relative
+/// motion and wheel are accumulated until SYN_REPORT, so that one hardware
+/// report gives one motion event no matter how many axes it touched.
+#[derive(Debug, Default)]
+pub struct Converter {
+    delta_x: i32,
+    delta_y: i32,
+    wheel_x: i32,
+    wheel_y: i32,
+    dropped: bool,
+}
+
+impl Converter {
+    pub fn feed(
+        &mut self,
+        event: &libc::input_event,
+        is_synthetic: bool,
+        position: &mut Position,
+        out: &mut Vec<Event>,
+    ) {
+        if self.dropped {
+            // The kernel buffer overflowed, everything up to the next report
+            // is incomplete.
+            if event.type_ == EV_SYN && event.code == SYN_REPORT {
+                self.dropped = false;
+            }
+            return;
+        }
+        let time = event_time(event);
+        match event.type_ {
+            EV_KEY => {
+                let is_press = event.value != 0;
+                let code = event.code;
+                let event_type = if (BTN_MISC..KEY_OK).contains(&code) {
+                    let Some(button) = button_from_code(code) else {
+                        return;
+                    };
+                    if is_press {
+                        EventType::ButtonPress(button)
+                    } else {
+                        EventType::ButtonRelease(button)
+                    }
+                } else {
+                    // Autorepeat (value 2) is reported as another press, like X does.
+                    let key = key_from_code(code as u32 + X_KEYCODE_OFFSET);
+                    if is_press {
+                        EventType::KeyPress(key)
+                    } else {
+                        EventType::KeyRelease(key)
+                    }
+                };
+                out.push(new_event(
+                    event_type,
+                    time,
+                    code as u32 + X_KEYCODE_OFFSET,
+                    is_synthetic,
+                ));
+            }
+            EV_REL => match event.code {
+                REL_X => self.delta_x += event.value,
+                REL_Y => self.delta_y += event.value,
+                REL_WHEEL => self.wheel_y += event.value,
+                REL_HWHEEL => self.wheel_x += event.value,
+                _ => {}
+            },
+            EV_SYN => match event.code {
+                SYN_REPORT => self.flush(time, is_synthetic, position, out),
+                SYN_DROPPED => {
+                    self.delta_x = 0;
+                    self.delta_y = 0;
+                    self.wheel_x = 0;
+                    self.wheel_y = 0;
+                    self.dropped = true;
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn flush(
+        &mut self,
+        time: SystemTime,
+        is_synthetic: bool,
+        position: &mut Position,
+        out: &mut Vec<Event>,
+    ) {
+        if self.delta_x != 0 || self.delta_y != 0 {
+            position.x += self.delta_x as f64;
+            position.y += self.delta_y as f64;
+            out.push(new_event(
+                EventType::MouseMoveRaw {
+                    delta_x: self.delta_x,
+                    delta_y: self.delta_y,
+                },
+                time,
+                0,
+                is_synthetic,
+            ));
+            out.push(new_event(
+                EventType::MouseMove {
+                    x: position.x,
+                    y: position.y,
+                },
+                time,
+                0,
+                is_synthetic,
+            ));
+            self.delta_x = 0;
+            self.delta_y = 0;
+        }
+        if self.wheel_x != 0 || self.wheel_y != 0 {
+            out.push(new_event(
+                EventType::Wheel {
+                    delta_x: self.wheel_x as f64,
+                    delta_y: self.wheel_y as f64,
+                },
+                time,
+                0,
+                is_synthetic,
+            ));
+            self.wheel_x = 0;
+            self.wheel_y = 0;
+        }
+    }
+}
+
+fn new_event(event_type: EventType, time: SystemTime, code: u32, is_synthetic: bool) -> Event {
+    Event {
+        event_type,
+        time,
+        unicode: None,
+        platform_code: code,
+        position_code: code,
+        usb_hid: 0,
+        extra_data: 0,
+        is_synthetic,
+        modifiers: Modifiers::empty(),
+    }
+}
+
+/// Sets the modifiers of the events from the keys seen before them. The
+/// state is shared by every device, the modifiers of one keyboard apply to
+/// the clicks of any mouse.
+pub(crate) fn set_modifiers(state: &mut InputState, events: &mut [Event]) {
+    for event in events {
+        event.modifiers = state.modifiers();
+        state.update(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdev::Key;
+
+    fn input(type_: u16, code: u16, value: i32) -> libc::input_event {
+        libc::input_event {
+            time: libc::timeval {
+                tv_sec: 1,
+                tv_usec: 0,
+            },
+            type_,
+            code,
+            value,
+        }
+    }
+
+    fn convert(events: &[libc::input_event]) -> Vec<EventType> {
+        let mut converter = Converter::default();
+        let mut position = Position::default();
+        let mut out = Vec::new();
+        for event in events {
+            converter.feed(event, false, &mut position, &mut out);
+        }
+        out.into_iter().map(|event| event.event_type).collect()
+    }
+
+    #[test]
+    fn test_keys() {
+        // KEY_S = 31, KEY_LEFTSHIFT = 42
+        let events = convert(&[
+            input(EV_KEY, 42, 1),
+            input(EV_SYN, SYN_REPORT, 0),
+            input(EV_KEY, 31, 1),
+            input(EV_SYN, SYN_REPORT, 0),
+            input(EV_KEY, 31, 2),
+            input(EV_SYN, SYN_REPORT, 0),
+            input(EV_KEY, 31, 0),
+            input(EV_SYN, SYN_REPORT, 0),
+        ]);
+        assert_eq!(
+            events,
+            vec![
+                EventType::KeyPress(Key::ShiftLeft),
+                EventType::KeyPress(Key::KeyS),
+                EventType::KeyPress(Key::KeyS),
+                EventType::KeyRelease(Key::KeyS),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_motion_is_folded_per_report() {
+        let events = convert(&[
+            input(EV_REL, REL_X, 3),
+            input(EV_REL, REL_Y, -2),
+            input(EV_SYN, SYN_REPORT, 0),
+            input(EV_REL, REL_X, 4),
+            input(EV_SYN, SYN_REPORT, 0),
+        ]);
+        assert_eq!(
+            events,
+            vec![
+                EventType::MouseMoveRaw {
+                    delta_x: 3,
+                    delta_y: -2
+                },
+                EventType::MouseMove { x: 3.0, y: -2.0 },
+                EventType::MouseMoveRaw {
+                    delta_x: 4,
+                    delta_y: 0
+                },
+                EventType::MouseMove { x: 7.0, y: -2.0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_buttons_and_wheel() {
+        let events = convert(&[
+            input(EV_KEY, BTN_LEFT, 1),
+            input(EV_SYN, SYN_REPORT, 0),
+            input(EV_KEY, BTN_LEFT, 0),
+            input(EV_REL, REL_WHEEL, -1),
+            input(EV_REL, REL_HWHEEL, 2),
+            input(EV_SYN, SYN_REPORT, 0),
+        ]);
+        assert_eq!(
+            events,
+            vec![
+                EventType::ButtonPress(Button::Left),
+                EventType::ButtonRelease(Button::Left),
+                EventType::Wheel {
+                    delta_x: 2.0,
+                    delta_y: -1.0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_syn_dropped() {
+        let events = convert(&[
+            input(EV_REL, REL_X, 3),
+            input(EV_SYN, SYN_DROPPED, 0),
+            input(EV_KEY, 31, 1),
+            input(EV_SYN, SYN_REPORT, 0),
+            input(EV_KEY, 31, 0),
+            input(EV_SYN, SYN_REPORT, 0),
+        ]);
+        assert_eq!(events, vec![EventType::KeyRelease(Key::KeyS)]);
+    }
+
+    #[test]
+    fn test_synthetic_during_grab() {
+        // The user's input forwarded by a grab is what a listener sees of the
+        // grabbed devices, it must not pass for injected.
+        assert!(!is_synthetic(BUS_VIRTUAL, FORWARD_DEVICE_NAME));
+        assert!(is_synthetic(BUS_VIRTUAL, "rdev virtual input"));
+        assert!(is_synthetic(BUS_VIRTUAL, "ydotoold virtual device"));
+        // BUS_USB
+        assert!(!is_synthetic(0x03, "Logitech USB Receiver"));
+    }
+}
@@ -0,0 +1,363 @@
+use crate::keycodes::linux::code_from_key;
+use crate::linux::evdev::{
+    BTN_BACK, BTN_EXTRA, BTN_FORWARD, BTN_LEFT, BTN_MIDDLE, BTN_RIGHT, BTN_SIDE, BTN_TASK,
+    BUS_VIRTUAL, EV_KEY, EV_REL, EV_SYN, IOC_WRITE, REL_HWHEEL, REL_WHEEL, REL_X, REL_Y,
+    SYN_REPORT, X_KEYCODE_OFFSET, ioc,
+};
+use crate::rdev::{Button, EventType, Key, RawKey, SimulateError};
+use parking_lot::Mutex;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::mem::size_of;
+use std::os::fd::AsRawFd;
+use std::os::raw::{c_char, c_int, c_ulong};
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::Duration;
+use tracing::debug;
+
+const UINPUT_PATH: &str = "/dev/uinput";
+
+pub const EV_ABS: u16 = 0x03;
+pub const REL_WHEEL_HI_RES: u16 = 0x0b;
+pub const REL_HWHEEL_HI_RES: u16 = 0x0c;
+pub const ABS_X: u16 = 0x00;
+pub const ABS_Y: u16 = 0x01;
+
+const IOC_NONE: u32 = 0;
+
+const UI_DEV_CREATE: u32 = ioc(IOC_NONE, b'U', 1, 0);
+const UI_DEV_DESTROY: u32 = ioc(IOC_NONE, b'U', 2, 0);
+const UI_DEV_SETUP: u32 = ioc(IOC_WRITE, b'U', 3, size_of::<libc::uinput_setup>());
+const UI_ABS_SETUP: u32 = ioc(IOC_WRITE, b'U', 4, size_of::<libc::uinput_abs_setup>());
+const UI_SET_EVBIT: u32 = ioc(IOC_WRITE, b'U', 100, size_of::<c_int>());
+const UI_SET_KEYBIT: u32 = ioc(IOC_WRITE, b'U', 101, size_of::<c_int>());
+const UI_SET_RELBIT: u32 = ioc(IOC_WRITE, b'U', 102, size_of::<c_int>());
+const UI_SET_ABSBIT: u32 = ioc(IOC_WRITE, b'U', 103, size_of::<c_int>());
+
+/// Name given to the devices we create, so that readers (including our own
+/// grab) can recognise them.
+pub const DEVICE_NAME: &str = "rdev virtual input";
+const POINTER_PRODUCT: u16 = 0x0001;
+const ABSOLUTE_PRODUCT: u16 = 0x0002;
+const VENDOR: u16 = 0x7264;
+
+/// One notch of a wheel in REL_WHEEL_HI_RES units.
+const WHEEL_HI_RES: f64 = 120.0;
+
+/// Compositors and the X server need a moment to open a freshly created
+/// device, anything sent before that is lost.
+const SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+fn ioctl(file: &File, request: u32, arg: c_ulong) -> io::Result<()> {
+    if unsafe { libc::ioctl(file.as_raw_fd(), request as _, arg) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn create(
+    product: u16,
+    name: &str,
+    setup: impl FnOnce(&File) -> io::Result<()>,
+) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+        .open(UINPUT_PATH)?;
+    ioctl(&file, UI_SET_EVBIT, EV_SYN as c_ulong)?;
+    setup(&file)?;
+
+    let mut device = libc::uinput_setup {
+        id: libc::input_id {
+            bustype: BUS_VIRTUAL,
+            vendor: VENDOR,
+            product,
+            version: 1,
+        },
+        name: [0 as c_char; libc::UINPUT_MAX_NAME_SIZE],
+        ff_effects_max: 0,
+    };
+    for (dst, src) in device.name.iter_mut().zip(name.bytes()) {
+        *dst = src as c_char;
+    }
+    if unsafe { libc::ioctl(file.as_raw_fd(), UI_DEV_SETUP as _, &device) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    ioctl(&file, UI_DEV_CREATE, 0)?;
+    Ok(file)
+}
+
+fn set_bits(file: &File, request: u32, codes: impl IntoIterator<Item = u16>) -> io::Result<()> {
+    for code in codes {
+        ioctl(file, request, code as c_ulong)?;
+    }
+    Ok(())
+}
+
+fn input(type_: u16, code: u16, value: i32) -> libc::input_event {
+    libc::input_event {
+        // The kernel stamps the events itself.
+        time: libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+        type_,
+        code,
+        value,
+    }
+}
+
+fn write_events(file: &File, events: &[libc::input_event]) -> io::Result<()> {
+    let bytes = unsafe {
+        std::slice::from_raw_parts(events.as_ptr() as *const u8, std::mem::size_of_val(events))
+    };
+    (&*file).write_all(bytes)
+}
+
+fn code_from_button(button: Button) -> Option<u16> {
+    match button {
+        Button::Left => Some(BTN_LEFT),
+        Button::Right => Some(BTN_RIGHT),
+        Button::Middle => Some(BTN_MIDDLE),
+        Button::Back | Button::Unknown(8) => Some(BTN_SIDE),
+        Button::Forward | Button::Unknown(9) => Some(BTN_EXTRA),
+        Button::Unknown(10) => Some(BTN_FORWARD),
+        Button::Unknown(11) => Some(BTN_BACK),
+        Button::Unknown(12) => Some(BTN_TASK),
+        Button::Unknown(_) => None,
+    }
+}
+
+/// Kernel keycode for a key, which is what uinput expects.
+pub fn kernel_code_from_key(key: Key) -> Option<u16> {
+    let code = match key {
+        Key::RawKey(RawKey::LinuxConsoleKeycode(code)) => code,
+        Key::RawKey(RawKey::LinuxXorgKeycode(code)) | Key::Unknown(code) => {
+            code.checked_sub(X_KEYCODE_OFFSET)?
+        }
+        key => code_from_key(key)?.checked_sub(X_KEYCODE_OFFSET)?,
+    };
+    u16::try_from(code).ok()
+}
+
+/// A virtual keyboard and mouse created through /dev/uinput.
+///
+/// Events sent through it come from the kernel, so they reach X11, Wayland
+/// compositors and the console alike. Requires write access to /dev/uinput.
+pub struct VirtualDevice {
+    pointer: File,
+    absolute: Option<File>,
+    // Hi-res wheel units not yet reported as a whole notch.
+    wheel_x: i32,
+    wheel_y: i32,
+}
+
+impl VirtualDevice {
+    /// Creates the device, sizing the absolute pointer from `display_size`.
+    /// When the screen size is unknown (no X server), `MouseMove` is not
+    /// available; use [`VirtualDevice::with_screen_size`] instead.
+    pub fn new() -> io::Result<VirtualDevice> {
+        let screen = crate::linux::display_size().ok();
+        VirtualDevice::create(screen, DEVICE_NAME)
+    }
+
+    /// Creates the device for a screen of `width` x `height` pixels, which
+    /// is what `MouseMove` coordinates are mapped onto.
+    pub fn with_screen_size(width: u64, height: u64) -> io::Result<VirtualDevice> {
+        VirtualDevice::create(Some((width, height)), DEVICE_NAME)
+    }
+
+    /// Keyboard and relative pointer only, which is all a grab needs to
+    /// forward events.
+    #[cfg(feature = "unstable_grab")]
+    pub(crate) fn without_absolute() -> io::Result<VirtualDevice> {
+        VirtualDevice::create(None, crate::linux::evdev::FORWARD_DEVICE_NAME)
+    }
+
+    fn create(screen: Option<(u64, u64)>, name: &str) -> io::Result<VirtualDevice> {
+        let pointer = create(POINTER_PRODUCT, name, |file| {
+            ioctl(file, UI_SET_EVBIT, EV_KEY as c_ulong)?;
+            ioctl(file, UI_SET_EVBIT, EV_REL as c_ulong)?;
+            // Keyboard keys, skipping the joystick/gamepad/digitizer ranges
+            // that would make libinput misclassify the device.
+            set_bits(file, UI_SET_KEYBIT, 1..0x100)?;
+            set_bits(file, UI_SET_KEYBIT, BTN_LEFT..=BTN_TASK)?;
+            set_bits(file, UI_SET_KEYBIT, 0x160..0x2c0)?;
+            set_bits(
+                file,
+                UI_SET_RELBIT,
+                [
+                    REL_X,
+                    REL_Y,
+                    REL_WHEEL,
+                    REL_HWHEEL,
+                    REL_WHEEL_HI_RES,
+                    REL_HWHEEL_HI_RES,
+                ],
+            )
+        })?;
+        let absolute = match screen {
+            Some((width, height)) => Some(create(ABSOLUTE_PRODUCT, name, |file| {
+                ioctl(file, UI_SET_EVBIT, EV_KEY as c_ulong)?;
+                ioctl(file, UI_SET_EVBIT, EV_ABS as c_ulong)?;
+                // Never pressed, but it makes this an absolute pointer
+                // rather than a joystick.
+                ioctl(file, UI_SET_KEYBIT, BTN_LEFT as c_ulong)?;
+                for (code, size) in [(ABS_X, width), (ABS_Y, height)] {
+                    ioctl(file, UI_SET_ABSBIT, code as c_ulong)?;
+                    let setup = libc::uinput_abs_setup {
+                        code,
+                        absinfo: libc::input_absinfo {
+                            value: 0,
+                            minimum: 0,
+                            maximum: i32::try_from(size.saturating_sub(1)).unwrap_or(i32::MAX),
+                            fuzz: 0,
+                            flat: 0,
+                            resolution: 0,
+                        },
+                    };
+                    if unsafe { libc::ioctl(file.as_raw_fd(), UI_ABS_SETUP as _, &setup) } < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            })?),
+            None => None,
+        };
+        std::thread::sleep(SETTLE_DELAY);
+        Ok(VirtualDevice {
+            pointer,
+            absolute,
+            wheel_x: 0,
+            wheel_y: 0,
+        })
+    }
+
+    pub(crate) fn emit(&self, events: &[libc::input_event]) -> io::Result<()> {
+        write_events(&self.pointer, events)
+    }
+
+    fn wheel(&mut self, delta_x: f64, delta_y: f64) -> io::Result<()> {
+        let hi_x = (delta_x * WHEEL_HI_RES).round() as i32;
+        let hi_y = (delta_y * WHEEL_HI_RES).round() as i32;
+        self.wheel_x += hi_x;
+        self.wheel_y += hi_y;
+        let notches_x = self.wheel_x / WHEEL_HI_RES as i32;
+        let notches_y = self.wheel_y / WHEEL_HI_RES as i32;
+        self.wheel_x -= notches_x * WHEEL_HI_RES as i32;
+        self.wheel_y -= notches_y * WHEEL_HI_RES as i32;
+
+        let mut events = Vec::with_capacity(5);
+        for (code, value) in [
+            (REL_WHEEL_HI_RES, hi_y),
+            (REL_HWHEEL_HI_RES, hi_x),
+            (REL_WHEEL, notches_y),
+            (REL_HWHEEL, notches_x),
+        ] {
+            if value != 0 {
+                events.push(input(EV_REL, code, value));
+            }
+        }
+        events.push(input(EV_SYN, SYN_REPORT, 0));
+        self.emit(&events)
+    }
+
+    pub fn send(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+        let key =
+            |code: u16, value: i32| [input(EV_KEY, code, value), input(EV_SYN, SYN_REPORT, 0)];
+        let result = match event_type {
+            EventType::KeyPress(k) | EventType::KeyPressRaw(k) => {
+                self.emit(&key(kernel_code_from_key(*k).ok_or(SimulateError)?, 1))
+            }
+            EventType::KeyRelease(k) | EventType::KeyReleaseRaw(k) => {
+                self.emit(&key(kernel_code_from_key(*k).ok_or(SimulateError)?, 0))
+            }
+            EventType::ButtonPress(b) | EventType::ButtonPressRaw(b) => {
+                self.emit(&key(code_from_button(*b).ok_or(SimulateError)?, 1))
+            }
+            EventType::ButtonRelease(b) | EventType::ButtonReleaseRaw(b) => {
+                self.emit(&key(code_from_button(*b).ok_or(SimulateError)?, 0))
+            }
+            EventType::MouseMove { x, y } => {
+                let absolute = self.absolute.as_ref().ok_or(SimulateError)?;
+                write_events(
+                    absolute,
+                    &[
+                        input(EV_ABS, ABS_X, *x as i32),
+                        input(EV_ABS, ABS_Y, *y as i32),
+                        input(EV_SYN, SYN_REPORT, 0),
+                    ],
+                )
+            }
+            EventType::MouseMoveRaw { delta_x, delta_y } => self.emit(&[
+                input(EV_REL, REL_X, *delta_x),
+                input(EV_REL, REL_Y, *delta_y),
+                input(EV_SYN, SYN_REPORT, 0),
+            ]),
+            EventType::Wheel { delta_x, delta_y } | EventType::WheelRaw { delta_x, delta_y } => {
+                self.wheel(*delta_x, *delta_y)
+            }
+        };
+        result.map_err(|e| {
+            debug!("Could not write to uinput device: {}", e);
+            SimulateError
+        })
+    }
+}
+
+impl Drop for VirtualDevice {
+    fn drop(&mut self) {
+        let _ = ioctl(&self.pointer, UI_DEV_DESTROY, 0);
+        if let Some(absolute) = &self.absolute {
+            let _ = ioctl(absolute, UI_DEV_DESTROY, 0);
+        }
+    }
+}
+
+static DEVICE: Mutex<Option<VirtualDevice>> = Mutex::new(None);
+
+/// Same as `simulate`, but through a uinput device instead of XTest. The
+/// device is created on first use and kept for the lifetime of the process.
+pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
+    let mut device = DEVICE.lock();
+    if device.is_none() {
+        *device = Some(VirtualDevice::new().map_err(|e| {
+            debug!("Could not create uinput device: {}", e);
+            SimulateError
+        })?);
+    }
+    match device.as_mut() {
+        Some(device) => device.send(event_type),
+        None => Err(SimulateError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kernel_code_from_key() {
+        // KEY_A = 30, KEY_LEFTSHIFT = 42
+        assert_eq!(kernel_code_from_key(Key::KeyA), Some(30));
+        assert_eq!(kernel_code_from_key(Key::ShiftLeft), Some(42));
+        assert_eq!(
+            kernel_code_from_key(Key::RawKey(RawKey::LinuxConsoleKeycode(30))),
+            Some(30)
+        );
+        assert_eq!(
+            kernel_code_from_key(Key::RawKey(RawKey::LinuxXorgKeycode(38))),
+            Some(30)
+        );
+        assert_eq!(kernel_code_from_key(Key::Unknown(3)), None);
+    }
+
+    #[test]
+    fn test_ioctl_numbers() {
+        // Values from linux/uinput.h as compiled on x86_64.
+        assert_eq!(UI_DEV_CREATE, 0x5501);
+        assert_eq!(UI_DEV_SETUP, 0x405c5503);
+        assert_eq!(UI_ABS_SETUP, 0x401c5504);
+        assert_eq!(UI_SET_EVBIT, 0x40045564);
+    }
+}
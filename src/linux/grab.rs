@@ -1,7 +1,8 @@
 use crate::rdev::UnicodeInfo;
 // This code is awful. Good luck
+use crate::listen::ListenerBuilder;
 use crate::{Event, EventType, GrabError, Keyboard, KeyboardState, key_from_code};
-use crossbeam_channel::{Receiver, Sender, bounded, unbounded};
+use crossbeam_channel::{Receiver, Sender, unbounded};
 use mio::{Events, Interest, Poll, Token, unix::SourceFd};
 use parking_lot::Mutex;
 use std::{
@@ -9,37 +10,46 @@ use std::{
     os::raw::c_int,
     ptr,
     sync::{
-        Arc, LazyLock, OnceLock,
+        Arc, LazyLock,
         atomic::{AtomicBool, Ordering},
     },
     thread::{self, JoinHandle},
     time::{Duration, SystemTime},
 };
 use tracing::{debug, error};
-use x11::xlib::{self, GrabModeAsync, KeyPressMask, KeyReleaseMask, Window};
+use x11::xlib::{
+    self, ButtonPressMask, ButtonReleaseMask, GrabModeAsync, KeyPressMask, KeyReleaseMask,
+    PointerMotionMask, Window,
+};
 
-use super::common::KEYBOARD;
+use super::common::{KEYBOARD, convert_event as convert_pointer_event, modifiers_from_state};
+use super::injected::record_replay;
+use super::injected::take_extra_data;
+use super::raw::SyntheticDetector;
+use super::simulate::fake_event;
 
 enum GrabEvent {
     Exit,
-    KeyEvent(Event),
+    Event(Event),
 }
 
 enum GrabControl {
     Grab,
     UnGrab,
+    /// Sends an event the callback let through back to the server.
+    Replay(EventType),
     Exit,
 }
 
-struct KeyboardGrabber {
+struct InputGrabber {
     display: *mut xlib::Display,
     screen: *mut xlib::Screen,
     window: Window,
     grab_fd: c_int,
 }
 
-unsafe impl Send for KeyboardGrabber {}
-unsafe impl Sync for KeyboardGrabber {}
+unsafe impl Send for InputGrabber {}
+unsafe impl Sync for InputGrabber {}
 
 static GRAB_KEY_EVENT_SENDER: LazyLock<Mutex<Option<Sender<GrabEvent>>>> =
     LazyLock::new(|| Mutex::new(None));
@@ -50,14 +60,15 @@ static GRAB_CONTROL_SENDER: LazyLock<Mutex<Option<Sender<GrabControl>>>> =
 static THREAD_HANDLES: LazyLock<Mutex<Vec<JoinHandle<()>>>> =
     LazyLock::new(|| Mutex::new(Vec::new()));
 
-type GrabCallbackType = Mutex<Box<dyn FnMut(Event) -> Option<Event> + Send>>;
+type GrabCallbackType = Box<dyn FnMut(Event) -> Option<Event> + Send>;
 
-const KEYPRESS_EVENT: i32 = 2;
+const POINTER_EVENT_MASK: i64 = ButtonPressMask | ButtonReleaseMask | PointerMotionMask;
 static IS_GRABBING: AtomicBool = AtomicBool::new(false);
-static GLOBAL_CALLBACK: OnceLock<GrabCallbackType> = OnceLock::new();
+// Cleared when the grab ends, so that it can be started again.
+static GLOBAL_CALLBACK: Mutex<Option<GrabCallbackType>> = Mutex::new(None);
 const GRAB_RECV: Token = Token(0);
 
-impl KeyboardGrabber {
+impl InputGrabber {
     fn create() -> Result<Self, GrabError> {
         let mut grabber = Self {
             display: ptr::null_mut(),
@@ -84,7 +95,7 @@ impl KeyboardGrabber {
             xlib::XSelectInput(
                 grabber.display,
                 grabber.window,
-                KeyPressMask | KeyReleaseMask,
+                KeyPressMask | KeyReleaseMask | POINTER_EVENT_MASK,
             );
         }
 
@@ -109,10 +120,10 @@ impl KeyboardGrabber {
     }
 }
 
-impl Drop for KeyboardGrabber {
+impl Drop for InputGrabber {
     fn drop(&mut self) {
         if !self.display.is_null() {
-            ungrab_keys_(self.display);
+            ungrab_input_(self.display);
             let _ignore = unsafe { xlib::XCloseDisplay(self.display) };
         }
     }
@@ -132,7 +143,7 @@ fn is_control(unicode_info: &Option<UnicodeInfo>) -> bool {
     })
 }
 
-fn convert_event(code: u32, is_press: bool) -> Event {
+fn convert_key_event(code: u32, state: u32, is_press: bool, is_synthetic: bool) -> Event {
     let key = key_from_code(code);
     let event_type = if is_press {
         EventType::KeyPress(key)
@@ -162,49 +173,112 @@ fn convert_event(code: u32, is_press: bool) -> Event {
         position_code: code as _,
         usb_hid: 0,
         extra_data: 0,
-        // Linux does not have an API to detect synthetic events
-        is_synthetic: false,
+        is_synthetic,
+        modifiers: modifiers_from_state(state),
     }
 }
 
-fn grab_keys(display: Arc<Mutex<u64>>, grab_window: libc::c_ulong) {
+fn pointer_event(x_event: &xlib::XEvent, state: u32, is_synthetic: bool) -> Option<Event> {
+    let (code, x, y) = unsafe {
+        match x_event.type_ {
+            xlib::MotionNotify => (0, x_event.motion.x_root, x_event.motion.y_root),
+            _ => (
+                x_event.button.button,
+                x_event.button.x_root,
+                x_event.button.y_root,
+            ),
+        }
+    };
+    let event_type = convert_pointer_event(code as _, x_event.get_type(), x as f64, y as f64)?;
+    Some(Event {
+        event_type,
+        time: SystemTime::now(),
+        unicode: None,
+        platform_code: code,
+        position_code: code,
+        usb_hid: 0,
+        extra_data: 0,
+        is_synthetic,
+        modifiers: modifiers_from_state(state),
+    })
+}
+
+unsafe fn grab_input_(display: *mut xlib::Display, grab_window: Window) {
     unsafe {
-        // Minimize lock duration - don't hold during sleep
-        let display_ptr = {
-            let lock = display.lock();
-            let display_ptr = *lock as *mut xlib::Display;
-            xlib::XGrabKeyboard(
-                display_ptr,
-                grab_window,
-                c_int::from(true),
-                GrabModeAsync,
-                GrabModeAsync,
-                xlib::CurrentTime,
-            );
+        xlib::XGrabKeyboard(
+            display,
+            grab_window,
+            c_int::from(true),
+            GrabModeAsync,
+            GrabModeAsync,
+            xlib::CurrentTime,
+        );
+        xlib::XGrabPointer(
+            display,
+            grab_window,
+            c_int::from(true),
+            POINTER_EVENT_MASK as _,
+            GrabModeAsync,
+            GrabModeAsync,
+            0,
+            0,
+            xlib::CurrentTime,
+        );
+    }
+}
+
+fn grab_input(display: Arc<Mutex<u64>>, grab_window: Window) {
+    // Minimize lock duration - don't hold during sleep
+    {
+        let lock = display.lock();
+        let display_ptr = *lock as *mut xlib::Display;
+        unsafe {
+            grab_input_(display_ptr, grab_window);
             xlib::XFlush(display_ptr);
-            display_ptr
-        };
-        // Lock released here before sleep
-        thread::sleep(Duration::from_millis(50));
+        }
     }
+    // Lock released here before sleep
+    thread::sleep(Duration::from_millis(50));
+}
+
+fn ungrab_input(display: Arc<Mutex<u64>>) {
+    // Minimize lock duration - don't hold during sleep
+    {
+        let lock = display.lock();
+        let display = *lock as *mut xlib::Display;
+        ungrab_input_(display);
+    }
+    // Lock released here before sleep
+    thread::sleep(Duration::from_millis(50));
 }
 
-fn ungrab_keys(display: Arc<Mutex<u64>>) {
+fn ungrab_input_(display: *mut xlib::Display) {
     unsafe {
-        // Minimize lock duration - don't hold during sleep
-        {
-            let lock = display.lock();
-            let display = *lock as *mut xlib::Display;
-            ungrab_keys_(display);
-        }
-        // Lock released here before sleep
-        thread::sleep(Duration::from_millis(50));
+        xlib::XUngrabKeyboard(display, xlib::CurrentTime);
+        xlib::XUngrabPointer(display, xlib::CurrentTime);
+        xlib::XFlush(display);
     }
 }
 
-fn ungrab_keys_(display: *mut xlib::Display) {
+/// While grabbed, XTest events would be delivered back to us. The server
+/// handles the requests of a connection in order, so ungrabbing, faking the
+/// event and grabbing again on the same display lets it through. The replay
+/// is recorded as such, for listeners to report it as the user's input.
+fn replay_event(display: &Arc<Mutex<u64>>, grab_window: Window, event_type: &EventType) {
+    // The server moves the cursor whether the pointer is grabbed or not, so
+    // motion has already happened and cannot be blocked.
+    if let EventType::MouseMove { .. } = event_type {
+        return;
+    }
+    let lock = display.lock();
+    let display = *lock as *mut xlib::Display;
     unsafe {
         xlib::XUngrabKeyboard(display, xlib::CurrentTime);
+        xlib::XUngrabPointer(display, xlib::CurrentTime);
+        if fake_event(event_type, display, record_replay).is_none() {
+            debug!("Failed to replay grabbed event {:?}", event_type);
+        }
+        grab_input_(display, grab_window);
         xlib::XFlush(display);
     }
 }
@@ -213,10 +287,18 @@ fn start_callback_event_thread(recv: Receiver<GrabEvent>) {
     let handle = thread::spawn(move || {
         loop {
             match recv.recv() {
-                Ok(GrabEvent::KeyEvent(event)) => {
-                    if let Some(callback_mutex) = GLOBAL_CALLBACK.get() {
-                        let mut callback = callback_mutex.lock();
-                        callback(event);
+                Ok(GrabEvent::Event(event)) => {
+                    let event = match GLOBAL_CALLBACK.lock().as_mut() {
+                        Some(callback) => callback(event),
+                        None => continue,
+                    };
+                    // Not through send_grab_control, which sleeps after
+                    // every command.
+                    if let Some(event) = event
+                        && let Some(sender) = GRAB_CONTROL_SENDER.lock().as_ref()
+                        && let Err(e) = sender.send(GrabControl::Replay(event.event_type))
+                    {
+                        error!("Failed to send grab command, {e}");
                     }
                 }
                 Ok(GrabEvent::Exit) => {
@@ -228,6 +310,8 @@ fn start_callback_event_thread(recv: Receiver<GrabEvent>) {
                 }
             }
         }
+        // Here rather than in `stop`, which may be called from the callback.
+        *GLOBAL_CALLBACK.lock() = None;
     });
 
     // Store thread handle for cleanup
@@ -252,7 +336,11 @@ fn start_grab_service() -> Result<(), GrabError> {
     Ok(())
 }
 
-fn read_x_event(x_event: &mut xlib::XEvent, display: *mut xlib::Display) {
+fn read_x_event(
+    x_event: &mut xlib::XEvent,
+    display: *mut xlib::Display,
+    detector: &mut Option<SyntheticDetector>,
+) {
     while (unsafe { xlib::XPending(display) }) > 0 {
         unsafe {
             // to-do: check the result.
@@ -260,11 +348,39 @@ fn read_x_event(x_event: &mut xlib::XEvent, display: *mut xlib::Display) {
             // https://linux.die.net/man/3/xnextevent
             xlib::XNextEvent(display, x_event);
         }
-        let keycode = unsafe { x_event.key.keycode };
-        let is_press = unsafe { x_event.type_ == KEYPRESS_EVENT };
-        let event = convert_event(keycode, is_press);
-        if let Some(tx) = GRAB_KEY_EVENT_SENDER.lock().as_ref() {
-            let _ = tx.send(GrabEvent::KeyEvent(event));
+        let code = match x_event.get_type() {
+            xlib::KeyPress | xlib::KeyRelease => unsafe { x_event.key.keycode },
+            xlib::ButtonPress | xlib::ButtonRelease => unsafe { x_event.button.button },
+            _ => 0,
+        };
+        let state = match x_event.get_type() {
+            xlib::KeyPress | xlib::KeyRelease => unsafe { x_event.key.state },
+            xlib::ButtonPress | xlib::ButtonRelease => unsafe { x_event.button.state },
+            xlib::MotionNotify => unsafe { x_event.motion.state },
+            _ => 0,
+        };
+        let is_synthetic = detector
+            .as_mut()
+            .is_some_and(|detector| detector.is_xtest(x_event.get_type(), code as c_int));
+        let mut event = match x_event.get_type() {
+            xlib::KeyPress | xlib::KeyRelease => Some(convert_key_event(
+                code,
+                state,
+                x_event.get_type() == xlib::KeyPress,
+                is_synthetic,
+            )),
+            xlib::ButtonPress | xlib::ButtonRelease | xlib::MotionNotify => {
+                pointer_event(x_event, state, is_synthetic)
+            }
+            _ => None,
+        };
+        if is_synthetic && let Some(event) = event.as_mut() {
+            event.extra_data = take_extra_data(x_event.get_type(), code);
+        }
+        if let Some(event) = event
+            && let Some(tx) = GRAB_KEY_EVENT_SENDER.lock().as_ref()
+        {
+            let _ = tx.send(GrabEvent::Event(event));
         }
     }
 }
@@ -275,6 +391,8 @@ fn start_grab_control_thread(
     rx: Receiver<GrabControl>,
 ) {
     let handle = std::thread::spawn(move || {
+        // Without the grab, events already reached their destination.
+        let mut grabbed = false;
         loop {
             match rx.recv() {
                 Ok(evt) => match evt {
@@ -283,10 +401,17 @@ fn start_grab_control_thread(
                         break;
                     }
                     GrabControl::Grab => {
-                        grab_keys(display.clone(), grab_window);
+                        grab_input(display.clone(), grab_window);
+                        grabbed = true;
                     }
                     GrabControl::UnGrab => {
-                        ungrab_keys(display.clone());
+                        ungrab_input(display.clone());
+                        grabbed = false;
+                    }
+                    GrabControl::Replay(event_type) => {
+                        if grabbed {
+                            replay_event(&display, grab_window, &event_type);
+                        }
                     }
                 },
                 Err(e) => {
@@ -305,6 +430,8 @@ fn start_grab_control_thread(
 fn loop_poll_x_event(display: Arc<Mutex<u64>>, mut poll: Poll) {
     let mut x_event: xlib::XEvent = unsafe { zeroed() };
     let mut events = Events::with_capacity(128);
+    let mut detector =
+        SyntheticDetector::open(&ListenerBuilder::new().keys(true).buttons(true).motion(true));
     loop {
         if !IS_GRABBING.load(Ordering::SeqCst) {
             break;
@@ -314,12 +441,10 @@ fn loop_poll_x_event(display: Arc<Mutex<u64>>, mut poll: Poll) {
             Ok(_) => {
                 for event in &events {
                     if event.token() == GRAB_RECV {
-                        // Minimize lock duration
-                        let display_ptr = {
-                            let lock = display.lock();
-                            *lock as *mut xlib::Display
-                        };
-                        read_x_event(&mut x_event, display_ptr);
+                        // Xlib is not thread safe, the control thread
+                        // uses the same display.
+                        let lock = display.lock();
+                        read_x_event(&mut x_event, *lock as *mut xlib::Display, &mut detector);
                     }
                 }
             }
@@ -333,7 +458,7 @@ fn loop_poll_x_event(display: Arc<Mutex<u64>>, mut poll: Poll) {
 
 #[inline]
 fn start_grab() -> Result<(), GrabError> {
-    let grabber = KeyboardGrabber::create()?;
+    let grabber = InputGrabber::create()?;
     grabber.start()
 }
 
@@ -346,7 +471,7 @@ fn start_grab_thread() {
             }
 
             if let Err(err) = start_grab() {
-                debug!("Failed to start grab keyboard: {:?}", err);
+                debug!("Failed to start grab: {:?}", err);
                 if c <= 3 {
                     c += 1;
                     thread::sleep(Duration::from_millis(100));
@@ -379,14 +504,25 @@ fn send_grab_control(data: GrabControl) {
     thread::sleep(Duration::from_millis(50));
 }
 
+#[inline]
+pub(crate) fn set_grab(grab: bool) {
+    send_grab_control(if grab {
+        GrabControl::Grab
+    } else {
+        GrabControl::UnGrab
+    });
+}
+
+#[deprecated(note = "use `rdev::start_grab` and `GrabHandle::resume` instead")]
 #[inline]
 pub fn enable_grab() {
-    send_grab_control(GrabControl::Grab);
+    set_grab(true);
 }
 
+#[deprecated(note = "use `rdev::start_grab` and `GrabHandle::pause` instead")]
 #[inline]
 pub fn disable_grab() {
-    send_grab_control(GrabControl::UnGrab);
+    set_grab(false);
 }
 
 #[inline]
@@ -394,30 +530,37 @@ pub fn is_grabbed() -> bool {
     IS_GRABBING.load(Ordering::SeqCst)
 }
 
-pub fn start_grab_listen<T>(callback: T) -> Result<(), GrabError>
+/// Starts the grab service, without grabbing yet (see `set_grab`).
+/// While grabbed, returning `None` from the callback swallows the event,
+/// returning `Some(event)` sends it (possibly modified) through XTest.
+/// Both the keyboard and the pointer are grabbed. Pointer motion is reported
+/// but cannot be blocked: the X server moves the cursor regardless.
+pub(crate) fn start<T>(callback: T) -> Result<(), GrabError>
 where
     T: FnMut(Event) -> Option<Event> + Send + 'static,
 {
-    if is_grabbed() {
-        return Ok(());
+    {
+        let mut global_callback = GLOBAL_CALLBACK.lock();
+        if is_grabbed() || global_callback.is_some() {
+            error!(
+                "grab() called multiple times - this is not allowed. Only one grab can be active at a time."
+            );
+            return Err(GrabError::AlreadyGrabbing);
+        }
+        *global_callback = Some(Box::new(callback));
     }
-
     IS_GRABBING.store(true, Ordering::SeqCst);
 
-    // Initialize callback in OnceLock
-    if GLOBAL_CALLBACK.set(Mutex::new(Box::new(callback))).is_err() {
-        error!(
-            "start_grab_listen() called multiple times - this is not allowed. Only one grab can be active at a time."
-        );
-        return Err(GrabError::AlreadyGrabbing);
+    if let Err(err) = start_grab_service() {
+        stop();
+        *GLOBAL_CALLBACK.lock() = None;
+        return Err(err);
     }
-
-    start_grab_service()?;
     thread::sleep(Duration::from_millis(100));
     Ok(())
 }
 
-pub fn exit_grab_listen() {
+pub(crate) fn stop() {
     IS_GRABBING.store(false, Ordering::SeqCst);
 
     if let Some(tx) = GRAB_KEY_EVENT_SENDER.lock().as_ref() {
@@ -428,7 +571,49 @@ pub fn exit_grab_listen() {
     // Join all threads for proper cleanup
     let mut handles = THREAD_HANDLES.lock();
     for handle in handles.drain(..) {
+        // The callback thread cannot join itself, it exits on its own.
+        if handle.thread().id() == thread::current().id() {
+            continue;
+        }
         // Give threads a moment to exit gracefully
         let _ = handle.join();
     }
+    *GRAB_KEY_EVENT_SENDER.lock() = None;
+    *GRAB_CONTROL_SENDER.lock() = None;
+}
+
+#[deprecated(note = "use `rdev::start_grab` instead")]
+pub fn start_grab_listen<T>(callback: T) -> Result<(), GrabError>
+where
+    T: FnMut(Event) -> Option<Event> + Send + 'static,
+{
+    if is_grabbed() {
+        return Ok(());
+    }
+    start(callback)
+}
+
+#[deprecated(note = "use `GrabHandle::stop` or `rdev::exit_grab` instead")]
+pub fn exit_grab_listen() {
+    stop();
+}
+
+/// Grabs the keyboard and the pointer until `exit_grab` is called.
+#[cfg(not(feature = "unstable_grab"))]
+pub fn grab<T>(callback: T) -> Result<(), GrabError>
+where
+    T: FnMut(Event) -> Option<Event> + Send + 'static,
+{
+    start(callback)?;
+    set_grab(true);
+    while is_grabbed() {
+        thread::sleep(Duration::from_millis(50));
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "unstable_grab"))]
+pub fn exit_grab() -> Result<(), GrabError> {
+    stop();
+    Ok(())
 }
@@ -0,0 +1,218 @@
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, Instant};
+use x11::xlib;
+
+static MOUSE_EXTRA_INFO: AtomicI64 = AtomicI64::new(0);
+static KEYBOARD_EXTRA_INFO: AtomicI64 = AtomicI64::new(0);
+
+// XTest events carry nothing but the keycode or button, so the extra data
+// of the events this process injected waits here for the listener.
+static INJECTIONS: Mutex<VecDeque<Injection>> = Mutex::new(VecDeque::new());
+// Injections no listener saw, because none was running or the event was not
+// recorded, are forgotten after that.
+const MAX_AGE: Duration = Duration::from_secs(1);
+const MAX_INJECTIONS: usize = 1024;
+
+struct Injection {
+    type_: c_int,
+    detail: u32,
+    extra_data: i64,
+    time: Instant,
+}
+
+// The user's input the X11 grab let through, faked again through XTest.
+// XRecord sees the replay as hardware input, XInput2 already saw the
+// original raw event. Each listener takes its own share.
+static REPLAYS: Mutex<VecDeque<Replay>> = Mutex::new(VecDeque::new());
+
+struct Replay {
+    type_: c_int,
+    detail: u32,
+    time: Instant,
+    // Not taken yet by `take_replay` and `take_raw_replay`.
+    core: bool,
+    raw: bool,
+}
+
+/// The `extra_data` of the mouse events simulated from now on. Only the
+/// listeners of this process see it, as with the ones of other applications
+/// the events are only known to be synthetic.
+pub fn set_mouse_extra_info(extra: i64) {
+    MOUSE_EXTRA_INFO.store(extra, Ordering::Relaxed);
+}
+
+/// Same as [`set_mouse_extra_info`], for the keyboard events.
+pub fn set_keyboard_extra_info(extra: i64) {
+    KEYBOARD_EXTRA_INFO.store(extra, Ordering::Relaxed);
+}
+
+/// Called before faking a core event of this type, with its keycode or button.
+pub(crate) fn record_injection(type_: c_int, detail: u32) {
+    let extra_data = match type_ {
+        xlib::KeyPress | xlib::KeyRelease => KEYBOARD_EXTRA_INFO.load(Ordering::Relaxed),
+        _ => MOUSE_EXTRA_INFO.load(Ordering::Relaxed),
+    };
+    let mut injections = INJECTIONS.lock();
+    if injections.len() == MAX_INJECTIONS {
+        injections.pop_front();
+    }
+    injections.push_back(Injection {
+        type_,
+        detail,
+        extra_data,
+        time: Instant::now(),
+    });
+}
+
+/// The extra data of the oldest matching injection, for a synthetic event.
+pub(crate) fn take_extra_data(type_: c_int, detail: u32) -> i64 {
+    let mut injections = INJECTIONS.lock();
+    take(&mut injections, type_, detail, Instant::now())
+}
+
+fn take(injections: &mut VecDeque<Injection>, type_: c_int, detail: u32, now: Instant) -> i64 {
+    injections.retain(|injection| now.duration_since(injection.time) < MAX_AGE);
+    let detail = if type_ == xlib::MotionNotify {
+        0
+    } else {
+        detail
+    };
+    injections
+        .iter()
+        .position(|injection| injection.type_ == type_ && injection.detail == detail)
+        .and_then(|index| injections.remove(index))
+        .map_or(0, |injection| injection.extra_data)
+}
+
+/// Called before replaying a core event of this type, with its keycode or
+/// button.
+pub(crate) fn record_replay(type_: c_int, detail: u32) {
+    let mut replays = REPLAYS.lock();
+    if replays.len() == MAX_INJECTIONS {
+        replays.pop_front();
+    }
+    replays.push_back(Replay {
+        type_,
+        detail,
+        time: Instant::now(),
+        core: true,
+        raw: true,
+    });
+}
+
+/// Whether a synthetic core event is a replay of the user's input.
+pub(crate) fn take_replay(type_: c_int, detail: u32) -> bool {
+    take_share(
+        &mut REPLAYS.lock(),
+        type_,
+        detail,
+        Instant::now(),
+        |replay| &mut replay.core,
+    )
+}
+
+/// Same as [`take_replay`], for the raw event of the replay.
+pub(crate) fn take_raw_replay(type_: c_int, detail: u32) -> bool {
+    take_share(
+        &mut REPLAYS.lock(),
+        type_,
+        detail,
+        Instant::now(),
+        |replay| &mut replay.raw,
+    )
+}
+
+fn take_share(
+    replays: &mut VecDeque<Replay>,
+    type_: c_int,
+    detail: u32,
+    now: Instant,
+    share: fn(&mut Replay) -> &mut bool,
+) -> bool {
+    replays.retain(|replay| now.duration_since(replay.time) < MAX_AGE);
+    let Some(index) = replays
+        .iter_mut()
+        .position(|replay| replay.type_ == type_ && replay.detail == detail && *share(replay))
+    else {
+        return false;
+    };
+    *share(&mut replays[index]) = false;
+    if !replays[index].core && !replays[index].raw {
+        replays.remove(index);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn injection(type_: c_int, detail: u32, extra_data: i64, time: Instant) -> Injection {
+        Injection {
+            type_,
+            detail,
+            extra_data,
+            time,
+        }
+    }
+
+    #[test]
+    fn test_take() {
+        let now = Instant::now();
+        let mut injections = VecDeque::from([
+            injection(xlib::KeyPress, 39, 1, now),
+            injection(xlib::KeyPress, 39, 2, now),
+            injection(xlib::MotionNotify, 0, 3, now),
+        ]);
+        assert_eq!(take(&mut injections, xlib::KeyPress, 39, now), 1);
+        assert_eq!(take(&mut injections, xlib::MotionNotify, 120, now), 3);
+        assert_eq!(take(&mut injections, xlib::KeyRelease, 39, now), 0);
+        assert_eq!(take(&mut injections, xlib::KeyPress, 39, now), 2);
+        assert!(injections.is_empty());
+    }
+
+    #[test]
+    fn test_take_share() {
+        let now = Instant::now();
+        let replay = |detail| Replay {
+            type_: xlib::KeyPress,
+            detail,
+            time: now,
+            core: true,
+            raw: true,
+        };
+        let mut replays = VecDeque::from([replay(39), replay(39)]);
+        fn core(replay: &mut Replay) -> &mut bool {
+            &mut replay.core
+        }
+        fn raw(replay: &mut Replay) -> &mut bool {
+            &mut replay.raw
+        }
+        assert!(take_share(&mut replays, xlib::KeyPress, 39, now, core));
+        assert!(take_share(&mut replays, xlib::KeyPress, 39, now, core));
+        assert!(!take_share(&mut replays, xlib::KeyPress, 39, now, core));
+        assert!(!take_share(&mut replays, xlib::KeyRelease, 39, now, raw));
+        assert!(take_share(&mut replays, xlib::KeyPress, 39, now, raw));
+        assert_eq!(replays.len(), 1);
+        assert!(!take_share(
+            &mut replays,
+            xlib::KeyPress,
+            39,
+            now + MAX_AGE,
+            raw
+        ));
+        assert!(replays.is_empty());
+    }
+
+    #[test]
+    fn test_expired() {
+        let now = Instant::now();
+        let mut injections = VecDeque::from([injection(xlib::ButtonPress, 1, 7, now)]);
+        let later = now + MAX_AGE;
+        assert_eq!(take(&mut injections, xlib::ButtonPress, 1, later), 0);
+        assert!(injections.is_empty());
+    }
+}
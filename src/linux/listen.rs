@@ -1,7 +1,10 @@
 extern crate libc;
 extern crate x11;
 use crate::linux::common::{FALSE, KEYBOARD, convert};
+use crate::linux::injected::take_extra_data;
 use crate::linux::keyboard::Keyboard;
+use crate::linux::raw::{RawListener, SyntheticDetector};
+use crate::listen::ListenerBuilder;
 use crate::rdev::{Event, ListenError};
 use std::convert::TryInto;
 use std::ffi::CStr;
@@ -11,35 +14,84 @@ use x11::xlib;
 use x11::xrecord;
 
 use parking_lot::Mutex;
-use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tracing::error;
 
-type ListenCallbackType = Mutex<Box<dyn FnMut(Event) + Send>>;
+type ListenCallbackType = Box<dyn FnMut(Event) + Send>;
 
 static mut RECORD_ALL_CLIENTS: c_ulong = xrecord::XRecordAllClients;
-static GLOBAL_CALLBACK: OnceLock<ListenCallbackType> = OnceLock::new();
+// Cleared when listen returns, so that it can be called again.
+static GLOBAL_CALLBACK: Mutex<Option<ListenCallbackType>> = Mutex::new(None);
+// The context being recorded, disabled from another connection by `exit_listen`.
+// Only set once the server started recording: disabling a context before it
+// is enabled does nothing, and enabling it then blocks for good.
+static RECORD_CONTEXT: Mutex<Option<xrecord::XRecordContext>> = Mutex::new(None);
+// Tells XTest events apart, see `SyntheticDetector`.
+static SYNTHETIC: Mutex<Option<SyntheticDetector>> = Mutex::new(None);
+// In case `exit_listen` runs before the context is enabled. Only changed
+// with `RECORD_CONTEXT` locked, so that exactly one of them disables it.
+static EXIT_REQUESTED: AtomicBool = AtomicBool::new(false);
 
-pub fn listen<T>(callback: T) -> Result<(), ListenError>
+pub fn listen<T>(config: &ListenerBuilder, callback: T) -> Result<(), ListenError>
 where
     T: FnMut(Event) + Send + 'static,
 {
-    let keyboard = Keyboard::new().ok_or(ListenError::KeyboardError)?;
+    let keyboard = if config.unicode {
+        Some(Keyboard::new().ok_or(ListenError::KeyboardError)?)
+    } else {
+        None
+    };
 
-    // Initialize callback in OnceLock
-    if GLOBAL_CALLBACK.set(Mutex::new(Box::new(callback))).is_err() {
-        error!(
-            "listen() called multiple times - this is not allowed. Only one listener can be active at a time."
-        );
-        return Err(ListenError::AlreadyListening);
+    {
+        let mut global_callback = GLOBAL_CALLBACK.lock();
+        if global_callback.is_some() {
+            error!(
+                "listen() called multiple times - this is not allowed. Only one listener can be active at a time."
+            );
+            return Err(ListenError::AlreadyListening);
+        }
+        *global_callback = Some(Box::new(callback));
     }
+    EXIT_REQUESTED.store(false, Ordering::SeqCst);
+    // XRecord only sees the core events, the raw ones come from XInput2.
+    let raw_listener = if config.raw {
+        RawListener::start(config, emit)
+    } else {
+        None
+    };
+    *SYNTHETIC.lock() = SyntheticDetector::open(config);
 
-    unsafe {
-        KEYBOARD = Some(keyboard);
+    let result = unsafe {
+        KEYBOARD = keyboard;
         // Open displays
         let dpy_control = xlib::XOpenDisplay(null());
         if dpy_control.is_null() {
-            return Err(ListenError::MissingDisplayError);
+            Err(ListenError::MissingDisplayError)
+        } else {
+            let result = record(dpy_control, config);
+            xlib::XCloseDisplay(dpy_control);
+            result
         }
+    };
+    if let Some(raw_listener) = raw_listener {
+        raw_listener.stop();
+    }
+    *SYNTHETIC.lock() = None;
+    *GLOBAL_CALLBACK.lock() = None;
+    result
+}
+
+fn emit(event: Event) {
+    if let Some(callback) = GLOBAL_CALLBACK.lock().as_mut() {
+        callback(event);
+    }
+}
+
+unsafe fn record(
+    dpy_control: *mut xlib::Display,
+    config: &ListenerBuilder,
+) -> Result<(), ListenError> {
+    unsafe {
         let extension_name = CStr::from_bytes_with_nul(b"RECORD\0")
             .map_err(|_| ListenError::XRecordExtensionError)?;
         let extension = xlib::XInitExtension(dpy_control, extension_name.as_ptr());
@@ -47,14 +99,29 @@ where
             return Err(ListenError::XRecordExtensionError);
         }
 
-        // Prepare record range
-        let mut record_range: xrecord::XRecordRange = *xrecord::XRecordAllocRange();
-        record_range.device_events.first = xlib::KeyPress as c_uchar;
-        record_range.device_events.last = if crate::keyboard_only() {
-            xlib::KeyRelease
-        } else {
-            xlib::MotionNotify
-        } as c_uchar;
+        // Prepare record ranges, one per enabled event class. The wheel
+        // comes as buttons 4 to 7.
+        let mut ranges = Vec::new();
+        if config.keys {
+            ranges.push((xlib::KeyPress, xlib::KeyRelease));
+        }
+        if config.buttons || config.wheel {
+            ranges.push((xlib::ButtonPress, xlib::ButtonRelease));
+        }
+        if config.motion {
+            ranges.push((xlib::MotionNotify, xlib::MotionNotify));
+        }
+        let mut record_ranges = Vec::with_capacity(ranges.len());
+        for (first, last) in ranges {
+            let record_range = xrecord::XRecordAllocRange();
+            if record_range.is_null() {
+                free_ranges(&record_ranges);
+                return Err(ListenError::RecordContextError);
+            }
+            (*record_range).device_events.first = first as c_uchar;
+            (*record_range).device_events.last = last as c_uchar;
+            record_ranges.push(record_range);
+        }
 
         // Create context
         let context = xrecord::XRecordCreateContext(
@@ -62,19 +129,27 @@ where
             0,
             &raw mut RECORD_ALL_CLIENTS,
             1,
-            &mut &mut record_range as *mut &mut xrecord::XRecordRange
-                as *mut *mut xrecord::XRecordRange,
-            1,
+            record_ranges.as_mut_ptr(),
+            record_ranges.len() as c_int,
         );
+        free_ranges(&record_ranges);
 
         if context == 0 {
             return Err(ListenError::RecordContextError);
         }
 
         xlib::XSync(dpy_control, FALSE);
-        // Run
-        let result =
-            xrecord::XRecordEnableContext(dpy_control, context, Some(record_callback), &mut 0);
+        // Run, until the context is disabled. The callback publishes the
+        // context it is given once the recording starts.
+        let mut closure = context;
+        let result = xrecord::XRecordEnableContext(
+            dpy_control,
+            context,
+            Some(record_callback),
+            (&raw mut closure).cast(),
+        );
+        *RECORD_CONTEXT.lock() = None;
+        xrecord::XRecordFreeContext(dpy_control, context);
         if result == 0 {
             return Err(ListenError::RecordContextEnablingError);
         }
@@ -82,6 +157,43 @@ where
     Ok(())
 }
 
+unsafe fn free_ranges(ranges: &[*mut xrecord::XRecordRange]) {
+    for &range in ranges {
+        unsafe { xlib::XFree(range.cast()) };
+    }
+}
+
+#[inline]
+pub fn is_listening() -> bool {
+    RECORD_CONTEXT.lock().is_some()
+}
+
+/// Stops a running `listen`, which then returns `Ok(())`.
+pub fn exit_listen() -> Result<(), ListenError> {
+    let record_context = RECORD_CONTEXT.lock();
+    EXIT_REQUESTED.store(true, Ordering::SeqCst);
+    match *record_context {
+        // The record callback disables it when the recording starts.
+        None => Ok(()),
+        Some(context) => disable_context(context),
+    }
+}
+
+fn disable_context(context: xrecord::XRecordContext) -> Result<(), ListenError> {
+    unsafe {
+        // The recording connection is busy delivering the data, the
+        // context has to be disabled from another one.
+        let dpy = xlib::XOpenDisplay(null());
+        if dpy.is_null() {
+            return Err(ListenError::MissingDisplayError);
+        }
+        xrecord::XRecordDisableContext(dpy, context);
+        xlib::XSync(dpy, FALSE);
+        xlib::XCloseDisplay(dpy);
+    }
+    Ok(())
+}
+
 // No idea how to do that properly relevant doc lives here:
 // https://www.x.org/releases/X11R7.7/doc/libXtst/recordlib.html#Datum_Flags
 // https://docs.rs/xproto/1.1.5/xproto/struct._xEvent__bindgen_ty_1.html
@@ -103,7 +215,7 @@ struct XRecordDatum {
 }
 
 unsafe extern "C" fn record_callback(
-    _null: *mut c_char,
+    closure: *mut c_char,
     raw_data: *mut xrecord::XRecordInterceptData,
 ) {
     unsafe {
@@ -111,6 +223,17 @@ unsafe extern "C" fn record_callback(
             return;
         };
 
+        if data.category == xrecord::XRecordStartOfData {
+            let context = *closure.cast::<xrecord::XRecordContext>();
+            let mut record_context = RECORD_CONTEXT.lock();
+            *record_context = Some(context);
+            if EXIT_REQUESTED.load(Ordering::SeqCst) {
+                // Nothing to report from inside the recording.
+                let _ = disable_context(context);
+            }
+            xrecord::XRecordFreeData(raw_data);
+            return;
+        }
         if data.category != xrecord::XRecordFromServer {
             return;
         }
@@ -124,16 +247,29 @@ unsafe extern "C" fn record_callback(
 
         let code: c_uint = xdatum.code.into();
         let type_: c_int = xdatum.type_.into();
-        // let state = xdatum.state;
+        let state: c_uint = xdatum.state.into();
 
         let x = xdatum.root_x as f64;
         let y = xdatum.root_y as f64;
 
-        if let Some(event) = convert(&mut *(&raw mut KEYBOARD), code, type_, x, y) {
-            if let Some(callback_mutex) = GLOBAL_CALLBACK.get() {
-                let mut callback = callback_mutex.lock();
-                callback(event);
+        let is_synthetic = SYNTHETIC
+            .lock()
+            .as_mut()
+            .is_some_and(|detector| detector.is_synthetic(type_, code as c_int));
+
+        if let Some(mut event) = convert(
+            &mut *(&raw mut KEYBOARD),
+            code,
+            state,
+            type_,
+            x,
+            y,
+            is_synthetic,
+        ) {
+            if is_synthetic {
+                event.extra_data = take_extra_data(type_, code);
             }
+            emit(event);
         }
         xrecord::XRecordFreeData(raw_data);
     }
@@ -3,15 +3,45 @@ extern crate x11;
 
 mod common;
 mod display;
+mod evdev;
 mod grab;
+mod injected;
 mod keyboard;
 mod listen;
+mod raw;
 mod simulate;
+mod xkb;
 
 pub use crate::linux::display::display_size;
-pub use crate::linux::grab::{
-    disable_grab, enable_grab, exit_grab_listen, is_grabbed, start_grab_listen,
+#[cfg(feature = "unstable_wayland")]
+pub use crate::linux::evdev::{VirtualDevice, simulate as simulate_uinput};
+#[cfg(feature = "unstable_grab")]
+pub use crate::linux::evdev::{exit_grab, grab, is_grabbed};
+pub(crate) use crate::linux::evdev::{
+    exit_listen as exit_listen_evdev, is_listening as is_listening_evdev, listen as listen_evdev,
 };
+#[allow(deprecated)]
+pub use crate::linux::grab::{disable_grab, enable_grab, exit_grab_listen, start_grab_listen};
+#[cfg(not(feature = "unstable_grab"))]
+pub use crate::linux::grab::{exit_grab, grab, is_grabbed};
+#[cfg(not(feature = "unstable_grab"))]
+pub(crate) use crate::linux::grab::{set_grab, start as start_grab};
+pub use crate::linux::injected::{set_keyboard_extra_info, set_mouse_extra_info};
 pub use crate::linux::keyboard::Keyboard;
-pub use crate::linux::listen::listen;
-pub use crate::linux::simulate::{simulate, simulate_char, simulate_unicode};
+pub(crate) use crate::linux::listen::{
+    exit_listen as exit_listen_x11, is_listening as is_listening_x11, listen,
+};
+pub use crate::linux::simulate::{Simulator, simulate, simulate_char, simulate_unicode};
+pub(crate) use crate::linux::simulate::{type_layout, type_unicode};
+pub use crate::linux::xkb::XkbKeyboard;
+
+/// Whether a listener is running, with either backend.
+pub fn is_listening() -> bool {
+    is_listening_x11() || is_listening_evdev()
+}
+
+/// Stops the running `listen` or `listen_evdev`, which then returns `Ok(())`.
+pub fn exit_listen() -> Result<(), crate::rdev::ListenError> {
+    exit_listen_x11()?;
+    exit_listen_evdev()
+}
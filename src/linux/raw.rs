@@ -0,0 +1,509 @@
+use crate::keycodes::linux::key_from_code;
+use crate::linux::injected::{take_raw_replay, take_replay};
+use crate::linux::xkb::x11_modifiers;
+use crate::listen::ListenerBuilder;
+use crate::rdev::{Button, Event, EventType, Modifiers};
+use mio::{Events, Interest, Poll, Token, Waker, unix::SourceFd};
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use std::mem::MaybeUninit;
+use std::os::raw::{c_int, c_uchar};
+use std::ptr::null;
+use std::slice;
+use std::thread::{self, JoinHandle};
+use std::time::SystemTime;
+use tracing::{debug, warn};
+use x11::{xinput2, xlib};
+
+const DISPLAY: Token = Token(0);
+const WAKE: Token = Token(1);
+// Raw events without a core event, such as motion at the edge of the screen,
+// are never matched.
+const MAX_PENDING: usize = 256;
+
+/// Reads the XInput2 raw events, which XRecord does not see, on a connection
+/// and thread of its own.
+pub(crate) struct RawListener {
+    waker: Waker,
+    thread: JoinHandle<()>,
+}
+
+impl RawListener {
+    /// Returns `None` when the X server does not support XInput2, the
+    /// listener then goes on without raw events.
+    pub(crate) fn start(config: &ListenerBuilder, emit: fn(Event)) -> Option<RawListener> {
+        let connection = unsafe { Connection::open(config) }?;
+        let waker = Waker::new(connection.poll.registry(), WAKE).ok()?;
+        let thread = thread::spawn(move || connection.run(emit));
+        Some(RawListener { waker, thread })
+    }
+
+    pub(crate) fn stop(self) {
+        if self.waker.wake().is_ok() {
+            let _ = self.thread.join();
+        }
+    }
+}
+
+/// A connection receiving the XInput2 raw events of the master devices.
+pub(crate) struct XiDisplay {
+    display: *mut xlib::Display,
+    // The major opcode of the XInput extension.
+    opcode: c_int,
+    // The slave devices XTest events come from.
+    xtest_devices: Vec<c_int>,
+}
+
+// The display is only used by the thread the connection is moved to.
+unsafe impl Send for XiDisplay {}
+
+impl Drop for XiDisplay {
+    fn drop(&mut self) {
+        unsafe { xlib::XCloseDisplay(self.display) };
+    }
+}
+
+impl XiDisplay {
+    /// Selects the raw events of the event classes `config` accepts.
+    unsafe fn open(config: &ListenerBuilder) -> Option<XiDisplay> {
+        unsafe {
+            let display = xlib::XOpenDisplay(null());
+            if display.is_null() {
+                return None;
+            }
+            let mut xi = XiDisplay {
+                display,
+                opcode: 0,
+                xtest_devices: Vec::new(),
+            };
+            let (mut event, mut error) = (0, 0);
+            if xlib::XQueryExtension(
+                display,
+                c"XInputExtension".as_ptr(),
+                &mut xi.opcode,
+                &mut event,
+                &mut error,
+            ) == 0
+            {
+                warn!("The X server has no XInput extension, raw events are not available");
+                return None;
+            }
+            // From 2.2 on, raw events are delivered even while another client
+            // has a grab, the server answers with the version it supports.
+            let (mut major, mut minor) = (2, 2);
+            if xinput2::XIQueryVersion(display, &mut major, &mut minor) != xlib::Success as c_int {
+                warn!("The X server does not support XInput2, raw events are not available");
+                return None;
+            }
+            if (major, minor) < (2, 2) {
+                warn!("XInput {major}.{minor} holds back raw events during grabs");
+            }
+            xi.xtest_devices = xtest_devices(display);
+
+            let mut mask = [0 as c_uchar; (xinput2::XI_LASTEVENT >> 3) as usize + 1];
+            if config.keys {
+                xinput2::XISetMask(&mut mask, xinput2::XI_RawKeyPress);
+                xinput2::XISetMask(&mut mask, xinput2::XI_RawKeyRelease);
+            }
+            // The wheel comes as buttons 4 to 7.
+            if config.buttons || config.wheel {
+                xinput2::XISetMask(&mut mask, xinput2::XI_RawButtonPress);
+                xinput2::XISetMask(&mut mask, xinput2::XI_RawButtonRelease);
+            }
+            if config.motion {
+                xinput2::XISetMask(&mut mask, xinput2::XI_RawMotion);
+            }
+            // Master devices only, every physical event is also reported
+            // by its slave device.
+            let mut event_mask = xinput2::XIEventMask {
+                deviceid: xinput2::XIAllMasterDevices,
+                mask_len: mask.len() as c_int,
+                mask: mask.as_mut_ptr(),
+            };
+            xinput2::XISelectEvents(
+                display,
+                xlib::XDefaultRootWindow(display),
+                &mut event_mask,
+                1,
+            );
+            xlib::XFlush(display);
+            Some(xi)
+        }
+    }
+
+    /// Passes the raw events already received to `handle`.
+    fn read(&self, mut handle: impl FnMut(c_int, &xinput2::XIRawEvent, bool)) {
+        // Xlib may already have queued events while waiting for a reply.
+        while unsafe { xlib::XPending(self.display) } > 0 {
+            let mut xevent = MaybeUninit::<xlib::XEvent>::uninit();
+            unsafe { xlib::XNextEvent(self.display, xevent.as_mut_ptr()) };
+            let mut xevent = unsafe { xevent.assume_init() };
+            let cookie = unsafe { &mut xevent.generic_event_cookie };
+            if cookie.type_ != xlib::GenericEvent || cookie.extension != self.opcode {
+                continue;
+            }
+            if unsafe { xlib::XGetEventData(self.display, cookie) } == 0 {
+                continue;
+            }
+            let raw = unsafe { &*(cookie.data as *const xinput2::XIRawEvent) };
+            handle(
+                cookie.evtype,
+                raw,
+                self.xtest_devices.contains(&raw.sourceid),
+            );
+            unsafe { xlib::XFreeEventData(self.display, cookie) };
+        }
+    }
+}
+
+/// The ids of the "Virtual core XTEST keyboard/pointer" devices.
+unsafe fn xtest_devices(display: *mut xlib::Display) -> Vec<c_int> {
+    unsafe {
+        let mut count = 0;
+        let devices = xinput2::XIQueryDevice(display, xinput2::XIAllDevices, &mut count);
+        if devices.is_null() {
+            return Vec::new();
+        }
+        let xtest_devices = slice::from_raw_parts(devices, count.max(0) as usize)
+            .iter()
+            .filter(|device| {
+                !device.name.is_null()
+                    && CStr::from_ptr(device.name)
+                        .to_bytes()
+                        .windows(5)
+                        .any(|word| word == b"XTEST")
+            })
+            .map(|device| device.deviceid)
+            .collect();
+        xinput2::XIFreeDeviceInfo(devices);
+        xtest_devices
+    }
+}
+
+struct Connection {
+    xi: XiDisplay,
+    poll: Poll,
+}
+
+impl Connection {
+    unsafe fn open(config: &ListenerBuilder) -> Option<Connection> {
+        let xi = unsafe { XiDisplay::open(config) }?;
+        let poll = Poll::new().ok()?;
+        poll.registry()
+            .register(
+                &mut SourceFd(&unsafe { xlib::XConnectionNumber(xi.display) }),
+                DISPLAY,
+                Interest::READABLE,
+            )
+            .ok()?;
+        Some(Connection { xi, poll })
+    }
+
+    fn run(mut self, emit: fn(Event)) {
+        let mut events = Events::with_capacity(4);
+        let mut motion = Motion::default();
+        let mut modifiers = Modifiers::empty();
+        let display = self.xi.display;
+        loop {
+            self.xi.read(|evtype, raw, is_synthetic| {
+                // The original of a grab replay was already reported.
+                if is_synthetic
+                    && core_type(evtype)
+                        .is_some_and(|type_| take_raw_replay(type_, raw.detail as u32))
+                {
+                    return;
+                }
+                // Motion keeps the modifiers of the last key or button, to
+                // save a round trip per event.
+                if evtype != xinput2::XI_RawMotion {
+                    modifiers = unsafe { x11_modifiers(display) };
+                }
+                if let Some(event) =
+                    unsafe { convert(evtype, raw, is_synthetic, modifiers, &mut motion) }
+                {
+                    emit(event);
+                }
+            });
+            match self.poll.poll(&mut events, None) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    debug!("Raw events stopped: {}", e);
+                    return;
+                }
+            }
+            if events.iter().any(|event| event.token() == WAKE) {
+                return;
+            }
+        }
+    }
+}
+
+unsafe fn convert(
+    evtype: c_int,
+    raw: &xinput2::XIRawEvent,
+    is_synthetic: bool,
+    modifiers: Modifiers,
+    motion: &mut Motion,
+) -> Option<Event> {
+    let event_type = unsafe { convert_raw(evtype, raw, motion) }?;
+    let code = match event_type {
+        EventType::KeyPressRaw(_) | EventType::KeyReleaseRaw(_) => raw.detail as u32,
+        _ => 0,
+    };
+    // The state was read after the event, take its own key out of it.
+    let modifiers = match event_type {
+        EventType::KeyPressRaw(key) => modifiers - Modifiers::from_key(key),
+        EventType::KeyReleaseRaw(key) => modifiers | Modifiers::from_key(key),
+        _ => modifiers,
+    };
+    Some(Event {
+        event_type,
+        time: SystemTime::now(),
+        unicode: None,
+        platform_code: code,
+        position_code: code,
+        usb_hid: 0,
+        extra_data: 0,
+        is_synthetic,
+        modifiers,
+    })
+}
+
+/// Whether the core events XRecord reports come from XTest, which only the
+/// `sourceid` of the matching raw events tells.
+pub(crate) struct SyntheticDetector {
+    xi: XiDisplay,
+    // The raw events not matched yet: evtype, detail and whether they are
+    // synthetic.
+    pending: VecDeque<(c_int, c_int, bool)>,
+}
+
+impl SyntheticDetector {
+    pub(crate) fn open(config: &ListenerBuilder) -> Option<SyntheticDetector> {
+        let xi = unsafe { XiDisplay::open(config) }?;
+        Some(SyntheticDetector {
+            xi,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Takes a core event type and its keycode or button. The events the X11
+    /// grab replays are the user's input, even though XTest sends them.
+    pub(crate) fn is_synthetic(&mut self, type_: c_int, code: c_int) -> bool {
+        self.is_xtest(type_, code) && !take_replay(type_, code as u32)
+    }
+
+    /// Whether the event comes from XTest, replays included.
+    pub(crate) fn is_xtest(&mut self, type_: c_int, code: c_int) -> bool {
+        let evtype = match type_ {
+            xlib::KeyPress => xinput2::XI_RawKeyPress,
+            xlib::KeyRelease => xinput2::XI_RawKeyRelease,
+            xlib::ButtonPress => xinput2::XI_RawButtonPress,
+            xlib::ButtonRelease => xinput2::XI_RawButtonRelease,
+            xlib::MotionNotify => xinput2::XI_RawMotion,
+            _ => return false,
+        };
+        let detail = if evtype == xinput2::XI_RawMotion {
+            0
+        } else {
+            code
+        };
+        self.receive();
+        if let Some(is_synthetic) = take_matching(&mut self.pending, evtype, detail) {
+            return is_synthetic;
+        }
+        // The raw event is generated along with the core one: after a round
+        // trip, it has been received.
+        unsafe { xlib::XSync(self.xi.display, xlib::False) };
+        self.receive();
+        take_matching(&mut self.pending, evtype, detail).unwrap_or(false)
+    }
+
+    fn receive(&mut self) {
+        let pending = &mut self.pending;
+        self.xi.read(|evtype, raw, is_synthetic| {
+            if pending.len() == MAX_PENDING {
+                pending.pop_front();
+            }
+            pending.push_back((evtype, raw.detail, is_synthetic));
+        });
+    }
+}
+
+/// The core event type of a raw event type.
+fn core_type(evtype: c_int) -> Option<c_int> {
+    match evtype {
+        xinput2::XI_RawKeyPress => Some(xlib::KeyPress),
+        xinput2::XI_RawKeyRelease => Some(xlib::KeyRelease),
+        xinput2::XI_RawButtonPress => Some(xlib::ButtonPress),
+        xinput2::XI_RawButtonRelease => Some(xlib::ButtonRelease),
+        _ => None,
+    }
+}
+
+/// Removes the first raw event matching, along with the older ones which
+/// had no core event.
+fn take_matching(
+    pending: &mut VecDeque<(c_int, c_int, bool)>,
+    evtype: c_int,
+    detail: c_int,
+) -> Option<bool> {
+    let index = pending
+        .iter()
+        .position(|(pending_evtype, pending_detail, _)| {
+            *pending_evtype == evtype && *pending_detail == detail
+        })?;
+    let (_, _, is_synthetic) = pending.drain(..=index).next_back()?;
+    Some(is_synthetic)
+}
+
+/// What is left of the motion after rounding to whole units, so that slow
+/// movements still add up.
+#[derive(Default)]
+struct Motion {
+    x: f64,
+    y: f64,
+}
+
+impl Motion {
+    fn add(&mut self, dx: f64, dy: f64) -> Option<EventType> {
+        self.x += dx;
+        self.y += dy;
+        let (delta_x, delta_y) = (self.x.trunc(), self.y.trunc());
+        self.x -= delta_x;
+        self.y -= delta_y;
+        if delta_x == 0.0 && delta_y == 0.0 {
+            return None;
+        }
+        Some(EventType::MouseMoveRaw {
+            delta_x: delta_x as i32,
+            delta_y: delta_y as i32,
+        })
+    }
+}
+
+unsafe fn convert_raw(
+    evtype: c_int,
+    raw: &xinput2::XIRawEvent,
+    motion: &mut Motion,
+) -> Option<EventType> {
+    match evtype {
+        xinput2::XI_RawKeyPress => Some(EventType::KeyPressRaw(key_from_code(raw.detail as u32))),
+        xinput2::XI_RawKeyRelease => {
+            Some(EventType::KeyReleaseRaw(key_from_code(raw.detail as u32)))
+        }
+        xinput2::XI_RawButtonPress => match raw.detail {
+            1 => Some(EventType::ButtonPressRaw(Button::Left)),
+            2 => Some(EventType::ButtonPressRaw(Button::Middle)),
+            3 => Some(EventType::ButtonPressRaw(Button::Right)),
+            4 => Some(wheel(0.0, 1.0)),
+            5 => Some(wheel(0.0, -1.0)),
+            6 => Some(wheel(-1.0, 0.0)),
+            7 => Some(wheel(1.0, 0.0)),
+            8 => Some(EventType::ButtonPressRaw(Button::Back)),
+            9 => Some(EventType::ButtonPressRaw(Button::Forward)),
+            code => Some(EventType::ButtonPressRaw(Button::Unknown(code as u8))),
+        },
+        xinput2::XI_RawButtonRelease => match raw.detail {
+            1 => Some(EventType::ButtonReleaseRaw(Button::Left)),
+            2 => Some(EventType::ButtonReleaseRaw(Button::Middle)),
+            3 => Some(EventType::ButtonReleaseRaw(Button::Right)),
+            4..=7 => None,
+            8 => Some(EventType::ButtonReleaseRaw(Button::Back)),
+            9 => Some(EventType::ButtonReleaseRaw(Button::Forward)),
+            code => Some(EventType::ButtonReleaseRaw(Button::Unknown(code as u8))),
+        },
+        xinput2::XI_RawMotion => {
+            let (dx, dy) = unsafe { raw_deltas(&raw.valuators, raw.raw_values) };
+            motion.add(dx, dy)
+        }
+        _ => None,
+    }
+}
+
+fn wheel(delta_x: f64, delta_y: f64) -> EventType {
+    EventType::WheelRaw { delta_x, delta_y }
+}
+
+/// The unaccelerated values of valuators 0 and 1, the relative x and y
+/// motion. `values` only holds the valuators set in the mask, in order.
+unsafe fn raw_deltas(valuators: &xinput2::XIValuatorState, values: *const f64) -> (f64, f64) {
+    if valuators.mask.is_null() || values.is_null() {
+        return (0.0, 0.0);
+    }
+    let mask = unsafe { slice::from_raw_parts(valuators.mask, valuators.mask_len as usize) };
+    let mut deltas = [0.0; 2];
+    let mut index = 0;
+    for (valuator, delta) in deltas.iter_mut().enumerate() {
+        if (valuator >> 3) >= mask.len() {
+            break;
+        }
+        if xinput2::XIMaskIsSet(mask, valuator as i32) {
+            *delta = unsafe { *values.add(index) };
+            index += 1;
+        }
+    }
+    (deltas[0], deltas[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_matching() {
+        let mut pending = VecDeque::from([
+            (xinput2::XI_RawMotion, 0, false),
+            (xinput2::XI_RawKeyPress, 38, true),
+            (xinput2::XI_RawKeyPress, 39, false),
+        ]);
+        assert_eq!(
+            take_matching(&mut pending, xinput2::XI_RawKeyPress, 38),
+            Some(true)
+        );
+        // The motion before it is gone.
+        assert_eq!(take_matching(&mut pending, xinput2::XI_RawMotion, 0), None);
+        assert_eq!(
+            take_matching(&mut pending, xinput2::XI_RawKeyPress, 39),
+            Some(false)
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_motion_remainder() {
+        let mut motion = Motion::default();
+        assert_eq!(motion.add(0.4, -0.6), None);
+        assert_eq!(
+            motion.add(0.7, -0.6),
+            Some(EventType::MouseMoveRaw {
+                delta_x: 1,
+                delta_y: -1
+            })
+        );
+        assert_eq!(
+            motion.add(2.0, 0.0),
+            Some(EventType::MouseMoveRaw {
+                delta_x: 2,
+                delta_y: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_raw_deltas() {
+        // Valuators 1 and 3 set, only the y delta is relevant.
+        let mut mask = [0b1010_u8];
+        let values = [-3.5, 7.0];
+        let valuators = xinput2::XIValuatorState {
+            mask_len: 1,
+            mask: mask.as_mut_ptr(),
+            values: std::ptr::null_mut(),
+        };
+        assert_eq!(
+            unsafe { raw_deltas(&valuators, values.as_ptr()) },
+            (0.0, -3.5)
+        );
+    }
+}
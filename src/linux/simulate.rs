@@ -1,57 +1,67 @@
 use crate::keycodes::linux::code_from_key;
 use crate::linux::common::{FALSE, TRUE};
-use crate::rdev::{Button, EventType, RawKey, SimulateError};
-use std::convert::TryInto;
+use crate::linux::injected::record_injection;
+use crate::linux::xkb::{XkbKeyboard, x11_layout, x11_rules_names};
+use crate::rdev::{Button, EventType, Key, RawKey, SimulateError};
+use parking_lot::Mutex;
 use std::os::raw::c_int;
 use std::ptr::null;
+use std::slice;
+use std::thread;
+use std::time::Duration;
 use x11::xlib;
 use x11::xtest;
 
-unsafe fn send_native(event_type: &EventType, display: *mut xlib::Display) -> Option<()> {
+// The keyboard of the active layout, rebuilt when the layout changes.
+static ACTIVE_KEYBOARD: Mutex<Option<(Vec<u8>, XkbKeyboard)>> = Mutex::new(None);
+
+// An unused keycode (F24) remapped to the characters no key produces.
+const SPARE_KEYCODE: u32 = 194;
+// The keysyms of SPARE_KEYCODE before it was remapped.
+static SPARE_KEYSYMS: Mutex<Option<Vec<xlib::KeySym>>> = Mutex::new(None);
+const RESTORE_DELAY: Duration = Duration::from_millis(10);
+// The high surrogate given to `simulate_unicode`, waiting for the low one.
+static PENDING_SURROGATE: Mutex<Option<u16>> = Mutex::new(None);
+
+// Keeps an absurd delta from flooding the X server.
+const MAX_WHEEL_CLICKS: usize = 100;
+
+pub(crate) unsafe fn send_native(
+    event_type: &EventType,
+    display: *mut xlib::Display,
+) -> Option<()> {
+    unsafe { fake_event(event_type, display, record_injection) }
+}
+
+/// Fakes the event through XTest, telling `record` the core events it gives
+/// before sending them.
+pub(crate) unsafe fn fake_event(
+    event_type: &EventType,
+    display: *mut xlib::Display,
+    record: fn(c_int, u32),
+) -> Option<()> {
     unsafe {
         let res = match event_type {
-            EventType::KeyPress(key) => match key {
-                crate::Key::RawKey(rawkey) => {
-                    if let RawKey::LinuxXorgKeycode(keycode) = rawkey {
-                        xtest::XTestFakeKeyEvent(display, *keycode as _, TRUE, 0)
-                    } else {
-                        return None;
-                    }
-                }
-                _ => {
-                    let code = code_from_key(*key)?;
-                    xtest::XTestFakeKeyEvent(display, code, TRUE, 0)
-                }
-            },
-            EventType::KeyRelease(key) => match key {
-                crate::Key::RawKey(rawkey) => {
-                    if let RawKey::LinuxXorgKeycode(keycode) = rawkey {
-                        xtest::XTestFakeKeyEvent(display, *keycode as _, FALSE, 0)
-                    } else {
-                        return None;
-                    }
-                }
-                _ => {
-                    let code = code_from_key(*key)?;
-                    xtest::XTestFakeKeyEvent(display, code, FALSE, 0)
-                }
-            },
-            EventType::ButtonPress(button) => match button {
-                Button::Left => xtest::XTestFakeButtonEvent(display, 1, TRUE, 0),
-                Button::Middle => xtest::XTestFakeButtonEvent(display, 2, TRUE, 0),
-                Button::Right => xtest::XTestFakeButtonEvent(display, 3, TRUE, 0),
-                Button::Unknown(code) => {
-                    xtest::XTestFakeButtonEvent(display, (*code).try_into().ok()?, TRUE, 0)
-                }
-            },
-            EventType::ButtonRelease(button) => match button {
-                Button::Left => xtest::XTestFakeButtonEvent(display, 1, FALSE, 0),
-                Button::Middle => xtest::XTestFakeButtonEvent(display, 2, FALSE, 0),
-                Button::Right => xtest::XTestFakeButtonEvent(display, 3, FALSE, 0),
-                Button::Unknown(code) => {
-                    xtest::XTestFakeButtonEvent(display, (*code).try_into().ok()?, FALSE, 0)
-                }
-            },
+            EventType::KeyPress(key) => {
+                let code = keycode(key)?;
+                record(xlib::KeyPress, code);
+                xtest::XTestFakeKeyEvent(display, code, TRUE, 0)
+            }
+            EventType::KeyRelease(key) => {
+                let code = keycode(key)?;
+                record(xlib::KeyRelease, code);
+                xtest::XTestFakeKeyEvent(display, code, FALSE, 0)
+            }
+            EventType::ButtonPress(button) => {
+                let code = button_code(*button);
+                record(xlib::ButtonPress, code);
+                xtest::XTestFakeButtonEvent(display, code, TRUE, 0)
+            }
+            EventType::ButtonRelease(button) => {
+                let code = button_code(*button);
+                record(xlib::ButtonRelease, code);
+                xtest::XTestFakeButtonEvent(display, code, FALSE, 0)
+            }
             EventType::MouseMove { x, y } => {
                 //TODO: replace with clamp if it is stabalized
                 let x = if x.is_finite() {
@@ -68,13 +78,26 @@ unsafe fn send_native(event_type: &EventType, display: *mut xlib::Display) -> Op
                 } else {
                     0
                 };
+                record(xlib::MotionNotify, 0);
                 xtest::XTestFakeMotionEvent(display, 0, x, y, 0)
                 //     xlib::XWarpPointer(display, 0, root, 0, 0, 0, 0, *x as i32, *y as i32);
             }
-            EventType::Wheel { delta_y, .. } => {
-                let code = if *delta_y > 0.0 { 4 } else { 5 };
-                xtest::XTestFakeButtonEvent(display, code, TRUE, 0)
-                    & xtest::XTestFakeButtonEvent(display, code, FALSE, 0)
+            EventType::Wheel { delta_x, delta_y } => {
+                // One click of buttons 4 to 7 per unit of delta.
+                let clicks = [(*delta_y, 4, 5), (*delta_x, 7, 6)].into_iter().flat_map(
+                    |(delta, positive, negative)| {
+                        let code = if delta > 0.0 { positive } else { negative };
+                        std::iter::repeat_n(code, wheel_clicks(delta))
+                    },
+                );
+                let mut res = 1;
+                for code in clicks {
+                    // The releases of buttons 4 to 7 give no event.
+                    record(xlib::ButtonPress, code);
+                    res &= xtest::XTestFakeButtonEvent(display, code, TRUE, 0)
+                        & xtest::XTestFakeButtonEvent(display, code, FALSE, 0);
+                }
+                res
             }
             // Raw events are for capture only, not simulation
             EventType::MouseMoveRaw { .. }
@@ -88,74 +111,315 @@ unsafe fn send_native(event_type: &EventType, display: *mut xlib::Display) -> Op
     }
 }
 
-pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
-    unsafe {
-        let dpy = xlib::XOpenDisplay(null());
-        if dpy.is_null() {
-            return Err(SimulateError);
-        }
-        match send_native(event_type, dpy) {
-            Some(_) => {
-                xlib::XFlush(dpy);
-                xlib::XSync(dpy, 0);
-                xlib::XCloseDisplay(dpy);
-                Ok(())
-            }
-            None => {
-                xlib::XCloseDisplay(dpy);
-                Err(SimulateError)
-            }
-        }
+fn keycode(key: &Key) -> Option<u32> {
+    match key {
+        Key::RawKey(RawKey::LinuxXorgKeycode(keycode)) => Some(*keycode),
+        Key::RawKey(_) => None,
+        _ => code_from_key(*key),
     }
 }
 
-unsafe fn send_native_char(chr: char, pressed: bool, display: *mut xlib::Display) -> Option<()> {
-    unsafe {
-        // unuse keycode: F24 -> 194
-        let keycode: u32 = 194;
+fn button_code(button: Button) -> u32 {
+    match button {
+        Button::Left => 1,
+        Button::Middle => 2,
+        Button::Right => 3,
+        Button::Back => 8,
+        Button::Forward => 9,
+        Button::Unknown(code) => code.into(),
+    }
+}
 
-        // char to keysym
-        let ordinal: u32 = chr.into();
-        let mut keysym = if ordinal < 0x100 {
-            ordinal
-        } else {
-            ordinal | 0x01000000
-        } as libc::c_ulong;
+/// The number of wheel clicks for a delta, at least one unless it is zero.
+fn wheel_clicks(delta: f64) -> usize {
+    if !delta.is_finite() || delta == 0.0 {
+        return 0;
+    }
+    (delta.abs().round() as usize).clamp(1, MAX_WHEEL_CLICKS)
+}
 
-        // remap keycode to keysym
-        x11::xlib::XChangeKeyboardMapping(display, keycode as _, 1, &mut keysym, 1);
+/// Simulates one event on a new connection, see [`Simulator`] to reuse one.
+pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
+    Simulator::new()?.simulate(event_type)
+}
 
+/// Presses the spare keycode remapped to `chr`, or releases it and restores
+/// its mapping.
+unsafe fn send_native_char(chr: char, pressed: bool, display: *mut xlib::Display) -> Option<()> {
+    unsafe {
         let res = if pressed {
-            xtest::XTestFakeKeyEvent(display, keycode as _, TRUE, 0)
+            remap_spare_keycode(display, keysym_from_char(chr))?;
+            record_injection(xlib::KeyPress, SPARE_KEYCODE);
+            xtest::XTestFakeKeyEvent(display, SPARE_KEYCODE, TRUE, 0)
         } else {
-            xtest::XTestFakeKeyEvent(display, keycode as _, FALSE, 0)
+            record_injection(xlib::KeyRelease, SPARE_KEYCODE);
+            let res = xtest::XTestFakeKeyEvent(display, SPARE_KEYCODE, FALSE, 0);
+            restore_spare_keycode(display);
+            res
         };
 
         if res == 0 { None } else { Some(()) }
     }
 }
 
-pub fn simulate_char(chr: char, pressed: bool) -> Result<(), SimulateError> {
+/// Latin-1 characters have the same keysym, the others the Unicode keysym.
+fn keysym_from_char(chr: char) -> xlib::KeySym {
+    let ordinal: u32 = chr.into();
+    if ordinal < 0x100 {
+        ordinal.into()
+    } else {
+        (ordinal | 0x01000000).into()
+    }
+}
+
+unsafe fn remap_spare_keycode(display: *mut xlib::Display, mut keysym: xlib::KeySym) -> Option<()> {
+    unsafe {
+        let mut original = SPARE_KEYSYMS.lock();
+        if original.is_none() {
+            let mut per_keycode = 0;
+            let keysyms =
+                xlib::XGetKeyboardMapping(display, SPARE_KEYCODE as _, 1, &mut per_keycode);
+            if keysyms.is_null() {
+                return None;
+            }
+            *original = Some(slice::from_raw_parts(keysyms, per_keycode.max(0) as usize).to_vec());
+            xlib::XFree(keysyms.cast());
+        }
+        xlib::XChangeKeyboardMapping(display, SPARE_KEYCODE as _, 1, &mut keysym, 1);
+        Some(())
+    }
+}
+
+unsafe fn restore_spare_keycode(display: *mut xlib::Display) {
+    let Some(mut keysyms) = SPARE_KEYSYMS.lock().take() else {
+        return;
+    };
+    unsafe {
+        // Clients look the keycode up when they handle the event, so the
+        // mapping has to outlive it a little.
+        xlib::XSync(display, FALSE);
+        thread::sleep(RESTORE_DELAY);
+        xlib::XChangeKeyboardMapping(
+            display,
+            SPARE_KEYCODE as _,
+            keysyms.len() as c_int,
+            keysyms.as_mut_ptr(),
+            1,
+        );
+    }
+}
+
+/// The key events typing `chr` with the active layout, see
+/// [`XkbKeyboard::events_for_char`].
+unsafe fn events_for_char(chr: char, display: *mut xlib::Display) -> Option<Vec<EventType>> {
     unsafe {
-        let dpy = xlib::XOpenDisplay(null());
-        if dpy.is_null() {
+        let names = x11_rules_names(display)?;
+        let mut active = ACTIVE_KEYBOARD.lock();
+        if active
+            .as_ref()
+            .is_none_or(|(active_names, _)| *active_names != names)
+        {
+            *active = Some((names.clone(), XkbKeyboard::from_rules_names(&names)?));
+        }
+        let (_, keyboard) = active.as_mut()?;
+        keyboard.set_layout(x11_layout(display));
+        keyboard.events_for_char(chr)
+    }
+}
+
+/// Sends the events up to the last key press, or the ones after it.
+unsafe fn send_char_events(
+    events: &[EventType],
+    pressed: bool,
+    display: *mut xlib::Display,
+) -> Option<()> {
+    let split = events
+        .iter()
+        .rposition(|event| matches!(event, EventType::KeyPress(_)))?
+        + 1;
+    let events = if pressed {
+        &events[..split]
+    } else {
+        &events[split..]
+    };
+    for event in events {
+        unsafe { send_native(event, display) }?;
+    }
+    Some(())
+}
+
+/// Types `chr` with the keys of the active layout, including modifiers and
+/// dead keys. Only when no key produces it, a spare keycode is remapped to it
+/// until the release.
+pub fn simulate_char(chr: char, pressed: bool) -> Result<(), SimulateError> {
+    Simulator::new()?.simulate_char(chr, pressed)
+}
+
+/// Types the characters of `text` with the keys of the active layout, or
+/// nothing if one of them has no key.
+pub(crate) fn type_layout(text: &str) -> Result<(), SimulateError> {
+    Simulator::new()?.send(|display| unsafe {
+        let events = text
+            .chars()
+            .map(|chr| events_for_char(chr, display))
+            .collect::<Option<Vec<_>>>()?;
+        for event in events.iter().flatten() {
+            send_native(event, display)?;
+        }
+        Some(())
+    })
+}
+
+/// Types the characters of `text` through a remapped keycode, restoring its
+/// mapping after each one.
+pub(crate) fn type_unicode(text: &str) -> Result<(), SimulateError> {
+    Simulator::new()?.send(|display| unsafe {
+        for chr in text.chars() {
+            send_native_char(chr, true, display)?;
+            send_native_char(chr, false, display)?;
+        }
+        Some(())
+    })
+}
+
+/// A connection to the X server to simulate events with, where `simulate`
+/// opens a new one for every event.
+///
+/// ```no_run
+/// use rdev::{EventType, Simulator};
+///
+/// let simulator = Simulator::new().unwrap();
+/// let path: Vec<EventType> = (0..480)
+///     .map(|pixel| EventType::MouseMove {
+///         x: pixel as f64,
+///         y: pixel as f64,
+///     })
+///     .collect();
+/// simulator.simulate_all(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Simulator {
+    display: *mut xlib::Display,
+}
+
+// The connection is only used through `&self`, from one thread at a time.
+unsafe impl Send for Simulator {}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        unsafe { xlib::XCloseDisplay(self.display) };
+    }
+}
+
+impl Simulator {
+    pub fn new() -> Result<Simulator, SimulateError> {
+        let display = unsafe { xlib::XOpenDisplay(null()) };
+        if display.is_null() {
             return Err(SimulateError);
         }
-        match send_native_char(chr, pressed, dpy) {
-            Some(_) => {
-                xlib::XFlush(dpy);
-                xlib::XSync(dpy, 0);
-                xlib::XCloseDisplay(dpy);
-                Ok(())
+        Ok(Simulator { display })
+    }
+
+    /// Simulates one event and waits for the X server to process it.
+    pub fn simulate(&self, event_type: &EventType) -> Result<(), SimulateError> {
+        self.simulate_all(slice::from_ref(event_type))
+    }
+
+    /// Simulates the events in order, with a single round trip to the X
+    /// server. Stops at the first event that cannot be simulated, the ones
+    /// before it are still sent.
+    pub fn simulate_all(&self, events: &[EventType]) -> Result<(), SimulateError> {
+        self.send(|display| {
+            for event in events {
+                unsafe { send_native(event, display) }?;
             }
-            None => {
-                xlib::XCloseDisplay(dpy);
-                Err(SimulateError)
+            Some(())
+        })
+    }
+
+    /// Same as the free [`simulate_char`], on this connection.
+    pub fn simulate_char(&self, chr: char, pressed: bool) -> Result<(), SimulateError> {
+        self.send(|display| unsafe {
+            match events_for_char(chr, display) {
+                Some(events) => send_char_events(&events, pressed, display),
+                None => send_native_char(chr, pressed, display),
             }
+        })
+    }
+
+    /// Runs `send`, then flushes and waits for the X server.
+    fn send(
+        &self,
+        send: impl FnOnce(*mut xlib::Display) -> Option<()>,
+    ) -> Result<(), SimulateError> {
+        let res = send(self.display);
+        unsafe {
+            xlib::XFlush(self.display);
+            xlib::XSync(self.display, FALSE);
         }
+        res.ok_or(SimulateError)
+    }
+}
+
+/// Types a UTF-16 unit through a remapped keycode, whose mapping is restored
+/// afterwards. A high surrogate is kept until the low surrogate completing it.
+pub fn simulate_unicode(unicode: u16) -> Result<(), SimulateError> {
+    match decode_unit(&mut PENDING_SURROGATE.lock(), unicode)? {
+        Some(chr) => type_unicode(chr.encode_utf8(&mut [0; 4])),
+        None => Ok(()),
     }
 }
 
-pub fn simulate_unicode(_unicode: u16) -> Result<(), SimulateError> {
-    Err(SimulateError)
+/// The character completed by `unit`, if any.
+fn decode_unit(pending: &mut Option<u16>, unit: u16) -> Result<Option<char>, SimulateError> {
+    let units = match unit {
+        0xd800..=0xdbff => {
+            *pending = Some(unit);
+            return Ok(None);
+        }
+        0xdc00..=0xdfff => [pending.take().ok_or(SimulateError)?, unit],
+        _ => {
+            *pending = None;
+            [unit, 0]
+        }
+    };
+    match char::decode_utf16(units).next() {
+        Some(Ok(chr)) => Ok(Some(chr)),
+        _ => Err(SimulateError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_unit() {
+        let mut pending = None;
+        assert_eq!(decode_unit(&mut pending, 0x61).ok(), Some(Some('a')));
+        assert_eq!(decode_unit(&mut pending, 0xe9).ok(), Some(Some('é')));
+        // 😀 is U+1F600, D83D DE00 in UTF-16.
+        assert_eq!(decode_unit(&mut pending, 0xd83d).ok(), Some(None));
+        assert_eq!(decode_unit(&mut pending, 0xde00).ok(), Some(Some('😀')));
+        assert!(decode_unit(&mut pending, 0xde00).is_err());
+        assert_eq!(pending, None);
+    }
+
+    #[test]
+    fn test_keysym_from_char() {
+        assert_eq!(keysym_from_char('a'), 0x61);
+        assert_eq!(keysym_from_char('é'), 0xe9);
+        assert_eq!(keysym_from_char('€'), 0x010020ac);
+    }
+
+    #[test]
+    fn test_wheel_clicks() {
+        assert_eq!(wheel_clicks(0.0), 0);
+        assert_eq!(wheel_clicks(0.2), 1);
+        assert_eq!(wheel_clicks(-1.0), 1);
+        assert_eq!(wheel_clicks(3.4), 3);
+        assert_eq!(wheel_clicks(-2.6), 3);
+        assert_eq!(wheel_clicks(f64::INFINITY), 0);
+        assert_eq!(wheel_clicks(1e9), MAX_WHEEL_CLICKS);
+    }
 }
@@ -0,0 +1,916 @@
+use crate::keycodes::linux::{code_from_key, key_from_code};
+use crate::linux::common::modifiers_from_state;
+use crate::rdev::{EventType, Key, KeyboardState, Modifiers, UnicodeInfo};
+use std::ffi::{CStr, CString};
+use std::mem::MaybeUninit;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::path::Path;
+use std::ptr::{null, null_mut};
+use std::slice;
+use std::sync::LazyLock;
+use x11::xlib;
+
+const XKB_KEY_UP: c_int = 0;
+const XKB_KEY_DOWN: c_int = 1;
+const XKB_KEYMAP_FORMAT_TEXT_V1: c_int = 1;
+const XKB_CONTEXT_NO_DEFAULT_INCLUDES: c_int = 1 << 0;
+const XKB_COMPOSE_FEED_IGNORED: c_int = 0;
+const XKB_COMPOSE_COMPOSING: c_int = 1;
+const XKB_COMPOSE_COMPOSED: c_int = 2;
+const XKB_COMPOSE_CANCELLED: c_int = 3;
+const XKB_STATE_MODS_DEPRESSED: c_int = 1 << 0;
+const XKB_STATE_LAYOUT_EFFECTIVE: c_int = 1 << 7;
+const XKB_LAYOUT_INVALID: u32 = 0xffff_ffff;
+const XKB_USE_CORE_KBD: c_uint = 0x0100;
+
+#[repr(C)]
+struct RuleNames {
+    rules: *const c_char,
+    model: *const c_char,
+    layout: *const c_char,
+    variant: *const c_char,
+    options: *const c_char,
+}
+
+// libxkbcommon is loaded at runtime, so that it is only needed by the
+// applications using `XkbKeyboard`.
+macro_rules! xkb_functions {
+    ($($name:ident: fn($($arg:ty),*) $(-> $ret:ty)?;)*) => {
+        struct Xkb {
+            $($name: unsafe extern "C" fn($($arg),*) $(-> $ret)?,)*
+        }
+
+        impl Xkb {
+            unsafe fn load() -> Option<Xkb> {
+                unsafe {
+                    let handle = libc::dlopen(c"libxkbcommon.so.0".as_ptr(), libc::RTLD_NOW);
+                    if handle.is_null() {
+                        return None;
+                    }
+                    Some(Xkb {
+                        $($name: {
+                            let symbol = libc::dlsym(
+                                handle,
+                                concat!(stringify!($name), "\0").as_ptr() as *const c_char,
+                            );
+                            if symbol.is_null() {
+                                return None;
+                            }
+                            std::mem::transmute::<
+                                *mut c_void,
+                                unsafe extern "C" fn($($arg),*) $(-> $ret)?,
+                            >(symbol)
+                        },)*
+                    })
+                }
+            }
+        }
+    };
+}
+
+xkb_functions! {
+    xkb_context_new: fn(c_int) -> *mut c_void;
+    xkb_context_unref: fn(*mut c_void);
+    xkb_keymap_new_from_names: fn(*mut c_void, *const RuleNames, c_int) -> *mut c_void;
+    xkb_keymap_new_from_string: fn(*mut c_void, *const c_char, c_int, c_int) -> *mut c_void;
+    xkb_keymap_unref: fn(*mut c_void);
+    xkb_keymap_min_keycode: fn(*mut c_void) -> u32;
+    xkb_keymap_max_keycode: fn(*mut c_void) -> u32;
+    xkb_keymap_num_levels_for_key: fn(*mut c_void, u32, u32) -> u32;
+    xkb_keymap_key_get_syms_by_level: fn(*mut c_void, u32, u32, u32, *mut *const u32) -> c_int;
+    xkb_keymap_key_get_mods_for_level: fn(*mut c_void, u32, u32, u32, *mut u32, usize) -> usize;
+    xkb_state_new: fn(*mut c_void) -> *mut c_void;
+    xkb_state_unref: fn(*mut c_void);
+    xkb_state_update_key: fn(*mut c_void, u32, c_int) -> c_int;
+    xkb_state_key_get_one_sym: fn(*mut c_void, u32) -> u32;
+    xkb_state_key_get_layout: fn(*mut c_void, u32) -> u32;
+    xkb_state_serialize_mods: fn(*mut c_void, c_int) -> u32;
+    xkb_state_serialize_layout: fn(*mut c_void, c_int) -> u32;
+    xkb_state_update_mask: fn(*mut c_void, u32, u32, u32, u32, u32, u32) -> c_int;
+    xkb_keysym_to_utf32: fn(u32) -> u32;
+    xkb_keysym_to_utf8: fn(u32, *mut c_char, usize) -> c_int;
+    xkb_keysym_get_name: fn(u32, *mut c_char, usize) -> c_int;
+    xkb_compose_table_new_from_locale: fn(*mut c_void, *const c_char, c_int) -> *mut c_void;
+    xkb_compose_table_unref: fn(*mut c_void);
+    xkb_compose_state_new: fn(*mut c_void, c_int) -> *mut c_void;
+    xkb_compose_state_unref: fn(*mut c_void);
+    xkb_compose_state_feed: fn(*mut c_void, u32) -> c_int;
+    xkb_compose_state_reset: fn(*mut c_void);
+    xkb_compose_state_get_status: fn(*mut c_void) -> c_int;
+    xkb_compose_state_get_utf8: fn(*mut c_void, *mut c_char, usize) -> c_int;
+}
+
+static XKB: LazyLock<Option<Xkb>> = LazyLock::new(|| unsafe { Xkb::load() });
+
+/// Reads a string written by one of the `*_utf8`/`*_name` functions, which
+/// return the length it needed.
+fn read_string(write: impl FnOnce(*mut c_char, usize) -> c_int) -> Option<String> {
+    let mut buf = [0 as c_char; 64];
+    let len = write(buf.as_mut_ptr(), buf.len());
+    if len <= 0 || len as usize >= buf.len() {
+        return None;
+    }
+    let string = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    string.to_str().ok().map(str::to_owned)
+}
+
+/// A `KeyboardState` computed from an XKB keymap with libxkbcommon.
+///
+/// Unlike [`Keyboard`](crate::Keyboard) it does not ask the X server for
+/// anything: it tracks the modifiers, locks and layout group from the events
+/// it is given, and applies dead keys and compose sequences from the Compose
+/// file of the current locale. The same events always give the same result.
+///
+/// Needs libxkbcommon at runtime, the constructors return `None` without it.
+#[derive(Debug)]
+pub struct XkbKeyboard {
+    context: *mut c_void,
+    keymap: *mut c_void,
+    state: *mut c_void,
+    compose_table: *mut c_void,
+    compose_state: *mut c_void,
+    keysym: u32,
+    dead: bool,
+}
+
+// The libxkbcommon objects are only reachable through this struct.
+unsafe impl Send for XkbKeyboard {}
+
+impl Drop for XkbKeyboard {
+    fn drop(&mut self) {
+        let Some(xkb) = XKB.as_ref() else {
+            return;
+        };
+        unsafe {
+            if !self.compose_state.is_null() {
+                (xkb.xkb_compose_state_unref)(self.compose_state);
+            }
+            if !self.compose_table.is_null() {
+                (xkb.xkb_compose_table_unref)(self.compose_table);
+            }
+            (xkb.xkb_state_unref)(self.state);
+            (xkb.xkb_keymap_unref)(self.keymap);
+            (xkb.xkb_context_unref)(self.context);
+        }
+    }
+}
+
+impl XkbKeyboard {
+    /// The keymap libxkbcommon defaults to, which follows the
+    /// `XKB_DEFAULT_LAYOUT`, `XKB_DEFAULT_VARIANT`, ... environment variables.
+    pub fn new() -> Option<XkbKeyboard> {
+        Self::from_names(&RuleNames {
+            rules: null(),
+            model: null(),
+            layout: null(),
+            variant: null(),
+            options: null(),
+        })
+    }
+
+    /// The keymap of an XKB layout and optional variant, as in
+    /// `setxkbmap <layout> [<variant>]`, on a pc105 keyboard without any option.
+    /// It only depends on the XKB data files (xkeyboard-config), never on the
+    /// current layout or display, which makes it fit for tests.
+    ///
+    /// ```no_run
+    /// use rdev::{EventType, Key, KeyboardState, XkbKeyboard};
+    ///
+    /// let mut keyboard = XkbKeyboard::from_layout("de", Some("nodeadkeys")).unwrap();
+    /// let string = keyboard.add(&EventType::KeyPress(Key::KeyZ));
+    /// // string == Some("y")
+    /// ```
+    pub fn from_layout(layout: &str, variant: Option<&str>) -> Option<XkbKeyboard> {
+        let rules = CString::new("evdev").ok()?;
+        let model = CString::new("pc105").ok()?;
+        let layout = CString::new(layout).ok()?;
+        // Empty strings rather than null pointers, which would pick the
+        // defaults from the environment.
+        let variant = CString::new(variant.unwrap_or_default()).ok()?;
+        let options = CString::new("").ok()?;
+        Self::from_names(&RuleNames {
+            rules: rules.as_ptr(),
+            model: model.as_ptr(),
+            layout: layout.as_ptr(),
+            variant: variant.as_ptr(),
+            options: options.as_ptr(),
+        })
+    }
+
+    /// The layouts the X server was configured with, as reported by
+    /// `setxkbmap -query`, in the layout group currently active. Changes made
+    /// with `xmodmap` are not seen.
+    pub fn from_x11() -> Option<XkbKeyboard> {
+        unsafe {
+            let display = xlib::XOpenDisplay(null());
+            if display.is_null() {
+                return None;
+            }
+            let keyboard = x11_rules_names(display)
+                .and_then(|names| Self::from_rules_names(&names))
+                .map(|mut keyboard| {
+                    keyboard.set_layout(x11_layout(display));
+                    keyboard
+                });
+            xlib::XCloseDisplay(display);
+            keyboard
+        }
+    }
+
+    /// From the `_XKB_RULES_NAMES` property: rules, model, layout, variant
+    /// and options separated by NUL bytes.
+    pub(crate) fn from_rules_names(names: &[u8]) -> Option<XkbKeyboard> {
+        let mut names = names
+            .split(|byte| *byte == 0)
+            .map(|name| CString::new(name).ok());
+        let mut next = || names.next().flatten().unwrap_or_default();
+        let (rules, model, layout, variant, options) = (next(), next(), next(), next(), next());
+        Self::from_names(&RuleNames {
+            rules: rules.as_ptr(),
+            model: model.as_ptr(),
+            layout: layout.as_ptr(),
+            variant: variant.as_ptr(),
+            options: options.as_ptr(),
+        })
+    }
+
+    /// Locks the layout group, as the X server does when switching layouts.
+    pub(crate) fn set_layout(&mut self, layout: u32) {
+        if let Some(xkb) = XKB.as_ref() {
+            unsafe { (xkb.xkb_state_update_mask)(self.state, 0, 0, 0, 0, 0, layout) };
+        }
+    }
+
+    /// Reads a keymap in the XKB text format, as written by `xkbcomp $DISPLAY out.xkb`.
+    pub fn from_keymap_file<P: AsRef<Path>>(path: P) -> Option<XkbKeyboard> {
+        let keymap = std::fs::read_to_string(path).ok()?;
+        Self::from_keymap_string(&keymap)
+    }
+
+    /// Same as [`XkbKeyboard::from_keymap_file`], with the content of the file.
+    pub fn from_keymap_string(keymap: &str) -> Option<XkbKeyboard> {
+        let keymap = CString::new(keymap).ok()?;
+        Self::build(|xkb, context| unsafe {
+            (xkb.xkb_keymap_new_from_string)(context, keymap.as_ptr(), XKB_KEYMAP_FORMAT_TEXT_V1, 0)
+        })
+    }
+
+    fn from_names(names: &RuleNames) -> Option<XkbKeyboard> {
+        Self::build(|xkb, context| unsafe { (xkb.xkb_keymap_new_from_names)(context, names, 0) })
+    }
+
+    fn build(new_keymap: impl FnOnce(&Xkb, *mut c_void) -> *mut c_void) -> Option<XkbKeyboard> {
+        let xkb = XKB.as_ref()?;
+        unsafe {
+            // Without the xkeyboard-config data, only complete keymaps
+            // (`xkbcomp -xkb` output) still load.
+            let context = [0, XKB_CONTEXT_NO_DEFAULT_INCLUDES]
+                .into_iter()
+                .map(|flags| (xkb.xkb_context_new)(flags))
+                .find(|context| !context.is_null())?;
+            let keymap = new_keymap(xkb, context);
+            if keymap.is_null() {
+                (xkb.xkb_context_unref)(context);
+                return None;
+            }
+            let state = (xkb.xkb_state_new)(keymap);
+            if state.is_null() {
+                (xkb.xkb_keymap_unref)(keymap);
+                (xkb.xkb_context_unref)(context);
+                return None;
+            }
+            // Without a Compose file dead keys still report `is_dead`, they
+            // just never combine.
+            let mut compose_table = compose_locale()
+                .iter()
+                .map(|locale| (xkb.xkb_compose_table_new_from_locale)(context, locale.as_ptr(), 0))
+                .find(|table| !table.is_null())
+                .unwrap_or(null_mut());
+            let compose_state = if compose_table.is_null() {
+                compose_table
+            } else {
+                (xkb.xkb_compose_state_new)(compose_table, 0)
+            };
+            if compose_state.is_null() && !compose_table.is_null() {
+                (xkb.xkb_compose_table_unref)(compose_table);
+                compose_table = null_mut();
+            }
+            Some(XkbKeyboard {
+                context,
+                keymap,
+                state,
+                compose_table,
+                compose_state,
+                keysym: 0,
+                dead: false,
+            })
+        }
+    }
+
+    /// Whether the last key press started or continued a dead key or compose
+    /// sequence.
+    pub fn is_dead(&self) -> bool {
+        self.dead
+    }
+
+    /// The keysym of the last key press.
+    pub fn keysym(&self) -> u32 {
+        self.keysym
+    }
+
+    /// The key presses and releases that type `chr` with the current layout
+    /// group: the modifiers are pressed first and released last, and
+    /// characters that no key produces are looked up as a dead key followed
+    /// by a base key. `None` when no key of the keymap types it.
+    ///
+    /// It is the reverse of [`KeyboardState::add`], and does not change the
+    /// state of the keyboard.
+    ///
+    /// ```no_run
+    /// use rdev::{EventType, Key, XkbKeyboard};
+    ///
+    /// let keyboard = XkbKeyboard::from_layout("us", None).unwrap();
+    /// let events = keyboard.events_for_char('"').unwrap();
+    /// // events == [KeyPress(ShiftLeft), KeyPress(Quote), KeyRelease(Quote), KeyRelease(ShiftLeft)]
+    /// ```
+    pub fn events_for_char(&self, chr: char) -> Option<Vec<EventType>> {
+        let xkb = XKB.as_ref()?;
+        // Return gives a carriage return, Linefeed has no key on most keyboards.
+        let chr = if chr == '\n' { '\r' } else { chr };
+        let reachable = unsafe { self.reachable_keysyms(xkb) };
+        let mut direct = reachable
+            .iter()
+            .filter(|(keysym, _)| unsafe { (xkb.xkb_keysym_to_utf32)(*keysym) } == chr as u32)
+            .map(|(_, events)| events);
+        // Keys named in `Key` are more likely to exist on the keyboard than
+        // the extra keycodes of the keymap.
+        if let Some(events) = direct
+            .clone()
+            .find(|events| all_known(events))
+            .or(direct.next())
+        {
+            return Some(events.clone());
+        }
+        unsafe { self.dead_key_events(xkb, chr, &reachable) }
+    }
+
+    /// Every keysym of the current layout group along with the events typing
+    /// it, in the order of the keycodes and then of the levels.
+    unsafe fn reachable_keysyms(&self, xkb: &Xkb) -> Vec<(u32, Vec<EventType>)> {
+        unsafe {
+            let layout = (xkb.xkb_state_serialize_layout)(self.state, XKB_STATE_LAYOUT_EFFECTIVE);
+            let min = (xkb.xkb_keymap_min_keycode)(self.keymap);
+            let max = (xkb.xkb_keymap_max_keycode)(self.keymap);
+            let modifier_keys = self.modifier_keys(xkb, layout, min..=max);
+
+            let mut reachable = Vec::new();
+            let Some(state) = self.scratch_state(xkb, layout) else {
+                return reachable;
+            };
+            for keycode in min..=max {
+                let key_layout = (xkb.xkb_state_key_get_layout)(state, keycode);
+                if key_layout == XKB_LAYOUT_INVALID {
+                    continue;
+                }
+                let levels = (xkb.xkb_keymap_num_levels_for_key)(self.keymap, keycode, key_layout);
+                for level in 0..levels {
+                    let mut syms = null();
+                    let count = (xkb.xkb_keymap_key_get_syms_by_level)(
+                        self.keymap,
+                        keycode,
+                        key_layout,
+                        level,
+                        &mut syms,
+                    );
+                    if count != 1 {
+                        continue;
+                    }
+                    let keysym = *syms;
+                    let mut masks = [0; 16];
+                    let count = (xkb.xkb_keymap_key_get_mods_for_level)(
+                        self.keymap,
+                        keycode,
+                        key_layout,
+                        level,
+                        masks.as_mut_ptr(),
+                        masks.len(),
+                    );
+                    let mut masks = masks[..count.min(masks.len())].to_vec();
+                    masks.sort_by_key(|mask| mask.count_ones());
+                    let modifiers = masks.iter().find_map(|mask| {
+                        let keys = (0..32)
+                            .filter(|bit| mask & (1 << bit) != 0)
+                            .map(|bit| modifier_keys[bit])
+                            .collect::<Option<Vec<_>>>()?;
+                        self.check_level(xkb, layout, &keys, keycode, keysym)
+                            .then_some(keys)
+                    });
+                    if let Some(modifiers) = modifiers {
+                        reachable.push((keysym, key_events(&modifiers, keycode)));
+                    }
+                }
+            }
+            (xkb.xkb_state_unref)(state);
+            reachable
+        }
+    }
+
+    /// For each modifier, a key setting it and nothing else while held.
+    /// Locking keys such as Caps Lock are left out, typing should not change
+    /// the locks.
+    unsafe fn modifier_keys(
+        &self,
+        xkb: &Xkb,
+        layout: u32,
+        keycodes: std::ops::RangeInclusive<u32>,
+    ) -> [Option<u32>; 32] {
+        let mut modifier_keys = [None; 32];
+        for keycode in keycodes {
+            let Some(state) = (unsafe { self.scratch_state(xkb, layout) }) else {
+                break;
+            };
+            let mods = unsafe {
+                (xkb.xkb_state_update_key)(state, keycode, XKB_KEY_DOWN);
+                let mods = (xkb.xkb_state_serialize_mods)(state, XKB_STATE_MODS_DEPRESSED);
+                (xkb.xkb_state_unref)(state);
+                mods
+            };
+            if mods.count_ones() == 1 {
+                let modifier_key = &mut modifier_keys[mods.trailing_zeros() as usize];
+                if modifier_key.is_none_or(|code| matches!(key_from_code(code), Key::Unknown(_))) {
+                    *modifier_key = Some(keycode);
+                }
+            }
+        }
+        modifier_keys
+    }
+
+    /// Whether holding `modifiers` makes `keycode` produce `keysym`.
+    unsafe fn check_level(
+        &self,
+        xkb: &Xkb,
+        layout: u32,
+        modifiers: &[u32],
+        keycode: u32,
+        keysym: u32,
+    ) -> bool {
+        let Some(state) = (unsafe { self.scratch_state(xkb, layout) }) else {
+            return false;
+        };
+        unsafe {
+            for modifier in modifiers {
+                (xkb.xkb_state_update_key)(state, *modifier, XKB_KEY_DOWN);
+            }
+            let produced = (xkb.xkb_state_key_get_one_sym)(state, keycode);
+            (xkb.xkb_state_unref)(state);
+            produced == keysym
+        }
+    }
+
+    /// A new state with nothing pressed and the given layout group locked.
+    unsafe fn scratch_state(&self, xkb: &Xkb, layout: u32) -> Option<*mut c_void> {
+        unsafe {
+            let state = (xkb.xkb_state_new)(self.keymap);
+            if state.is_null() {
+                return None;
+            }
+            (xkb.xkb_state_update_mask)(state, 0, 0, 0, 0, 0, layout);
+            Some(state)
+        }
+    }
+
+    /// Looks for a dead key and a base key composing `chr`.
+    unsafe fn dead_key_events(
+        &self,
+        xkb: &Xkb,
+        chr: char,
+        reachable: &[(u32, Vec<EventType>)],
+    ) -> Option<Vec<EventType>> {
+        if self.compose_table.is_null() {
+            return None;
+        }
+        unsafe {
+            let compose_state = (xkb.xkb_compose_state_new)(self.compose_table, 0);
+            if compose_state.is_null() {
+                return None;
+            }
+            let expected = chr.to_string();
+            let is_dead = |keysym: u32| {
+                read_string(|buf, len| (xkb.xkb_keysym_get_name)(keysym, buf, len))
+                    .is_some_and(|name| name.starts_with("dead_"))
+            };
+            let mut found = None;
+            'dead: for (dead, dead_events) in
+                reachable.iter().filter(|(keysym, _)| is_dead(*keysym))
+            {
+                for (base, base_events) in reachable {
+                    (xkb.xkb_compose_state_reset)(compose_state);
+                    (xkb.xkb_compose_state_feed)(compose_state, *dead);
+                    (xkb.xkb_compose_state_feed)(compose_state, *base);
+                    if (xkb.xkb_compose_state_get_status)(compose_state) != XKB_COMPOSE_COMPOSED {
+                        continue;
+                    }
+                    let composed = read_string(|buf, len| {
+                        (xkb.xkb_compose_state_get_utf8)(compose_state, buf, len)
+                    });
+                    if composed.as_deref() == Some(expected.as_str()) {
+                        found = Some([dead_events.as_slice(), base_events].concat());
+                        break 'dead;
+                    }
+                }
+            }
+            (xkb.xkb_compose_state_unref)(compose_state);
+            found
+        }
+    }
+
+    fn press(&mut self, xkb: &Xkb, keycode: u32) -> Option<UnicodeInfo> {
+        unsafe {
+            // The symbol depends on the state before the key goes down.
+            let keysym = (xkb.xkb_state_key_get_one_sym)(self.state, keycode);
+            (xkb.xkb_state_update_key)(self.state, keycode, XKB_KEY_DOWN);
+            self.keysym = keysym;
+
+            if !self.compose_state.is_null()
+                && (xkb.xkb_compose_state_feed)(self.compose_state, keysym)
+                    != XKB_COMPOSE_FEED_IGNORED
+            {
+                match (xkb.xkb_compose_state_get_status)(self.compose_state) {
+                    XKB_COMPOSE_COMPOSING => return Some(self.dead_key()),
+                    XKB_COMPOSE_COMPOSED => {
+                        let composed = read_string(|buf, len| {
+                            (xkb.xkb_compose_state_get_utf8)(self.compose_state, buf, len)
+                        });
+                        (xkb.xkb_compose_state_reset)(self.compose_state);
+                        self.dead = false;
+                        return composed.map(unicode_info);
+                    }
+                    XKB_COMPOSE_CANCELLED => {
+                        (xkb.xkb_compose_state_reset)(self.compose_state);
+                        self.dead = false;
+                        return None;
+                    }
+                    _ => {}
+                }
+            }
+
+            let name = read_string(|buf, len| (xkb.xkb_keysym_get_name)(keysym, buf, len));
+            if name.is_some_and(|name| name.starts_with("dead_")) {
+                return Some(self.dead_key());
+            }
+            self.dead = false;
+            // From the keysym rather than the state, which would turn
+            // Control+C into the ETX control character.
+            let string = read_string(|buf, len| (xkb.xkb_keysym_to_utf8)(keysym, buf, len))?;
+            if string.chars().all(char::is_control) {
+                return None;
+            }
+            Some(unicode_info(string))
+        }
+    }
+
+    fn dead_key(&mut self) -> UnicodeInfo {
+        self.dead = true;
+        UnicodeInfo {
+            name: None,
+            unicode: Vec::new(),
+            is_dead: true,
+        }
+    }
+}
+
+impl KeyboardState for XkbKeyboard {
+    fn add(&mut self, event_type: &EventType) -> Option<UnicodeInfo> {
+        let xkb = XKB.as_ref()?;
+        match event_type {
+            // X keycodes and XKB keycodes are the same.
+            EventType::KeyPress(key) => self.press(xkb, code_from_key(*key)?),
+            EventType::KeyRelease(key) => {
+                let keycode = code_from_key(*key)?;
+                unsafe { (xkb.xkb_state_update_key)(self.state, keycode, XKB_KEY_UP) };
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Presses the modifiers, then types the key and releases the modifiers in
+/// reverse order.
+fn key_events(modifiers: &[u32], keycode: u32) -> Vec<EventType> {
+    let press = modifiers
+        .iter()
+        .chain(Some(&keycode))
+        .map(|code| EventType::KeyPress(key_from_code(*code)));
+    let release = Some(&keycode)
+        .into_iter()
+        .chain(modifiers.iter().rev())
+        .map(|code| EventType::KeyRelease(key_from_code(*code)));
+    press.chain(release).collect()
+}
+
+fn all_known(events: &[EventType]) -> bool {
+    events.iter().all(|event| {
+        !matches!(
+            event,
+            EventType::KeyPress(Key::Unknown(_)) | EventType::KeyRelease(Key::Unknown(_))
+        )
+    })
+}
+
+fn unicode_info(name: String) -> UnicodeInfo {
+    UnicodeInfo {
+        unicode: name.encode_utf16().collect(),
+        name: Some(name),
+        is_dead: false,
+    }
+}
+
+/// The `_XKB_RULES_NAMES` property of the root window, which `setxkbmap`
+/// updates.
+pub(crate) unsafe fn x11_rules_names(display: *mut xlib::Display) -> Option<Vec<u8>> {
+    unsafe {
+        let atom = xlib::XInternAtom(display, c"_XKB_RULES_NAMES".as_ptr(), xlib::True);
+        if atom == 0 {
+            return None;
+        }
+        let (mut actual_type, mut format, mut count, mut remaining) = (0, 0, 0, 0);
+        let mut data = null_mut();
+        let status = xlib::XGetWindowProperty(
+            display,
+            xlib::XDefaultRootWindow(display),
+            atom,
+            0,
+            1024,
+            xlib::False,
+            xlib::XA_STRING,
+            &mut actual_type,
+            &mut format,
+            &mut count,
+            &mut remaining,
+            &mut data,
+        );
+        if status != xlib::Success as c_int || data.is_null() {
+            return None;
+        }
+        let names = (format == 8).then(|| slice::from_raw_parts(data, count as usize).to_vec());
+        xlib::XFree(data.cast());
+        names
+    }
+}
+
+/// The layout group active on the X server.
+pub(crate) unsafe fn x11_layout(display: *mut xlib::Display) -> u32 {
+    let mut state = MaybeUninit::<xlib::XkbStateRec>::zeroed();
+    if unsafe { xlib::XkbGetState(display, XKB_USE_CORE_KBD, state.as_mut_ptr()) }
+        != xlib::Success as c_int
+    {
+        return 0;
+    }
+    unsafe { state.assume_init() }.group.into()
+}
+
+/// The modifiers and lock keys active on the X server.
+pub(crate) unsafe fn x11_modifiers(display: *mut xlib::Display) -> Modifiers {
+    let mut state = MaybeUninit::<xlib::XkbStateRec>::zeroed();
+    if unsafe { xlib::XkbGetState(display, XKB_USE_CORE_KBD, state.as_mut_ptr()) }
+        != xlib::Success as c_int
+    {
+        return Modifiers::empty();
+    }
+    modifiers_from_state(unsafe { state.assume_init() }.mods.into())
+}
+
+/// The locales to take the Compose file from, the first one found wins.
+fn compose_locale() -> Vec<CString> {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .filter(|locale| !locale.is_empty())
+        .take(1)
+        .chain(Some("C".to_owned()))
+        .filter_map(|locale| CString::new(locale).ok())
+        .collect()
+}
+
+#[cfg(test)]
+impl XkbKeyboard {
+    /// The keymap of `tests/fixtures/<name>.xkb`, which needs no XKB data
+    /// files. `None` when libxkbcommon is missing.
+    pub(crate) fn fixture(name: &str) -> Option<XkbKeyboard> {
+        if XKB.is_none() {
+            eprintln!("libxkbcommon is missing, skipping");
+            return None;
+        }
+        let path = format!("{}/tests/fixtures/{name}.xkb", env!("CARGO_MANIFEST_DIR"));
+        let keyboard = Self::from_keymap_file(&path);
+        Some(keyboard.unwrap_or_else(|| panic!("Could not load {path}")))
+    }
+
+    /// Whether a Compose file was found, dead keys never combine without one.
+    pub(crate) fn has_compose(&self) -> bool {
+        if self.compose_state.is_null() {
+            eprintln!("No Compose file, skipping");
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyboard() -> Option<XkbKeyboard> {
+        XkbKeyboard::fixture("us_intl")
+    }
+
+    fn type_keys(keyboard: &mut XkbKeyboard, keys: &[Key]) -> Option<UnicodeInfo> {
+        let mut last = None;
+        for key in keys {
+            last = keyboard.add(&EventType::KeyPress(*key));
+            keyboard.add(&EventType::KeyRelease(*key));
+        }
+        last
+    }
+
+    fn name(info: Option<UnicodeInfo>) -> Option<String> {
+        info.and_then(|info| info.name)
+    }
+
+    #[test]
+    fn test_plain_and_shifted() {
+        let Some(mut keyboard) = keyboard() else {
+            return;
+        };
+        assert_eq!(
+            name(type_keys(&mut keyboard, &[Key::KeyS])),
+            Some("s".into())
+        );
+        keyboard.add(&EventType::KeyPress(Key::ShiftLeft));
+        assert_eq!(
+            name(type_keys(&mut keyboard, &[Key::KeyS])),
+            Some("S".into())
+        );
+        keyboard.add(&EventType::KeyRelease(Key::ShiftLeft));
+        assert_eq!(
+            name(type_keys(&mut keyboard, &[Key::Num1])),
+            Some("1".into())
+        );
+        assert_eq!(type_keys(&mut keyboard, &[Key::ShiftLeft]), None);
+    }
+
+    #[test]
+    fn test_caps_lock() {
+        let Some(mut keyboard) = keyboard() else {
+            return;
+        };
+        type_keys(&mut keyboard, &[Key::CapsLock]);
+        assert_eq!(
+            name(type_keys(&mut keyboard, &[Key::KeyA])),
+            Some("A".into())
+        );
+        type_keys(&mut keyboard, &[Key::CapsLock]);
+        assert_eq!(
+            name(type_keys(&mut keyboard, &[Key::KeyA])),
+            Some("a".into())
+        );
+    }
+
+    #[test]
+    fn test_control_is_ignored() {
+        let Some(mut keyboard) = keyboard() else {
+            return;
+        };
+        keyboard.add(&EventType::KeyPress(Key::ControlLeft));
+        assert_eq!(
+            name(type_keys(&mut keyboard, &[Key::KeyC])),
+            Some("c".into())
+        );
+    }
+
+    #[test]
+    fn test_layouts() {
+        // (fixture, keys, expected)
+        let cases: &[(&str, &[Key], &str)] = &[
+            ("us", &[Key::KeyS], "s"),
+            ("us", &[Key::ShiftLeft, Key::Quote], "\""),
+            ("gb", &[Key::ShiftLeft, Key::Quote], "@"),
+            ("de_nodeadkeys", &[Key::KeyZ], "y"),
+            ("de_nodeadkeys", &[Key::Quote], "ä"),
+            ("de_nodeadkeys", &[Key::ShiftLeft, Key::Num2], "\""),
+            ("fr", &[Key::KeyQ], "a"),
+            ("fr", &[Key::Num2], "é"),
+            ("fr", &[Key::ShiftLeft, Key::Num2], "2"),
+        ];
+        for (fixture, keys, expected) in cases {
+            let Some(mut keyboard) = XkbKeyboard::fixture(fixture) else {
+                return;
+            };
+            let mut last = None;
+            // Hold every key, the last one gives the character.
+            for key in *keys {
+                last = keyboard.add(&EventType::KeyPress(*key));
+            }
+            assert_eq!(name(last).as_deref(), Some(*expected), "{fixture} {keys:?}");
+        }
+    }
+
+    #[test]
+    fn test_unknown_layout() {
+        assert!(XkbKeyboard::from_layout("not-a-layout", None).is_none());
+    }
+
+    #[test]
+    fn test_dead_key() {
+        let Some(mut keyboard) = keyboard() else {
+            return;
+        };
+        if !keyboard.has_compose() {
+            return;
+        }
+        let dead = type_keys(&mut keyboard, &[Key::Quote]).unwrap();
+        assert!(dead.is_dead);
+        assert!(keyboard.is_dead());
+        // Shift does not interrupt the sequence.
+        keyboard.add(&EventType::KeyPress(Key::ShiftLeft));
+        let composed = type_keys(&mut keyboard, &[Key::KeyE]).unwrap();
+        keyboard.add(&EventType::KeyRelease(Key::ShiftLeft));
+        assert_eq!(composed.name, Some("É".into()));
+        assert_eq!(composed.unicode, vec![0xc9]);
+        assert!(!keyboard.is_dead());
+    }
+
+    #[test]
+    fn test_events_for_char() {
+        let Some(keyboard) = XkbKeyboard::fixture("us") else {
+            return;
+        };
+        assert_eq!(
+            keyboard.events_for_char('s'),
+            Some(vec![
+                EventType::KeyPress(Key::KeyS),
+                EventType::KeyRelease(Key::KeyS)
+            ])
+        );
+        assert_eq!(
+            keyboard.events_for_char('"'),
+            Some(vec![
+                EventType::KeyPress(Key::ShiftLeft),
+                EventType::KeyPress(Key::Quote),
+                EventType::KeyRelease(Key::Quote),
+                EventType::KeyRelease(Key::ShiftLeft)
+            ])
+        );
+        assert_eq!(
+            keyboard.events_for_char('\n'),
+            Some(vec![
+                EventType::KeyPress(Key::Return),
+                EventType::KeyRelease(Key::Return)
+            ])
+        );
+        assert_eq!(keyboard.events_for_char('é'), None);
+    }
+
+    #[test]
+    fn test_events_for_dead_key() {
+        let Some(keyboard) = keyboard() else {
+            return;
+        };
+        if !keyboard.has_compose() {
+            return;
+        }
+        assert_eq!(
+            keyboard.events_for_char('ê'),
+            Some(vec![
+                EventType::KeyPress(Key::ShiftLeft),
+                EventType::KeyPress(Key::Num6),
+                EventType::KeyRelease(Key::Num6),
+                EventType::KeyRelease(Key::ShiftLeft),
+                EventType::KeyPress(Key::KeyE),
+                EventType::KeyRelease(Key::KeyE)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_events_round_trip() {
+        for fixture in ["us", "us_intl", "de", "fr"] {
+            let Some(planner) = XkbKeyboard::fixture(fixture) else {
+                return;
+            };
+            for chr in "aZ1!\"@€éê ".chars() {
+                let Some(events) = planner.events_for_char(chr) else {
+                    continue;
+                };
+                let mut keyboard = XkbKeyboard::fixture(fixture).unwrap();
+                let typed = events
+                    .iter()
+                    .filter_map(|event| keyboard.add(event))
+                    .filter_map(|info| info.name)
+                    .collect::<String>();
+                assert_eq!(typed, chr.to_string(), "{fixture} {events:?}");
+            }
+        }
+    }
+}
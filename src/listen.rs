@@ -0,0 +1,616 @@
+use crate::rdev::{Event, EventType, ListenError};
+use parking_lot::{Condvar, Mutex};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+type ExitListen = fn() -> Result<(), ListenError>;
+
+// Held while a listener starts, so that two of them cannot both find none
+// running.
+static STARTING: Mutex<()> = Mutex::new(());
+
+/// A listener started with [`start_listen`](crate::start_listen).
+///
+/// Dropping the handle stops the listener. Neither `stop` nor dropping the
+/// handle may happen from inside the listen callback.
+#[derive(Debug)]
+pub struct ListenHandle {
+    exit: ExitListen,
+    thread: Option<JoinHandle<Result<(), ListenError>>>,
+}
+
+impl ListenHandle {
+    /// Stops the listener and waits for it to end, returning the error it
+    /// ended with, if any. Another listener can be started afterwards.
+    pub fn stop(mut self) -> Result<(), ListenError> {
+        self.stop_()
+    }
+
+    fn stop_(&mut self) -> Result<(), ListenError> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+        // Once it ended, through `exit_listen` for instance, the listener
+        // running now is another one.
+        if !thread.is_finished() {
+            (self.exit)()?;
+        }
+        match thread.join() {
+            Ok(result) => result,
+            Err(payload) => std::panic::resume_unwind(payload),
+        }
+    }
+}
+
+impl Drop for ListenHandle {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            let _ = self.stop_();
+        }
+    }
+}
+
+/// Starts listening to global events in the background and returns once the
+/// listener is running. The callback is the same as for [`listen`](crate::listen).
+///
+/// Only one listener can run at a time, starting a second one fails with
+/// `ListenError::AlreadyListening`. When `exit_listen` stops the listener
+/// while it starts, the handle returned has nothing left to stop.
+///
+/// ```no_run
+/// use rdev::start_listen;
+///
+/// let handle = start_listen(|event| println!("{:?}", event.event_type)).unwrap();
+/// std::thread::sleep(std::time::Duration::from_secs(5));
+/// handle.stop().unwrap();
+/// ```
+pub fn start_listen<T>(callback: T) -> Result<ListenHandle, ListenError>
+where
+    T: FnMut(Event) + Send + 'static,
+{
+    ListenerBuilder::new().start(callback)
+}
+
+/// Same as [`start_listen`], with [`listen_evdev`](crate::listen_evdev).
+#[cfg(target_os = "linux")]
+pub fn start_listen_evdev<T>(callback: T) -> Result<ListenHandle, ListenError>
+where
+    T: FnMut(Event) + Send + 'static,
+{
+    ListenerBuilder::new()
+        .backend(Backend::Evdev)
+        .start(callback)
+}
+
+/// The OS facility a listener goes through.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backend {
+    /// The one [`listen`](crate::listen) uses, XRecord on Linux.
+    #[default]
+    Auto,
+    /// Linux: XRecord, see [`listen`](crate::listen).
+    #[cfg(target_os = "linux")]
+    X11,
+    /// Linux: the kernel input devices, see [`listen_evdev`](crate::listen_evdev).
+    #[cfg(target_os = "linux")]
+    Evdev,
+}
+
+/// Configures a listener. Event classes that are turned off are not even
+/// requested from the OS where it allows it (XRecord ranges, hooks, event tap
+/// masks, evdev devices), the rest is filtered before the callback.
+///
+/// By default everything is captured, or only key presses and releases if the
+/// `KEYBOARD_ONLY` environment variable is set.
+///
+/// ```no_run
+/// use rdev::ListenerBuilder;
+/// use std::time::Duration;
+///
+/// let handle = ListenerBuilder::new()
+///     .keys(false)
+///     .raw(false)
+///     .coalesce_motion(Duration::from_millis(16))
+///     .start(|event| println!("{:?}", event.event_type))
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct ListenerBuilder {
+    pub(crate) keys: bool,
+    pub(crate) buttons: bool,
+    pub(crate) motion: bool,
+    pub(crate) wheel: bool,
+    pub(crate) raw: bool,
+    pub(crate) unicode: bool,
+    coalesce_motion: Option<Duration>,
+    backend: Backend,
+}
+
+impl Default for ListenerBuilder {
+    fn default() -> Self {
+        let mouse = !crate::keyboard_only();
+        ListenerBuilder {
+            keys: true,
+            buttons: mouse,
+            motion: mouse,
+            wheel: mouse,
+            raw: mouse,
+            unicode: true,
+            coalesce_motion: None,
+            backend: Backend::Auto,
+        }
+    }
+}
+
+impl ListenerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `KeyPress` and `KeyRelease`.
+    pub fn keys(mut self, enabled: bool) -> Self {
+        self.keys = enabled;
+        self
+    }
+
+    /// `ButtonPress` and `ButtonRelease`.
+    pub fn buttons(mut self, enabled: bool) -> Self {
+        self.buttons = enabled;
+        self
+    }
+
+    /// `MouseMove`.
+    pub fn motion(mut self, enabled: bool) -> Self {
+        self.motion = enabled;
+        self
+    }
+
+    /// `Wheel`.
+    pub fn wheel(mut self, enabled: bool) -> Self {
+        self.wheel = enabled;
+        self
+    }
+
+    /// The raw counterparts of the enabled classes, `KeyPressRaw` with keys,
+    /// `MouseMoveRaw` with motion and so on.
+    pub fn raw(mut self, enabled: bool) -> Self {
+        self.raw = enabled;
+        self
+    }
+
+    /// Whether to fill `Event::unicode`, which needs to track the keyboard
+    /// state. Turning it off saves that work when only keys are needed.
+    pub fn unicode(mut self, enabled: bool) -> Self {
+        self.unicode = enabled;
+        self
+    }
+
+    /// Delivers at most one `MouseMove` per `interval`. The last skipped
+    /// position is delivered once the interval is over, from a timer thread,
+    /// or before the next event of another kind if that comes first.
+    pub fn coalesce_motion(mut self, interval: Duration) -> Self {
+        self.coalesce_motion = Some(interval);
+        self
+    }
+
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Listens until `exit_listen` is called, see [`listen`](crate::listen).
+    pub fn listen<T>(self, callback: T) -> Result<(), ListenError>
+    where
+        T: FnMut(Event) + Send + 'static,
+    {
+        let callback = self.filter(callback);
+        self.run(callback)
+    }
+
+    /// Listens in the background, see [`start_listen`].
+    pub fn start<T>(self, callback: T) -> Result<ListenHandle, ListenError>
+    where
+        T: FnMut(Event) + Send + 'static,
+    {
+        let (is_listening, exit) = self.controls();
+        spawn_listen(move || self.listen(callback), is_listening, exit)
+    }
+
+    pub(crate) fn accepts(&self, event_type: &EventType) -> bool {
+        match event_type {
+            EventType::KeyPress(_) | EventType::KeyRelease(_) => self.keys,
+            EventType::ButtonPress(_) | EventType::ButtonRelease(_) => self.buttons,
+            EventType::MouseMove { .. } => self.motion,
+            EventType::Wheel { .. } => self.wheel,
+            EventType::KeyPressRaw(_) | EventType::KeyReleaseRaw(_) => self.raw && self.keys,
+            EventType::ButtonPressRaw(_) | EventType::ButtonReleaseRaw(_) => {
+                self.raw && self.buttons
+            }
+            EventType::MouseMoveRaw { .. } => self.raw && self.motion,
+            EventType::WheelRaw { .. } => self.raw && self.wheel,
+        }
+    }
+
+    /// Whether any mouse event class is enabled.
+    pub(crate) fn mouse(&self) -> bool {
+        self.buttons || self.motion || self.wheel
+    }
+
+    fn filter<T>(&self, callback: T) -> impl FnMut(Event) + Send + 'static
+    where
+        T: FnMut(Event) + Send + 'static,
+    {
+        let config = self.clone();
+        let mut callback: Box<dyn FnMut(Event) + Send> = match self.coalesce_motion {
+            Some(interval) => Box::new(coalesce_motion(interval, callback)),
+            None => Box::new(callback),
+        };
+        move |mut event: Event| {
+            if !config.accepts(&event.event_type) {
+                return;
+            }
+            if !config.unicode {
+                event.unicode = None;
+            }
+            callback(event);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn is_evdev(&self) -> bool {
+        self.backend == Backend::Evdev
+    }
+
+    #[cfg(target_os = "linux")]
+    fn run<T>(&self, callback: T) -> Result<(), ListenError>
+    where
+        T: FnMut(Event) + Send + 'static,
+    {
+        if self.is_evdev() {
+            crate::linux::listen_evdev(self, callback)
+        } else {
+            crate::linux::listen(self, callback)
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn run<T>(&self, callback: T) -> Result<(), ListenError>
+    where
+        T: FnMut(Event) + Send + 'static,
+    {
+        crate::_listen(self, callback)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn controls(&self) -> (fn() -> bool, ExitListen) {
+        if self.is_evdev() {
+            (
+                crate::linux::is_listening_evdev,
+                crate::linux::exit_listen_evdev,
+            )
+        } else {
+            (
+                crate::linux::is_listening_x11,
+                crate::linux::exit_listen_x11,
+            )
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn controls(&self) -> (fn() -> bool, ExitListen) {
+        (crate::is_listening, crate::exit_listen)
+    }
+}
+
+/// Holds back the `MouseMove` events that come too close to the previous one.
+struct MotionCoalescer {
+    interval: Duration,
+    last: Option<SystemTime>,
+    pending: Option<Event>,
+}
+
+impl MotionCoalescer {
+    fn new(interval: Duration) -> Self {
+        MotionCoalescer {
+            interval,
+            last: None,
+            pending: None,
+        }
+    }
+
+    fn feed<T>(&mut self, event: Event, callback: &mut T)
+    where
+        T: FnMut(Event),
+    {
+        if let EventType::MouseMove { .. } = event.event_type {
+            let too_soon = self.last.is_some_and(|last| {
+                event
+                    .time
+                    .duration_since(last)
+                    .is_ok_and(|elapsed| elapsed < self.interval)
+            });
+            if too_soon {
+                self.pending = Some(event);
+                return;
+            }
+            self.pending = None;
+            self.last = Some(event.time);
+        } else if let Some(pending) = self.pending.take() {
+            self.last = Some(pending.time);
+            callback(pending);
+        }
+        callback(event);
+    }
+
+    /// When the move held back is due.
+    fn deadline(&self) -> Option<SystemTime> {
+        self.pending.as_ref()?;
+        self.last.map(|last| last + self.interval)
+    }
+
+    /// Delivers the move held back if it is due by `now`.
+    fn flush<T>(&mut self, now: SystemTime, callback: &mut T)
+    where
+        T: FnMut(Event),
+    {
+        if self.deadline().is_some_and(|deadline| deadline <= now) {
+            if let Some(pending) = self.pending.take() {
+                self.last = Some(pending.time);
+                callback(pending);
+            }
+        }
+    }
+}
+
+struct Coalescing<T> {
+    coalescer: MotionCoalescer,
+    callback: T,
+    // Set once the listener dropped its callback.
+    stopped: bool,
+}
+
+type SharedCoalescing<T> = Arc<(Mutex<Coalescing<T>>, Condvar)>;
+
+/// Stops the timer thread of `coalesce_motion` along with the callback.
+struct StopTimer<T>(SharedCoalescing<T>);
+
+impl<T> Drop for StopTimer<T> {
+    fn drop(&mut self) {
+        let (coalescing, wake) = &*self.0;
+        coalescing.lock().stopped = true;
+        wake.notify_one();
+    }
+}
+
+/// Runs the callback behind a `MotionCoalescer`, along with a thread that
+/// delivers the move held back once its interval is over: the end of a
+/// gesture does not wait for the next event.
+fn coalesce_motion<T>(interval: Duration, callback: T) -> impl FnMut(Event) + Send + 'static
+where
+    T: FnMut(Event) + Send + 'static,
+{
+    let shared: SharedCoalescing<T> = Arc::new((
+        Mutex::new(Coalescing {
+            coalescer: MotionCoalescer::new(interval),
+            callback,
+            stopped: false,
+        }),
+        Condvar::new(),
+    ));
+    let timer = shared.clone();
+    thread::spawn(move || {
+        let (coalescing, wake) = &*timer;
+        let mut coalescing = coalescing.lock();
+        while !coalescing.stopped {
+            let now = SystemTime::now();
+            match coalescing.coalescer.deadline() {
+                None => wake.wait(&mut coalescing),
+                Some(deadline) if deadline > now => {
+                    let wait = deadline.duration_since(now).unwrap_or_default();
+                    wake.wait_for(&mut coalescing, wait);
+                }
+                Some(_) => {
+                    let Coalescing {
+                        coalescer,
+                        callback,
+                        ..
+                    } = &mut *coalescing;
+                    coalescer.flush(now, callback);
+                }
+            }
+        }
+    });
+    let stop = StopTimer(shared);
+    move |event: Event| {
+        let (coalescing, wake) = &*stop.0;
+        let Coalescing {
+            coalescer,
+            callback,
+            ..
+        } = &mut *coalescing.lock();
+        coalescer.feed(event, callback);
+        wake.notify_one();
+    }
+}
+
+fn spawn_listen<F>(
+    listen: F,
+    is_listening: fn() -> bool,
+    exit: ExitListen,
+) -> Result<ListenHandle, ListenError>
+where
+    F: FnOnce() -> Result<(), ListenError> + Send + 'static,
+{
+    let _starting = STARTING.lock();
+    if is_listening() {
+        return Err(ListenError::AlreadyListening);
+    }
+    let thread = thread::spawn(listen);
+    loop {
+        if is_listening() {
+            return Ok(ListenHandle {
+                exit,
+                thread: Some(thread),
+            });
+        }
+        if thread.is_finished() {
+            return match thread.join() {
+                Ok(Err(err)) => Err(err),
+                // Stopped by `exit_listen` before it got going.
+                Ok(Ok(())) => Ok(ListenHandle { exit, thread: None }),
+                Err(payload) => std::panic::resume_unwind(payload),
+            };
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdev::{Button, Key};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::UNIX_EPOCH;
+
+    fn mouse_move(x: f64, millis: u64) -> Event {
+        Event::at(EventType::MouseMove { x, y: 0.0 }, millis)
+    }
+
+    #[test]
+    fn test_accepts() {
+        let config = ListenerBuilder::new()
+            .keys(true)
+            .buttons(true)
+            .motion(false)
+            .wheel(false)
+            .raw(true);
+        assert!(config.accepts(&EventType::KeyPress(Key::KeyA)));
+        assert!(config.accepts(&EventType::KeyPressRaw(Key::KeyA)));
+        assert!(config.accepts(&EventType::ButtonReleaseRaw(Button::Left)));
+        assert!(!config.accepts(&EventType::MouseMove { x: 0.0, y: 0.0 }));
+        assert!(!config.accepts(&EventType::MouseMoveRaw {
+            delta_x: 1,
+            delta_y: 1
+        }));
+        let config = config.raw(false);
+        assert!(config.accepts(&EventType::ButtonPress(Button::Left)));
+        assert!(!config.accepts(&EventType::ButtonPressRaw(Button::Left)));
+    }
+
+    #[test]
+    fn test_coalesce_motion() {
+        let mut coalescer = MotionCoalescer::new(Duration::from_millis(10));
+        let mut delivered = Vec::new();
+        let mut callback = |event: Event| delivered.push(event.event_type);
+        for (x, millis) in [(1.0, 0), (2.0, 4), (3.0, 8), (4.0, 12), (5.0, 15)] {
+            coalescer.feed(mouse_move(x, millis), &mut callback);
+        }
+        coalescer.feed(Event::at(EventType::KeyPress(Key::KeyA), 16), &mut callback);
+        assert_eq!(
+            delivered,
+            vec![
+                EventType::MouseMove { x: 1.0, y: 0.0 },
+                EventType::MouseMove { x: 4.0, y: 0.0 },
+                EventType::MouseMove { x: 5.0, y: 0.0 },
+                EventType::KeyPress(Key::KeyA),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flush_motion() {
+        let mut coalescer = MotionCoalescer::new(Duration::from_millis(10));
+        let mut delivered = Vec::new();
+        let mut callback = |event: Event| delivered.push(event.event_type);
+        coalescer.feed(mouse_move(1.0, 0), &mut callback);
+        coalescer.feed(mouse_move(2.0, 4), &mut callback);
+        assert_eq!(
+            coalescer.deadline(),
+            Some(UNIX_EPOCH + Duration::from_millis(10))
+        );
+        coalescer.flush(UNIX_EPOCH + Duration::from_millis(9), &mut callback);
+        coalescer.flush(UNIX_EPOCH + Duration::from_millis(10), &mut callback);
+        assert_eq!(coalescer.deadline(), None);
+        coalescer.feed(Event::at(EventType::KeyPress(Key::KeyA), 30), &mut callback);
+        assert_eq!(
+            delivered,
+            vec![
+                EventType::MouseMove { x: 1.0, y: 0.0 },
+                EventType::MouseMove { x: 2.0, y: 0.0 },
+                EventType::KeyPress(Key::KeyA),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_coalesce_motion_timer() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut callback = coalesce_motion(Duration::from_millis(20), move |event: Event| {
+            let _ = sender.send(event.event_type);
+        });
+        let now = SystemTime::now();
+        for x in [1.0, 2.0, 3.0] {
+            let mut event = mouse_move(x, 0);
+            event.time = now;
+            callback(event);
+        }
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            receiver.recv_timeout(timeout),
+            Ok(EventType::MouseMove { x: 1.0, y: 0.0 })
+        );
+        // Nothing else comes, the last position still does.
+        assert_eq!(
+            receiver.recv_timeout(timeout),
+            Ok(EventType::MouseMove { x: 3.0, y: 0.0 })
+        );
+    }
+
+    static RUNNING: AtomicBool = AtomicBool::new(false);
+    static EXITS: AtomicUsize = AtomicUsize::new(0);
+
+    fn is_running() -> bool {
+        RUNNING.load(Ordering::SeqCst)
+    }
+
+    fn exit() -> Result<(), ListenError> {
+        EXITS.fetch_add(1, Ordering::SeqCst);
+        RUNNING.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn run() -> Result<(), ListenError> {
+        RUNNING.store(true, Ordering::SeqCst);
+        while is_running() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_spawn_listen() {
+        let handle = spawn_listen(run, is_running, exit).unwrap();
+        assert!(matches!(
+            spawn_listen(run, is_running, exit),
+            Err(ListenError::AlreadyListening)
+        ));
+        // Stopped from elsewhere, the handle must not stop the next one.
+        exit().unwrap();
+        while !handle.thread.as_ref().unwrap().is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let next = spawn_listen(run, is_running, exit).unwrap();
+        handle.stop().unwrap();
+        assert!(is_running());
+        assert_eq!(EXITS.load(Ordering::SeqCst), 1);
+        next.stop().unwrap();
+        assert_eq!(EXITS.load(Ordering::SeqCst), 2);
+
+        // Exited before it started listening.
+        let handle = spawn_listen(|| Ok(()), is_running, exit).unwrap();
+        handle.stop().unwrap();
+        assert_eq!(EXITS.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,7 +1,7 @@
 #![allow(clippy::upper_case_acronyms)]
 use crate::keycodes::macos::virtual_keycodes::*;
 use crate::macos::keyboard::Keyboard;
-use crate::rdev::{Button, Event, EventType, Key};
+use crate::rdev::{Button, Event, EventType, Key, Modifiers};
 use objc2_core_graphics::{
     CGEvent, CGEventField, CGEventFlags, CGEventSource, CGEventSourceStateID, CGEventType,
     CGKeyCode,
@@ -82,12 +82,51 @@ unsafe fn get_code(cg_event: &CGEvent) -> Option<CGKeyCode> {
         .ok()
 }
 
+// NX_DEVICELALTKEYMASK and NX_DEVICERALTKEYMASK, which tell the Option keys
+// apart. Synthetic events often have neither.
+const LEFT_OPTION_MASK: u64 = 0x20;
+const RIGHT_OPTION_MASK: u64 = 0x40;
+
+fn modifiers_from_flags(flags: CGEventFlags) -> Modifiers {
+    let masks = [
+        (CGEventFlags::MaskShift, Modifiers::SHIFT),
+        (CGEventFlags::MaskControl, Modifiers::CTRL),
+        (CGEventFlags::MaskCommand, Modifiers::META),
+        (CGEventFlags::MaskAlphaShift, Modifiers::CAPS_LOCK),
+    ];
+    let mut modifiers = masks
+        .into_iter()
+        .filter(|(mask, _)| flags.0 & mask.0 != 0)
+        .fold(Modifiers::empty(), |modifiers, (_, modifier)| {
+            modifiers | modifier
+        });
+    if flags.0 & CGEventFlags::MaskAlternate.0 != 0 {
+        if flags.0 & RIGHT_OPTION_MASK != 0 {
+            modifiers |= Modifiers::ALT_GR;
+        }
+        if flags.0 & LEFT_OPTION_MASK != 0 || flags.0 & RIGHT_OPTION_MASK == 0 {
+            modifiers |= Modifiers::ALT;
+        }
+    }
+    modifiers
+}
+
+/// The button of an `OtherMouse` event, from its button number.
+fn button_from_number(button_num: i64) -> Button {
+    match button_num {
+        2 => Button::Middle,
+        3 => Button::Back,
+        4 => Button::Forward,
+        _ => Button::Unknown(button_num as u8),
+    }
+}
+
 /// Convert a CGEvent to rdev Events
 /// Returns a Vec because we emit both raw events and absolute events
 pub unsafe fn convert(
     _type: CGEventType,
     cg_event: NonNull<CGEvent>,
-    keyboard_state: &mut Keyboard,
+    keyboard_state: Option<&mut Keyboard>,
 ) -> Vec<Event> {
     unsafe {
         let cg_event_ref = cg_event.as_ref();
@@ -110,6 +149,14 @@ pub unsafe fn convert(
         // This catches events where source info is lost after posting to HID
         let is_synthetic = is_synthetic_by_source || extra_data == 100;
 
+        // The flags of FlagsChanged already count the key that changed them.
+        let flags = if _type == CGEventType::FlagsChanged {
+            CGEventFlags(LAST_FLAGS.load(Ordering::Acquire))
+        } else {
+            CGEvent::flags(Some(cg_event_ref))
+        };
+        let modifiers = modifiers_from_flags(flags);
+
         match _type {
             CGEventType::LeftMouseDown => {
                 // Raw event
@@ -122,6 +169,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
                 // Absolute event
                 events.push(Event {
@@ -133,6 +181,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
             }
             CGEventType::LeftMouseUp => {
@@ -145,6 +194,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
                 events.push(Event {
                     event_type: EventType::ButtonRelease(Button::Left),
@@ -155,6 +205,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
             }
             CGEventType::RightMouseDown => {
@@ -167,6 +218,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
                 events.push(Event {
                     event_type: EventType::ButtonPress(Button::Right),
@@ -177,6 +229,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
             }
             CGEventType::RightMouseUp => {
@@ -189,6 +242,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
                 events.push(Event {
                     event_type: EventType::ButtonRelease(Button::Right),
@@ -199,6 +253,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
             }
             CGEventType::OtherMouseDown => {
@@ -206,11 +261,7 @@ pub unsafe fn convert(
                     Some(cg_event_ref),
                     CGEventField::MouseEventButtonNumber,
                 );
-                let button = if button_num == 2 {
-                    Button::Middle
-                } else {
-                    Button::Unknown(button_num as u8)
-                };
+                let button = button_from_number(button_num);
                 events.push(Event {
                     event_type: EventType::ButtonPressRaw(button),
                     time,
@@ -220,6 +271,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
                 events.push(Event {
                     event_type: EventType::ButtonPress(button),
@@ -230,6 +282,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
             }
             CGEventType::OtherMouseUp => {
@@ -237,11 +290,7 @@ pub unsafe fn convert(
                     Some(cg_event_ref),
                     CGEventField::MouseEventButtonNumber,
                 );
-                let button = if button_num == 2 {
-                    Button::Middle
-                } else {
-                    Button::Unknown(button_num as u8)
-                };
+                let button = button_from_number(button_num);
                 events.push(Event {
                     event_type: EventType::ButtonReleaseRaw(button),
                     time,
@@ -251,6 +300,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
                 events.push(Event {
                     event_type: EventType::ButtonRelease(button),
@@ -261,6 +311,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
             }
             CGEventType::MouseMoved
@@ -285,6 +336,7 @@ pub unsafe fn convert(
                         usb_hid: 0,
                         extra_data,
                         is_synthetic,
+                        modifiers,
                     });
                 }
                 // Absolute position
@@ -301,6 +353,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
             }
             CGEventType::KeyDown => {
@@ -310,11 +363,12 @@ pub unsafe fn convert(
                     #[allow(non_upper_case_globals)]
                     let skip_unicode =
                         matches!(code, kVK_Shift | kVK_RightShift | kVK_ForwardDelete);
-                    let unicode = if skip_unicode {
-                        None
-                    } else {
-                        let flags = CGEvent::flags(Some(cg_event_ref));
-                        keyboard_state.create_unicode_for_key(key_code, flags)
+                    let unicode = match keyboard_state {
+                        Some(keyboard_state) if !skip_unicode => {
+                            let flags = CGEvent::flags(Some(cg_event_ref));
+                            keyboard_state.create_unicode_for_key(key_code, flags)
+                        }
+                        _ => None,
                     };
                     // Raw event
                     events.push(Event {
@@ -326,6 +380,7 @@ pub unsafe fn convert(
                         usb_hid: 0,
                         extra_data,
                         is_synthetic,
+                        modifiers,
                     });
                     // Regular event
                     events.push(Event {
@@ -337,6 +392,7 @@ pub unsafe fn convert(
                         usb_hid: 0,
                         extra_data,
                         is_synthetic,
+                        modifiers,
                     });
                 }
             }
@@ -353,6 +409,7 @@ pub unsafe fn convert(
                         usb_hid: 0,
                         extra_data,
                         is_synthetic,
+                        modifiers,
                     });
                     // Regular event
                     events.push(Event {
@@ -364,6 +421,7 @@ pub unsafe fn convert(
                         usb_hid: 0,
                         extra_data,
                         is_synthetic,
+                        modifiers,
                     });
                 }
             }
@@ -394,6 +452,7 @@ pub unsafe fn convert(
                         usb_hid: 0,
                         extra_data,
                         is_synthetic,
+                        modifiers,
                     });
                     // Regular event
                     events.push(Event {
@@ -405,6 +464,7 @@ pub unsafe fn convert(
                         usb_hid: 0,
                         extra_data,
                         is_synthetic,
+                        modifiers,
                     });
                 }
             }
@@ -430,6 +490,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
                 // Absolute wheel event (for compatibility)
                 events.push(Event {
@@ -444,6 +505,7 @@ pub unsafe fn convert(
                     usb_hid: 0,
                     extra_data,
                     is_synthetic,
+                    modifiers,
                 });
             }
             _ => {}
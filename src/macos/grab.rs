@@ -4,13 +4,13 @@ use crate::rdev::{Event, GrabError};
 use parking_lot::Mutex;
 use std::ffi::c_void;
 use std::ptr::{null, null_mut};
-use std::sync::OnceLock;
 use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
 use tracing::{debug, error, warn};
 
-type GrabCallbackType = Mutex<Box<dyn FnMut(Event) -> Option<Event> + Send>>;
+type GrabCallbackType = Box<dyn FnMut(Event) -> Option<Event> + Send>;
 
-static GLOBAL_CALLBACK: OnceLock<GrabCallbackType> = OnceLock::new();
+// Cleared when the grab ends, so that it can be started again.
+static GLOBAL_CALLBACK: Mutex<Option<GrabCallbackType>> = Mutex::new(None);
 static IS_GRABBED: AtomicBool = AtomicBool::new(false);
 static EVENT_TAP: AtomicPtr<c_void> = AtomicPtr::new(null_mut());
 static RUN_LOOP: AtomicPtr<c_void> = AtomicPtr::new(null_mut());
@@ -44,6 +44,8 @@ unsafe extern "C" {
     fn CFRunLoopRun();
     fn CFRunLoopStop(rl: *mut c_void);
     fn CFMachPortIsValid(port: *const c_void) -> bool;
+    fn CFMachPortInvalidate(port: *mut c_void);
+    fn CFRelease(cf: *const c_void);
 
     static kCFRunLoopCommonModes: *const c_void;
 }
@@ -126,13 +128,12 @@ unsafe extern "C" fn raw_callback(
 
         let mut guard = KEYBOARD_STATE.lock();
         if let Some(keyboard) = guard.as_mut() {
-            let events = unsafe { convert(cg_event_type, cg_event_ptr, keyboard) };
+            let events = unsafe { convert(cg_event_type, cg_event_ptr, Some(keyboard)) };
             drop(guard); // Release lock before calling user callback
 
             // Check if any event should be blocked
             let mut should_block = false;
-            if let Some(callback_mutex) = GLOBAL_CALLBACK.get() {
-                let mut callback = callback_mutex.lock();
+            if let Some(callback) = GLOBAL_CALLBACK.lock().as_mut() {
                 for ev in events {
                     if callback(ev).is_none() {
                         should_block = true;
@@ -173,7 +174,7 @@ pub fn is_grabbed() -> bool {
 /// - Accessibility permission is not granted
 /// - Failed to create the event tap
 ///
-/// Returns `GrabError::AlreadyGrabbing` if grab() was already called.
+/// Returns `GrabError::AlreadyGrabbing` if a grab is already running.
 pub fn grab<T>(callback: T) -> Result<(), GrabError>
 where
     T: FnMut(Event) -> Option<Event> + Send + 'static,
@@ -182,13 +183,23 @@ where
         return Ok(());
     }
 
-    // Initialize callback - only one grab allowed
-    if GLOBAL_CALLBACK.set(Mutex::new(Box::new(callback))).is_err() {
-        error!("grab() called multiple times - only one grab allowed");
-        return Err(GrabError::AlreadyGrabbing);
+    // Only one grab allowed at a time
+    {
+        let mut global_callback = GLOBAL_CALLBACK.lock();
+        if global_callback.is_some() {
+            error!("grab() called multiple times - only one grab allowed");
+            return Err(GrabError::AlreadyGrabbing);
+        }
+        *global_callback = Some(Box::new(callback));
     }
     debug!("Callback registered");
 
+    let result = run_grab();
+    *GLOBAL_CALLBACK.lock() = None;
+    result
+}
+
+fn run_grab() -> Result<(), GrabError> {
     // Check Accessibility permission (required for mouse events and modifier keys)
     let is_trusted = unsafe { AXIsProcessTrusted() };
     if !is_trusted {
@@ -259,6 +270,14 @@ where
         CFRunLoopRun();
 
         IS_GRABBED.store(false, Ordering::SeqCst);
+        // Otherwise the tap keeps intercepting events nobody services
+        // anymore, and a later grab would stack a second one.
+        CGEventTapEnable(tap, false);
+        EVENT_TAP.store(null_mut(), Ordering::Release);
+        RUN_LOOP.store(null_mut(), Ordering::Release);
+        CFMachPortInvalidate(tap);
+        CFRelease(source);
+        CFRelease(tap);
     }
 
     Ok(())
@@ -1,16 +1,20 @@
 #![allow(improper_ctypes_definitions)]
+use crate::listen::ListenerBuilder;
 use crate::macos::common::{convert, KEYBOARD_STATE};
 use crate::rdev::{Event, ListenError};
 use crossbeam_channel::{Sender, unbounded};
 use objc2_core_graphics::{CGEvent, CGEventType};
+use parking_lot::Mutex;
 use std::ffi::c_void;
 use std::ptr::{null, null_mut, NonNull};
-use std::sync::OnceLock;
-use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
 use tracing::{debug, error, warn};
 
-static EVENT_SENDER: OnceLock<Sender<Event>> = OnceLock::new();
+// Dropped when listen returns, which ends the callback thread.
+static EVENT_SENDER: Mutex<Option<Sender<Event>>> = Mutex::new(None);
 static EVENT_TAP: AtomicPtr<c_void> = AtomicPtr::new(null_mut());
+static RUN_LOOP: AtomicPtr<c_void> = AtomicPtr::new(null_mut());
+static UNICODE: AtomicBool = AtomicBool::new(true);
 
 #[link(name = "CoreGraphics", kind = "framework")]
 unsafe extern "C" {
@@ -24,7 +28,10 @@ unsafe extern "C" {
     fn CFRunLoopGetCurrent() -> *mut c_void;
     fn CFRunLoopAddSource(rl: *mut c_void, src: *mut c_void, mode: *const c_void);
     fn CFRunLoopRun();
+    fn CFRunLoopStop(rl: *mut c_void);
     fn CFMachPortIsValid(port: *const c_void) -> bool;
+    fn CFMachPortInvalidate(port: *mut c_void);
+    fn CFRelease(cf: *const c_void);
     static kCFRunLoopCommonModes: *const c_void;
 }
 
@@ -50,27 +57,55 @@ unsafe extern "C" fn tap_callback(_: *mut c_void, event_type: u32, event: *mut c
 
     if event_type == 0 || event.is_null() { return event; }
 
-    let sender = match EVENT_SENDER.get() { Some(s) => s, None => return event };
+    let guard = EVENT_SENDER.lock();
+    let sender = match guard.as_ref() { Some(s) => s, None => return event };
 
     if let Some(cg_event) = NonNull::new(event as *mut CGEvent) {
-        let mut guard = KEYBOARD_STATE.lock();
-        if let Some(kb) = guard.as_mut() {
-            for ev in unsafe { convert(CGEventType(event_type), cg_event, kb) } {
-                let _ = sender.send(ev);
+        let events = if UNICODE.load(Ordering::Relaxed) {
+            match KEYBOARD_STATE.lock().as_mut() {
+                Some(kb) => unsafe { convert(CGEventType(event_type), cg_event, Some(kb)) },
+                None => return event,
             }
-        }
+        } else {
+            unsafe { convert(CGEventType(event_type), cg_event, None) }
+        };
+        for ev in events { let _ = sender.send(ev); }
     }
     event
 }
 
-pub fn listen<T: FnMut(Event) + Send + 'static>(mut user_callback: T) -> Result<(), ListenError> {
-    let mask: u64 = if crate::keyboard_only() { (1 << 10) | (1 << 11) | (1 << 12) } else { !0 };
-
+pub fn listen<T: FnMut(Event) + Send + 'static>(config: &ListenerBuilder, mut user_callback: T) -> Result<(), ListenError> {
     let (tx, rx) = unbounded();
-    if EVENT_SENDER.set(tx).is_err() { return Err(ListenError::AlreadyListening); }
+    {
+        let mut sender = EVENT_SENDER.lock();
+        if sender.is_some() { return Err(ListenError::AlreadyListening); }
+        *sender = Some(tx);
+    }
 
-    std::thread::spawn(move || { while let Ok(ev) = rx.recv() { user_callback(ev); } });
+    let thread = std::thread::spawn(move || { while let Ok(ev) = rx.recv() { user_callback(ev); } });
+    UNICODE.store(config.unicode, Ordering::Relaxed);
+    let result = run_listen(event_mask(config));
+    *EVENT_SENDER.lock() = None;
+    let _ = thread.join();
+    result
+}
+
+/// The event tap mask for the enabled event classes, the raw events come from the same CGEvents.
+fn event_mask(config: &ListenerBuilder) -> u64 {
+    let bits = |types: &[u64]| types.iter().fold(0u64, |mask, t| mask | (1u64 << t));
+    let mut mask = 0;
+    // KeyDown, KeyUp, FlagsChanged
+    if config.keys { mask |= bits(&[10, 11, 12]); }
+    // Left, right and other mouse down and up
+    if config.buttons { mask |= bits(&[1, 2, 3, 4, 25, 26]); }
+    // MouseMoved and the dragged variants
+    if config.motion { mask |= bits(&[5, 6, 7, 27]); }
+    // ScrollWheel
+    if config.wheel { mask |= bits(&[22]); }
+    mask
+}
 
+fn run_listen(mask: u64) -> Result<(), ListenError> {
     if !unsafe { AXIsProcessTrusted() } {
         error!("Accessibility permission not granted");
         return Err(ListenError::EventTapError);
@@ -87,12 +122,37 @@ pub fn listen<T: FnMut(Event) + Send + 'static>(mut user_callback: T) -> Result<
         EVENT_TAP.store(tap, Ordering::Release);
 
         let src = CFMachPortCreateRunLoopSource(null(), tap, 0);
-        if src.is_null() { return Err(ListenError::LoopSourceError); }
+        if src.is_null() {
+            EVENT_TAP.store(null_mut(), Ordering::Release);
+            CFMachPortInvalidate(tap);
+            CFRelease(tap);
+            return Err(ListenError::LoopSourceError);
+        }
 
-        CFRunLoopAddSource(CFRunLoopGetCurrent(), src, kCFRunLoopCommonModes);
+        let run_loop = CFRunLoopGetCurrent();
+        CFRunLoopAddSource(run_loop, src, kCFRunLoopCommonModes);
         CGEventTapEnable(tap, true);
+        RUN_LOOP.store(run_loop, Ordering::Release);
         debug!("Event tap started (ListenOnly mode)");
+        // Blocks until exit_listen stops the run loop
         CFRunLoopRun();
+
+        RUN_LOOP.store(null_mut(), Ordering::Release);
+        CGEventTapEnable(tap, false);
+        EVENT_TAP.store(null_mut(), Ordering::Release);
+        CFMachPortInvalidate(tap);
+        CFRelease(src);
+        CFRelease(tap);
     }
     Ok(())
 }
+
+#[inline]
+pub fn is_listening() -> bool { !RUN_LOOP.load(Ordering::Acquire).is_null() }
+
+/// Stops a running `listen`, which then returns `Ok(())`.
+pub fn exit_listen() -> Result<(), ListenError> {
+    let run_loop = RUN_LOOP.load(Ordering::Acquire);
+    if !run_loop.is_null() { unsafe { CFRunLoopStop(run_loop) }; }
+    Ok(())
+}
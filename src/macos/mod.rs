@@ -9,7 +9,8 @@ pub use crate::macos::common::map_keycode;
 pub use crate::macos::display::display_size;
 pub use crate::macos::grab::{exit_grab, grab, is_grabbed};
 pub use crate::macos::keyboard::Keyboard;
-pub use crate::macos::listen::listen;
+pub use crate::macos::listen::{exit_listen, is_listening, listen};
 pub use crate::macos::simulate::{
-    VirtualInput, set_keyboard_extra_info, set_mouse_extra_info, simulate,
+    Simulator, VirtualInput, set_keyboard_extra_info, set_mouse_extra_info, simulate,
 };
+pub(crate) use crate::macos::simulate::{type_layout, type_unicode};
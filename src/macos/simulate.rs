@@ -113,26 +113,21 @@ unsafe fn convert_native_with_source(
             },
             EventType::ButtonPress(button) => {
                 let point = get_current_mouse_location()?;
-                let event_type = match button {
-                    Button::Left => CGEventType::LeftMouseDown,
-                    Button::Right => CGEventType::RightMouseDown,
-                    _ => return None,
+                let (event_type, mouse_button) = match button {
+                    Button::Left => (CGEventType::LeftMouseDown, CGMouseButton::Left),
+                    Button::Right => (CGEventType::RightMouseDown, CGMouseButton::Right),
+                    _ => (CGEventType::OtherMouseDown, other_mouse_button(*button)),
                 };
-                CGEvent::new_mouse_event(
-                    Some(source),
-                    event_type,
-                    point,
-                    CGMouseButton::Left, // ignored because we don't use OtherMouse EventType
-                )
+                CGEvent::new_mouse_event(Some(source), event_type, point, mouse_button)
             }
             EventType::ButtonRelease(button) => {
                 let point = get_current_mouse_location()?;
-                let event_type = match button {
-                    Button::Left => CGEventType::LeftMouseUp,
-                    Button::Right => CGEventType::RightMouseUp,
-                    _ => return None,
+                let (event_type, mouse_button) = match button {
+                    Button::Left => (CGEventType::LeftMouseUp, CGMouseButton::Left),
+                    Button::Right => (CGEventType::RightMouseUp, CGMouseButton::Right),
+                    _ => (CGEventType::OtherMouseUp, other_mouse_button(*button)),
                 };
-                CGEvent::new_mouse_event(Some(source), event_type, point, CGMouseButton::Left)
+                CGEvent::new_mouse_event(Some(source), event_type, point, mouse_button)
             }
             EventType::MouseMove { x, y } => {
                 let point = CGPoint { x: *x, y: *y };
@@ -175,6 +170,16 @@ unsafe fn convert_native(event_type: &EventType) -> Option<CFRetained<CGEvent>>
     }
 }
 
+/// The button number of an `OtherMouse` event, the inverse of what listening decodes.
+fn other_mouse_button(button: Button) -> CGMouseButton {
+    match button {
+        Button::Back => CGMouseButton(3),
+        Button::Forward => CGMouseButton(4),
+        Button::Unknown(code) => CGMouseButton(code.into()),
+        Button::Left | Button::Right | Button::Middle => CGMouseButton::Center,
+    }
+}
+
 unsafe fn get_current_mouse_location() -> Option<CGPoint> {
     let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)?;
     let event = CGEvent::new(Some(&source))?;
@@ -186,18 +191,72 @@ unsafe extern "C" {}
 
 pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
     if let Some(cg_event) = unsafe { convert_native(event_type) } {
-        CGEvent::set_integer_value_field(
-            Some(&cg_event),
-            CGEventField::EventSourceUserData,
-            MOUSE_EXTRA_INFO.load(Ordering::Relaxed),
-        );
-        CGEvent::post(CGEventTapLocation::HIDEventTap, Some(&cg_event));
+        post(&cg_event);
         Ok(())
     } else {
         Err(SimulateError)
     }
 }
 
+fn post(cg_event: &CGEvent) {
+    CGEvent::set_integer_value_field(
+        Some(cg_event),
+        CGEventField::EventSourceUserData,
+        MOUSE_EXTRA_INFO.load(Ordering::Relaxed),
+    );
+    CGEvent::post(CGEventTapLocation::HIDEventTap, Some(cg_event));
+}
+
+/// Simulates events like `simulate`, with a single event source.
+pub struct Simulator {
+    source: CFRetained<CGEventSource>,
+}
+
+impl Simulator {
+    pub fn new() -> Result<Simulator, SimulateError> {
+        // Private, so that the events are detected as synthetic.
+        let source = CGEventSource::new(CGEventSourceStateID::Private).ok_or(SimulateError)?;
+        Ok(Simulator { source })
+    }
+
+    pub fn simulate(&self, event_type: &EventType) -> Result<(), SimulateError> {
+        let cg_event = unsafe { convert_native_with_source(event_type, &self.source) }
+            .ok_or(SimulateError)?;
+        post(&cg_event);
+        Ok(())
+    }
+
+    /// Simulates the events in order, stopping at the first one that cannot
+    /// be simulated.
+    pub fn simulate_all(&self, events: &[EventType]) -> Result<(), SimulateError> {
+        events.iter().try_for_each(|event| self.simulate(event))
+    }
+}
+
+/// There is no lookup of the keys typing a character yet.
+pub(crate) fn type_layout(_text: &str) -> Result<(), SimulateError> {
+    Err(SimulateError)
+}
+
+/// Types `text` as the string of a key press, whatever the key.
+pub(crate) fn type_unicode(text: &str) -> Result<(), SimulateError> {
+    let units: Vec<u16> = text.encode_utf16().collect();
+    let source = CGEventSource::new(CGEventSourceStateID::Private).ok_or(SimulateError)?;
+    for pressed in [true, false] {
+        let event = CGEvent::new_keyboard_event(Some(&source), 0, pressed).ok_or(SimulateError)?;
+        unsafe {
+            CGEvent::keyboard_set_unicode_string(Some(&event), units.len() as _, units.as_ptr())
+        };
+        CGEvent::set_integer_value_field(
+            Some(&event),
+            CGEventField::EventSourceUserData,
+            KEYBOARD_EXTRA_INFO.load(Ordering::Relaxed),
+        );
+        CGEvent::post(CGEventTapLocation::HIDEventTap, Some(&event));
+    }
+    Ok(())
+}
+
 pub struct VirtualInput {
     source: CFRetained<CGEventSource>,
     tap_loc: CGEventTapLocation,
@@ -0,0 +1,255 @@
+use crate::keycodes::chrome::code_from_key;
+use crate::rdev::{Button, Key, KeyCode, ParseNameError, RawKey};
+use std::fmt;
+use std::str::FromStr;
+use strum::IntoEnumIterator;
+
+// Accepted on top of the canonical and variant names, without regard to case.
+const KEY_ALIASES: &[(&str, Key)] = &[
+    ("Ctrl", Key::ControlLeft),
+    ("Control", Key::ControlLeft),
+    ("Shift", Key::ShiftLeft),
+    ("Option", Key::Alt),
+    ("Meta", Key::MetaLeft),
+    ("Cmd", Key::MetaLeft),
+    ("Command", Key::MetaLeft),
+    ("Super", Key::MetaLeft),
+    ("Win", Key::MetaLeft),
+    ("Windows", Key::MetaLeft),
+    ("Esc", Key::Escape),
+    ("Del", Key::Delete),
+    ("Ins", Key::Insert),
+    ("Menu", Key::Apps),
+    ("Up", Key::UpArrow),
+    ("Down", Key::DownArrow),
+    ("Left", Key::LeftArrow),
+    ("Right", Key::RightArrow),
+];
+
+/// The canonical name of a key: its W3C `KeyboardEvent.code` when it has
+/// one, the name of the variant otherwise.
+fn key_name(key: Key) -> Option<&'static str> {
+    code_from_key(key).filter(|code| !code.is_empty())
+}
+
+fn named_keys() -> impl Iterator<Item = Key> {
+    Key::iter().filter(|key| !matches!(key, Key::Unknown(_) | Key::RawKey(_)))
+}
+
+impl fmt::Display for Key {
+    /// Writes the canonical name, which [`Key::from_str`] reads back:
+    /// `KeyA`, `Enter`, `AltRight`, `Unknown(42)`,
+    /// `RawKey(LinuxXorgKeycode(38))`...
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match key_name(*self) {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{self:?}"),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = ParseNameError;
+
+    /// Reads the canonical names, the variant names (`Return`, `Num1`), the
+    /// aliases like `Ctrl`, `Cmd` or `Super`, and single letters and digits,
+    /// all without regard to case. Aliases of modifiers give the left key.
+    fn from_str(s: &str) -> Result<Key, ParseNameError> {
+        let name = s.trim();
+        let error = || ParseNameError { name: s.to_owned() };
+        if let Some(code) = call_argument(name, "Unknown") {
+            return code.parse().map(Key::Unknown).map_err(|_| error());
+        }
+        if let Some(raw) = call_argument(name, "RawKey") {
+            return parse_raw_key(raw).map(Key::RawKey).ok_or_else(error);
+        }
+        if let Some(key) = named_keys().find(|key| {
+            key_name(*key).is_some_and(|code| code.eq_ignore_ascii_case(name))
+                || format!("{key:?}").eq_ignore_ascii_case(name)
+        }) {
+            return Ok(key);
+        }
+        if let Some((_, key)) = KEY_ALIASES
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+        {
+            return Ok(*key);
+        }
+        match name.as_bytes() {
+            [chr] if chr.is_ascii_alphanumeric() => {
+                let variant = if chr.is_ascii_digit() { "Num" } else { "Key" };
+                let name = format!("{variant}{}", chr.to_ascii_uppercase() as char);
+                named_keys()
+                    .find(|key| format!("{key:?}") == name)
+                    .ok_or_else(error)
+            }
+            _ => Err(error()),
+        }
+    }
+}
+
+fn parse_raw_key(name: &str) -> Option<RawKey> {
+    let (variant, _) = name.split_once('(')?;
+    let code: KeyCode = call_argument(name, variant)?.parse().ok()?;
+    let raw_key = match variant.to_ascii_lowercase().as_str() {
+        "scancode" => RawKey::ScanCode(code),
+        "winvirtualkeycode" => RawKey::WinVirtualKeycode(code),
+        "linuxxorgkeycode" => RawKey::LinuxXorgKeycode(code),
+        "linuxconsolekeycode" => RawKey::LinuxConsoleKeycode(code),
+        "macvirtualkeycode" => RawKey::MacVirtualKeycode(code),
+        _ => return None,
+    };
+    Some(raw_key)
+}
+
+/// `42` out of `Unknown(42)`.
+fn call_argument<'a>(name: &'a str, function: &str) -> Option<&'a str> {
+    let start = name.get(..function.len())?;
+    if !start.eq_ignore_ascii_case(function) {
+        return None;
+    }
+    let argument = name[function.len()..]
+        .strip_prefix('(')?
+        .strip_suffix(')')?;
+    Some(argument.trim())
+}
+
+impl fmt::Display for Button {
+    /// Writes `Left`, `Right`, `Middle`, `Back`, `Forward` or `Unknown(n)`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl FromStr for Button {
+    type Err = ParseNameError;
+
+    fn from_str(s: &str) -> Result<Button, ParseNameError> {
+        let name = s.trim();
+        let error = || ParseNameError { name: s.to_owned() };
+        if let Some(code) = call_argument(name, "Unknown") {
+            return code.parse().map(Button::Unknown).map_err(|_| error());
+        }
+        [
+            Button::Left,
+            Button::Right,
+            Button::Middle,
+            Button::Back,
+            Button::Forward,
+        ]
+        .into_iter()
+        .find(|button| format!("{button:?}").eq_ignore_ascii_case(name))
+        .ok_or_else(error)
+    }
+}
+
+/// Serializes a [`Key`] or a [`Button`] field as its name rather than as an
+/// enum, for config files:
+///
+/// ```
+/// use rdev::Key;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Binding {
+///     #[serde(with = "rdev::as_name")]
+///     key: Key,
+/// }
+/// ```
+#[cfg(feature = "serialize")]
+pub mod as_name {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let keys = Key::iter().chain([
+            Key::Unknown(42),
+            Key::RawKey(RawKey::LinuxXorgKeycode(38)),
+            Key::RawKey(RawKey::MacVirtualKeycode(12)),
+        ]);
+        for key in keys {
+            let name = key.to_string();
+            assert_eq!(name.parse::<Key>(), Ok(key), "{name}");
+            assert_eq!(name.to_lowercase().parse::<Key>(), Ok(key), "{name}");
+            assert_eq!(format!("{key:?}").parse::<Key>(), Ok(key), "{key:?}");
+        }
+        for button in [Button::Left, Button::Forward, Button::Unknown(8)] {
+            assert_eq!(button.to_string().parse::<Button>(), Ok(button));
+        }
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(Key::KeyA.to_string(), "KeyA");
+        assert_eq!(Key::Return.to_string(), "Enter");
+        assert_eq!(Key::AltGr.to_string(), "AltRight");
+        assert_eq!(Key::Pause.to_string(), "Pause");
+        assert_eq!(Key::Unknown(42).to_string(), "Unknown(42)");
+        assert_eq!(
+            Key::RawKey(RawKey::ScanCode(30)).to_string(),
+            "RawKey(ScanCode(30))"
+        );
+        assert_eq!(Button::Unknown(8).to_string(), "Unknown(8)");
+    }
+
+    #[test]
+    fn test_aliases() {
+        assert_eq!("Ctrl".parse(), Ok(Key::ControlLeft));
+        assert_eq!("cmd".parse(), Ok(Key::MetaLeft));
+        assert_eq!("Super".parse(), Ok(Key::MetaLeft));
+        assert_eq!("Return".parse(), Ok(Key::Return));
+        assert_eq!("a".parse(), Ok(Key::KeyA));
+        assert_eq!("4".parse(), Ok(Key::Num4));
+        assert_eq!(" Unknown( 7 ) ".parse(), Ok(Key::Unknown(7)));
+        assert!("Hyper".parse::<Key>().is_err());
+        assert!("Unknown(x)".parse::<Key>().is_err());
+        assert!("RawKey(Keycode(3))".parse::<Key>().is_err());
+        assert!("Wheel".parse::<Button>().is_err());
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn test_serde_names() {
+        #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+        struct Binding {
+            #[serde(with = "as_name")]
+            key: Key,
+            #[serde(with = "as_name")]
+            button: Button,
+        }
+        let binding = Binding {
+            key: Key::ControlLeft,
+            button: Button::Back,
+        };
+        let json = serde_json::to_string(&binding).unwrap();
+        assert_eq!(json, r#"{"key":"ControlLeft","button":"Back"}"#);
+        assert_eq!(serde_json::from_str::<Binding>(&json).unwrap(), binding);
+        let json = r#"{"key":"ctrl","button":"Unknown(9)"}"#;
+        assert!(serde_json::from_str::<Binding>(json).is_ok());
+    }
+}
@@ -29,10 +29,16 @@ pub enum ListenError {
     RecordContextError,
     /// Linux
     XRecordExtensionError,
+    /// Linux (evdev)
+    MissingDeviceError,
+    /// Linux (evdev)
+    IoError(std::io::Error),
     /// Windows
     KeyHookError(u32),
     /// Windows
     MouseHookError(u32),
+    /// All
+    ExitListenError(String),
     /// All platforms - listen() was already called
     AlreadyListening,
 }
@@ -56,6 +62,8 @@ pub enum GrabError {
     InvalidFileDescriptor,
     /// Linux
     KeyboardError,
+    /// Linux (evdev)
+    MissingDeviceError,
     /// Windows
     KeyHookError(u32),
     /// Windows
@@ -102,6 +110,63 @@ impl Display for SimulateError {
 
 impl std::error::Error for SimulateError {}
 
+/// The grapheme clusters `simulate_text` could not type, along with their
+/// byte offset in the text. The rest of the text was typed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextError {
+    pub untyped: Vec<(usize, String)>,
+}
+
+impl Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Could not type {} characters", self.untyped.len())
+    }
+}
+
+impl std::error::Error for TextError {}
+
+/// A name that is not the one of a [`Key`] or a [`Button`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseNameError {
+    pub name: String,
+}
+
+impl Display for ParseNameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown key or button name {:?}", self.name)
+    }
+}
+
+impl std::error::Error for ParseNameError {}
+
+/// Errors that occur when parsing an accelerator like `Ctrl+Alt+Delete`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseAcceleratorError {
+    Empty,
+    UnknownName(ParseNameError),
+    /// A key other than Control, Alt, Shift or Meta before the last `+`.
+    NotAModifier(Key),
+}
+
+impl From<ParseNameError> for ParseAcceleratorError {
+    fn from(error: ParseNameError) -> ParseAcceleratorError {
+        ParseAcceleratorError::UnknownName(error)
+    }
+}
+
+impl Display for ParseAcceleratorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseAcceleratorError::Empty => write!(f, "Empty accelerator"),
+            ParseAcceleratorError::UnknownName(error) => write!(f, "{error}"),
+            ParseAcceleratorError::NotAModifier(key) => write!(f, "{key} is not a modifier"),
+        }
+    }
+}
+
+impl std::error::Error for ParseAcceleratorError {}
+
 // Some keys from https://github.com/chromium/chromium/blob/main/ui/events/keycodes/dom/dom_code_data.inc
 
 /// Key names based on physical location on the device
@@ -296,7 +361,7 @@ impl Default for RawKey {
 }
 
 /// Standard mouse buttons
-/// Some mice have more than 3 buttons. These are not defined, and different
+/// Some mice have more buttons than these. Those are not defined, and different
 /// OSs will give different `Button::Unknown` values.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
@@ -304,6 +369,10 @@ pub enum Button {
     Left,
     Right,
     Middle,
+    /// The thumb button going back in browsers, X1 on Windows.
+    Back,
+    /// The thumb button going forward in browsers, X2 on Windows.
+    Forward,
     Unknown(u8),
 }
 
@@ -342,7 +411,7 @@ pub enum EventType {
 
     // Raw events:
     // These cannot be blocked by any user-mode application.
-    // Windows: Raw Input API, macOS: CGEventTap at HID level
+    // Windows: Raw Input API, macOS: CGEventTap at HID level, Linux: XInput2
     /// Raw mouse movement with relative deltas
     /// This is the hardware signal, unaffected by mouse acceleration or DPI settings.
     MouseMoveRaw {
@@ -353,20 +422,51 @@ pub enum EventType {
     ButtonPressRaw(Button),
     /// Raw button release event from hardware.
     ButtonReleaseRaw(Button),
-    /// Raw scroll/wheel event with deltas. From Raw Input (Windows) or
-    /// CGEventTap (macOS). Values are in scroll units (typically 1.0 = one line).
+    /// Raw scroll/wheel event with deltas. From Raw Input (Windows), CGEventTap
+    /// (macOS) or XInput2 (Linux). Values are in scroll units (typically 1.0 = one line).
     WheelRaw {
         delta_x: f64,
         delta_y: f64,
     },
     /// Raw key press event from hardware.
-    /// Windows: from Raw Input API, macOS: from CGEventTap at HID level.
+    /// Windows: from Raw Input API, macOS: from CGEventTap at HID level, Linux: from XInput2.
     KeyPressRaw(Key),
     /// Raw key release event from hardware.
-    /// Windows: from Raw Input API, macOS: from CGEventTap at HID level.
+    /// Windows: from Raw Input API, macOS: from CGEventTap at HID level, Linux: from XInput2.
     KeyReleaseRaw(Key),
 }
 
+bitflags::bitflags! {
+    /// Modifier keys held and lock keys on. Left and right keys are not told
+    /// apart.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+    pub struct Modifiers: u8 {
+        const SHIFT = 1;
+        const CTRL = 1 << 1;
+        const ALT = 1 << 2;
+        const ALT_GR = 1 << 3;
+        /// Windows, Super or Command.
+        const META = 1 << 4;
+        const CAPS_LOCK = 1 << 5;
+        const NUM_LOCK = 1 << 6;
+    }
+}
+
+impl Modifiers {
+    /// The modifier a key holds, if any. Lock keys toggle instead.
+    pub fn from_key(key: Key) -> Modifiers {
+        match key {
+            Key::ShiftLeft | Key::ShiftRight => Modifiers::SHIFT,
+            Key::ControlLeft | Key::ControlRight => Modifiers::CTRL,
+            Key::Alt => Modifiers::ALT,
+            Key::AltGr => Modifiers::ALT_GR,
+            Key::MetaLeft | Key::MetaRight => Modifiers::META,
+            _ => Modifiers::empty(),
+        }
+    }
+}
+
 /// The Unicode information of input.
 #[derive(Debug, Clone, PartialEq, Default)]
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
@@ -396,12 +496,45 @@ pub struct Event {
     /// Platform-specific extra data associated with the event.
     /// On Windows: dwExtraInfo from the hook structure (cast to i64)
     /// On macOS: CGEventGetIntegerValueField with kCGEventSourceUserData
+    /// On Linux (X11): what `set_*_extra_info` was set to when this process
+    /// simulated the event, 0 for the events of other processes
     pub extra_data: i64,
     /// True if this event was programmatically generated (synthetic/injected),
     /// false if it came from actual hardware input.
     /// On macOS: detected via CGEventSourceStateID != HIDSystemState
     /// On Windows: detected via LLKHF_INJECTED/LLMHF_INJECTED flags
+    /// On Linux (X11): the XInput2 source device is one of the XTEST devices.
+    /// Needs XInput2, and evdev does not tell
     pub is_synthetic: bool,
+    /// The modifiers held and the lock keys on when the event happened, not
+    /// counting the event itself: the press of Shift has no `SHIFT`, its
+    /// release does.
+    /// On Linux (X11): from the state of the core event, with the usual
+    /// modifier mapping (Mod1 for Alt, Mod2 for NumLock, Mod4 for Meta and
+    /// Mod5 for AltGr). Raw events have the state when they were read
+    /// On Linux (evdev): from the keys seen, the lock keys start off
+    /// On macOS: there is no NumLock
+    /// On Windows: the right Alt key is `ALT`, along with `ALT_GR` when the
+    /// layout has AltGr
+    pub modifiers: Modifiers,
+}
+
+#[cfg(test)]
+impl Event {
+    /// A bare event, `millis` after the epoch.
+    pub(crate) fn at(event_type: EventType, millis: u64) -> Event {
+        Event {
+            time: SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(millis),
+            unicode: None,
+            event_type,
+            platform_code: 0,
+            position_code: 0,
+            usb_hid: 0,
+            extra_data: 0,
+            is_synthetic: false,
+            modifiers: Modifiers::empty(),
+        }
+    }
 }
 
 /// We can define a dummy Keyboard, that we will use to detect
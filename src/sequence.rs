@@ -0,0 +1,479 @@
+use crate::hotkey::{Accelerator, is_modifier};
+use crate::rdev::{Event, EventType, Key, ParseAcceleratorError};
+use std::mem;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// One step of a [`Sequence`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stroke {
+    Accelerator(Accelerator),
+    /// All the keys, in any order, the last one at most `window` after the
+    /// first one. Modifiers pressed meanwhile do not matter.
+    Chord {
+        keys: Vec<Key>,
+        window: Duration,
+    },
+}
+
+/// Strokes to type one after the other, each one at most the timeout after
+/// the previous one.
+///
+/// Parsed from accelerators separated by spaces:
+///
+/// ```
+/// use rdev::Sequence;
+/// use std::time::Duration;
+///
+/// let save: Sequence = "Ctrl+X Ctrl+S".parse().unwrap();
+/// let leader: Sequence = "Space F F".parse().unwrap();
+/// let double_shift = "Shift Shift"
+///     .parse::<Sequence>()
+///     .unwrap()
+///     .timeout(Duration::from_millis(300));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequence {
+    strokes: Vec<Stroke>,
+    timeout: Option<Duration>,
+}
+
+impl Sequence {
+    pub fn new(strokes: Vec<Stroke>) -> Sequence {
+        Sequence {
+            strokes,
+            timeout: None,
+        }
+    }
+
+    /// A single chord, like `J` and `K` together within 50 ms.
+    pub fn chord(keys: &[Key], window: Duration) -> Sequence {
+        Sequence::new(vec![Stroke::Chord {
+            keys: keys.to_vec(),
+            window,
+        }])
+    }
+
+    /// Overrides the timeout of the [`SequenceRecognizer`] between the
+    /// strokes of this sequence.
+    pub fn timeout(mut self, timeout: Duration) -> Sequence {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn strokes(&self) -> &[Stroke] {
+        &self.strokes
+    }
+}
+
+impl FromStr for Sequence {
+    type Err = ParseAcceleratorError;
+
+    fn from_str(s: &str) -> Result<Sequence, ParseAcceleratorError> {
+        let strokes = s
+            .split_whitespace()
+            .map(|stroke| stroke.parse().map(Stroke::Accelerator))
+            .collect::<Result<Vec<_>, _>>()?;
+        if strokes.is_empty() {
+            return Err(ParseAcceleratorError::Empty);
+        }
+        Ok(Sequence::new(strokes))
+    }
+}
+
+/// Identifies a sequence of a [`SequenceRecognizer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SequenceId(u64);
+
+/// How far a sequence was typed.
+#[derive(Debug)]
+struct Progress {
+    id: SequenceId,
+    stroke: usize,
+    // The keys of the chord being typed, and when its first key was.
+    chord: Vec<Key>,
+    chord_start: SystemTime,
+    last_stroke: SystemTime,
+}
+
+enum Step {
+    Advanced,
+    /// Part of a chord.
+    Partial,
+    /// A modifier on the way to the next stroke.
+    Ignored,
+    Failed,
+}
+
+fn elapsed(since: SystemTime, now: SystemTime) -> Duration {
+    now.duration_since(since).unwrap_or_default()
+}
+
+/// Recognizes key sequences in the events it is fed, going by their `time`.
+///
+/// When a sequence is also the start of a longer one, like `Ctrl+X` and
+/// `Ctrl+X Ctrl+S`, it is only recognized once the longer ones can no longer
+/// match: another key was pressed, or the timeout elapsed. Call
+/// [`SequenceRecognizer::poll`] to notice the timeouts without waiting for
+/// the next event.
+///
+/// ```
+/// use rdev::{Sequence, SequenceRecognizer};
+///
+/// let mut sequences = SequenceRecognizer::new();
+/// let save = sequences.register("Ctrl+X Ctrl+S".parse().unwrap());
+/// // for event in events {
+/// //     if sequences.feed(&event).contains(&save) { ... }
+/// // }
+/// ```
+#[derive(Debug)]
+pub struct SequenceRecognizer {
+    sequences: Vec<(SequenceId, Sequence)>,
+    next_id: u64,
+    timeout: Duration,
+    held: Vec<Key>,
+    progress: Vec<Progress>,
+    // Typed sequences waiting for the longer ones they start.
+    pending: Vec<SequenceId>,
+}
+
+impl Default for SequenceRecognizer {
+    fn default() -> SequenceRecognizer {
+        SequenceRecognizer {
+            sequences: Vec::new(),
+            next_id: 0,
+            timeout: Duration::from_secs(1),
+            held: Vec::new(),
+            progress: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl SequenceRecognizer {
+    pub fn new() -> SequenceRecognizer {
+        SequenceRecognizer::default()
+    }
+
+    /// How long to wait for the next stroke of a sequence, one second by
+    /// default.
+    pub fn timeout(mut self, timeout: Duration) -> SequenceRecognizer {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn register(&mut self, sequence: Sequence) -> SequenceId {
+        let id = SequenceId(self.next_id);
+        self.next_id += 1;
+        self.sequences.push((id, sequence));
+        id
+    }
+
+    /// Returns false if the sequence was already unregistered.
+    pub fn unregister(&mut self, id: SequenceId) -> bool {
+        let len = self.sequences.len();
+        self.sequences.retain(|(registered, _)| *registered != id);
+        self.progress.retain(|progress| progress.id != id);
+        self.pending.retain(|pending| *pending != id);
+        self.sequences.len() != len
+    }
+
+    /// Whether the last keys are the start of a sequence.
+    pub fn in_progress(&self) -> bool {
+        !self.progress.is_empty()
+    }
+
+    /// Returns the sequences this event completes, or ends the wait for.
+    /// Key repeats are not strokes.
+    pub fn feed(&mut self, event: &Event) -> Vec<SequenceId> {
+        let mut recognized = self.poll(event.time);
+        match event.event_type {
+            EventType::KeyPress(key) if !self.held.contains(&key) => {
+                self.held.push(key);
+                recognized.extend(self.press(key, event.time));
+            }
+            EventType::KeyRelease(key) => self.held.retain(|held| *held != key),
+            _ => {}
+        }
+        recognized
+    }
+
+    /// Returns the sequences whose wait for a longer one timed out by `now`.
+    pub fn poll(&mut self, now: SystemTime) -> Vec<SequenceId> {
+        let timeout = self.timeout;
+        let sequences = &self.sequences;
+        self.progress.retain(|progress| {
+            let timeout = sequences
+                .iter()
+                .find(|(id, _)| *id == progress.id)
+                .and_then(|(_, sequence)| sequence.timeout)
+                .unwrap_or(timeout);
+            elapsed(progress.last_stroke, now) <= timeout
+        });
+        if self.progress.is_empty() {
+            mem::take(&mut self.pending)
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn press(&mut self, key: Key, time: SystemTime) -> Vec<SequenceId> {
+        let in_progress = self.in_progress();
+        let candidates = if in_progress {
+            mem::take(&mut self.progress)
+        } else {
+            self.sequences
+                .iter()
+                .map(|(id, _)| Progress {
+                    id: *id,
+                    stroke: 0,
+                    chord: Vec::new(),
+                    chord_start: time,
+                    last_stroke: time,
+                })
+                .collect()
+        };
+        let mut advanced = false;
+        let mut completed = Vec::new();
+        for mut progress in candidates {
+            let Some((_, sequence)) = self.sequences.iter().find(|(id, _)| *id == progress.id)
+            else {
+                continue;
+            };
+            let Some(stroke) = sequence.strokes.get(progress.stroke) else {
+                continue;
+            };
+            match step(stroke, &mut progress, &self.held, key, time) {
+                Step::Advanced => {
+                    advanced = true;
+                    progress.stroke += 1;
+                    progress.chord.clear();
+                    progress.last_stroke = time;
+                    if progress.stroke == sequence.strokes.len() {
+                        completed.push(progress.id);
+                    } else {
+                        self.progress.push(progress);
+                    }
+                }
+                Step::Partial => {
+                    advanced = true;
+                    self.progress.push(progress);
+                }
+                Step::Ignored if in_progress => self.progress.push(progress),
+                Step::Ignored | Step::Failed => {}
+            }
+        }
+        if !advanced {
+            if in_progress && self.progress.is_empty() {
+                // The key ends what was typed so far, and may start another sequence.
+                let mut recognized = mem::take(&mut self.pending);
+                recognized.extend(self.press(key, time));
+                return recognized;
+            }
+            return Vec::new();
+        }
+        if completed.is_empty() {
+            Vec::new()
+        } else if self.progress.is_empty() {
+            self.pending.clear();
+            completed
+        } else {
+            self.pending = completed;
+            Vec::new()
+        }
+    }
+}
+
+fn step(
+    stroke: &Stroke,
+    progress: &mut Progress,
+    held: &[Key],
+    key: Key,
+    time: SystemTime,
+) -> Step {
+    match stroke {
+        Stroke::Accelerator(accelerator) if accelerator.matches(held, key) => Step::Advanced,
+        Stroke::Chord { keys, window } if keys.contains(&key) && !progress.chord.contains(&key) => {
+            if progress.chord.is_empty() {
+                progress.chord_start = time;
+            } else if elapsed(progress.chord_start, time) > *window {
+                return Step::Failed;
+            }
+            progress.chord.push(key);
+            if progress.chord.len() == keys.len() {
+                Step::Advanced
+            } else {
+                Step::Partial
+            }
+        }
+        _ if is_modifier(key) => Step::Ignored,
+        _ => Step::Failed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Milliseconds after the epoch, far enough for the timeouts.
+    const START: u64 = 1_000_000_000;
+
+    /// Taps of keys given as accelerators, at the given times in
+    /// milliseconds, with what each tap recognized.
+    fn type_strokes(
+        sequences: &mut SequenceRecognizer,
+        strokes: &[(&str, u64)],
+    ) -> Vec<Vec<SequenceId>> {
+        strokes
+            .iter()
+            .map(|(stroke, millis)| {
+                let accelerator: Accelerator = stroke.parse().unwrap();
+                let mut recognized = Vec::new();
+                let mut keys = accelerator.modifiers().to_vec();
+                keys.push(accelerator.key());
+                for key in &keys {
+                    recognized.extend(
+                        sequences.feed(&Event::at(EventType::KeyPress(*key), START + *millis)),
+                    );
+                }
+                for key in keys.iter().rev() {
+                    recognized.extend(
+                        sequences.feed(&Event::at(EventType::KeyRelease(*key), START + *millis)),
+                    );
+                }
+                recognized
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let sequence: Sequence = "Ctrl+X  Ctrl+S".parse().unwrap();
+        assert_eq!(sequence.strokes().len(), 2);
+        assert_eq!(" ".parse::<Sequence>(), Err(ParseAcceleratorError::Empty));
+        assert_eq!(
+            "Ctrl+X S+Ctrl".parse::<Sequence>(),
+            Err(ParseAcceleratorError::NotAModifier(Key::KeyS))
+        );
+    }
+
+    #[test]
+    fn test_sequence() {
+        let mut sequences = SequenceRecognizer::new();
+        let save = sequences.register("Ctrl+X Ctrl+S".parse().unwrap());
+        let recognized = type_strokes(
+            &mut sequences,
+            &[
+                ("Ctrl+X", 0),
+                ("Ctrl+S", 500),
+                ("Ctrl+X", 1000),
+                ("Ctrl+S", 2500),
+            ],
+        );
+        assert_eq!(recognized, [vec![], vec![save], vec![], vec![]]);
+        assert!(!sequences.in_progress());
+    }
+
+    #[test]
+    fn test_prefix() {
+        let mut sequences = SequenceRecognizer::new();
+        let cut = sequences.register("Ctrl+X".parse().unwrap());
+        let save = sequences.register("Ctrl+X Ctrl+S".parse().unwrap());
+        let recognized = type_strokes(
+            &mut sequences,
+            &[("Ctrl+X", 0), ("Ctrl+S", 100), ("Ctrl+X", 200), ("A", 300)],
+        );
+        assert_eq!(recognized, [vec![], vec![save], vec![], vec![cut]]);
+        type_strokes(&mut sequences, &[("Ctrl+X", 1000)]);
+        let later = SystemTime::UNIX_EPOCH + Duration::from_millis(START + 1500);
+        assert_eq!(sequences.poll(later), []);
+        assert_eq!(sequences.poll(later + Duration::from_secs(1)), [cut]);
+    }
+
+    #[test]
+    fn test_restart() {
+        let mut sequences = SequenceRecognizer::new();
+        let leader = sequences.register("Space F F".parse().unwrap());
+        let recognized = type_strokes(
+            &mut sequences,
+            &[
+                ("Space", 0),
+                ("G", 100),
+                ("Space", 200),
+                ("Space", 300),
+                ("F", 400),
+                ("F", 500),
+            ],
+        );
+        assert_eq!(recognized[5], [leader]);
+    }
+
+    #[test]
+    fn test_double_tap() {
+        let mut sequences = SequenceRecognizer::new();
+        let double_shift = sequences.register(
+            "Shift Shift"
+                .parse::<Sequence>()
+                .unwrap()
+                .timeout(Duration::from_millis(300)),
+        );
+        let recognized = type_strokes(
+            &mut sequences,
+            &[
+                ("Shift", 0),
+                ("Shift", 500),
+                ("ShiftRight", 700),
+                ("Shift+A", 800),
+            ],
+        );
+        assert_eq!(recognized, [vec![], vec![], vec![double_shift], vec![]]);
+        // Repeats of a held key are not taps.
+        let repeats = [
+            EventType::KeyPress(Key::ShiftLeft),
+            EventType::KeyPress(Key::ShiftLeft),
+            EventType::KeyPress(Key::ShiftLeft),
+        ];
+        for (millis, event_type) in repeats.into_iter().enumerate() {
+            assert_eq!(
+                sequences.feed(&Event::at(event_type, START + 2000 + millis as u64)),
+                []
+            );
+        }
+    }
+
+    #[test]
+    fn test_chord() {
+        let mut sequences = SequenceRecognizer::new();
+        let escape = sequences.register(Sequence::chord(
+            &[Key::KeyJ, Key::KeyK],
+            Duration::from_millis(50),
+        ));
+        let events = [
+            (EventType::KeyPress(Key::KeyK), 0),
+            (EventType::KeyPress(Key::KeyJ), 30),
+            (EventType::KeyRelease(Key::KeyK), 60),
+            (EventType::KeyRelease(Key::KeyJ), 60),
+            (EventType::KeyPress(Key::KeyJ), 100),
+            (EventType::KeyPress(Key::KeyK), 200),
+            (EventType::KeyPress(Key::KeyJ), 300),
+        ];
+        let recognized: Vec<Vec<SequenceId>> = events
+            .into_iter()
+            .map(|(event_type, millis)| sequences.feed(&Event::at(event_type, START + millis)))
+            .collect();
+        assert_eq!(recognized[1], [escape]);
+        assert!(recognized[2..].iter().all(Vec::is_empty));
+    }
+
+    #[test]
+    fn test_unregister() {
+        let mut sequences = SequenceRecognizer::new();
+        let id = sequences.register("A B".parse().unwrap());
+        type_strokes(&mut sequences, &[("A", 0)]);
+        assert!(sequences.in_progress());
+        assert!(sequences.unregister(id));
+        assert!(!sequences.unregister(id));
+        assert!(!sequences.in_progress());
+        assert_eq!(type_strokes(&mut sequences, &[("B", 10)]), [vec![]]);
+    }
+}
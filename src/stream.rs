@@ -0,0 +1,75 @@
+use crate::broadcast::{Attachment, Sink, attach};
+use crate::rdev::{Event, EventType, ListenError, SimulateError};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use tokio::sync::mpsc::{Receiver, channel};
+
+/// The events an [`EventStream`] holds before discarding new ones.
+pub const STREAM_CAPACITY: usize = 1024;
+
+/// The global input events, as a [`Stream`]. See [`listen_stream`](crate::listen_stream).
+pub struct EventStream {
+    receiver: Receiver<Event>,
+    dropped: Arc<AtomicU64>,
+    _attachment: Attachment,
+}
+
+impl EventStream {
+    /// Waits for the next event, without needing `StreamExt`.
+    pub async fn recv(&mut self) -> Option<Event> {
+        self.receiver.recv().await
+    }
+
+    /// Number of events that were discarded because the stream was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Listens to global events as a stream, without blocking. Dropping the
+/// stream stops the listener, unless other streams or subscribers (see
+/// [`subscribe`](crate::subscribe)) still use it.
+/// The stream holds up to [`STREAM_CAPACITY`] events, new events are
+/// discarded while it is full, see [`EventStream::dropped`].
+/// Requires the `tokio` feature.
+///
+/// ```no_run
+/// use rdev::listen_stream;
+///
+/// # async fn run() {
+/// let mut events = listen_stream().unwrap();
+/// while let Some(event) = events.recv().await {
+///     println!("{:?}", event.event_type);
+/// }
+/// # }
+/// ```
+pub fn listen_stream() -> Result<EventStream, ListenError> {
+    let (sender, receiver) = channel(STREAM_CAPACITY);
+    let dropped = Arc::new(AtomicU64::new(0));
+    let attachment = attach(Box::new(|_| true), Sink::Stream(sender), dropped.clone())?;
+    Ok(EventStream {
+        receiver,
+        dropped,
+        _attachment: attachment,
+    })
+}
+
+/// Same as [`simulate`](crate::simulate), run on tokio's blocking thread pool
+/// since simulating may sleep. Requires the `tokio` feature.
+pub async fn simulate_async(event_type: &EventType) -> Result<(), SimulateError> {
+    let event_type = *event_type;
+    tokio::task::spawn_blocking(move || crate::simulate(&event_type))
+        .await
+        .unwrap_or(Err(SimulateError))
+}
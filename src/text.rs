@@ -0,0 +1,201 @@
+use crate::rdev::{EventType, Key, SimulateError, TextError};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use crate::linux::{type_layout, type_unicode};
+#[cfg(target_os = "macos")]
+use crate::macos::{type_layout, type_unicode};
+#[cfg(target_os = "windows")]
+use crate::windows::{type_layout, type_unicode};
+
+/// How [`TextBuilder`] types the characters. Newlines and tabs are always
+/// typed with the Return and Tab keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum TextStrategy {
+    /// The keys of the active layout, and Unicode injection for the
+    /// characters no key produces.
+    #[default]
+    LayoutThenUnicode,
+    /// Only the keys of the active layout, with modifiers and dead keys,
+    /// so that applications see regular key events. macOS has no reverse
+    /// layout lookup yet: only newlines and tabs are typed there.
+    Layout,
+    /// Injects the characters without going through the layout: Unicode
+    /// keyboard events on Windows and macOS, a temporarily remapped keycode
+    /// on Linux.
+    Unicode,
+}
+
+/// Types text, see [`simulate_text`] for the defaults.
+///
+/// ```no_run
+/// use rdev::{TextBuilder, TextStrategy};
+/// use std::time::Duration;
+///
+/// let result = TextBuilder::new()
+///     .strategy(TextStrategy::Layout)
+///     .delay(Duration::from_millis(10))
+///     .simulate("Hello, world!\n");
+/// if let Err(error) = result {
+///     println!("Could not type {:?}", error.untyped);
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TextBuilder {
+    strategy: TextStrategy,
+    delay: Duration,
+}
+
+impl TextBuilder {
+    pub fn new() -> TextBuilder {
+        TextBuilder::default()
+    }
+
+    pub fn strategy(mut self, strategy: TextStrategy) -> TextBuilder {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Waits this long after every character, for applications that drop
+    /// keys typed too fast. None by default.
+    pub fn delay(mut self, delay: Duration) -> TextBuilder {
+        self.delay = delay;
+        self
+    }
+
+    /// Types `text` one grapheme cluster at a time. The clusters that could
+    /// not be typed are skipped and reported in the error.
+    pub fn simulate(&self, text: &str) -> Result<(), TextError> {
+        let mut untyped = Vec::new();
+        let mut clusters = graphemes(text).peekable();
+        while let Some((offset, cluster)) = clusters.next() {
+            if self.type_cluster(cluster).is_err() {
+                untyped.push((offset, cluster.to_owned()));
+            }
+            if !self.delay.is_zero() && clusters.peek().is_some() {
+                thread::sleep(self.delay);
+            }
+        }
+        if untyped.is_empty() {
+            Ok(())
+        } else {
+            Err(TextError { untyped })
+        }
+    }
+
+    fn type_cluster(&self, cluster: &str) -> Result<(), SimulateError> {
+        match cluster {
+            // Injected line feeds are ignored by most applications.
+            "\n" | "\r" | "\r\n" => return tap(Key::Return),
+            "\t" => return tap(Key::Tab),
+            _ => {}
+        }
+        match self.strategy {
+            TextStrategy::LayoutThenUnicode => {
+                type_layout(cluster).or_else(|_| type_unicode(cluster))
+            }
+            TextStrategy::Layout => type_layout(cluster),
+            TextStrategy::Unicode => type_unicode(cluster),
+        }
+    }
+}
+
+/// Types `text` with the keys of the active layout, injecting the characters
+/// no key produces. Works with grapheme clusters, so that a letter and its
+/// combining accents are typed or reported together.
+///
+/// ```no_run
+/// use rdev::simulate_text;
+///
+/// simulate_text("Grüße 👋\n").unwrap();
+/// ```
+pub fn simulate_text(text: &str) -> Result<(), TextError> {
+    TextBuilder::new().simulate(text)
+}
+
+fn tap(key: Key) -> Result<(), SimulateError> {
+    crate::simulate(&EventType::KeyPress(key))?;
+    crate::simulate(&EventType::KeyRelease(key))
+}
+
+/// Splits `text` in grapheme clusters along with their byte offsets. This
+/// covers combining marks, variation selectors, emoji modifiers, zero width
+/// joiner sequences, flags and CRLF, not every rule of UAX #29.
+fn graphemes(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut chars = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let (start, first) = chars.next()?;
+        let mut previous = first;
+        let mut regional_indicators = usize::from(is_regional_indicator(first));
+        while let Some(&(_, next)) = chars.peek() {
+            let joins = (previous == '\r' && next == '\n')
+                || is_extend(next)
+                || previous == '\u{200d}'
+                || (is_regional_indicator(next) && regional_indicators % 2 == 1);
+            if !joins {
+                break;
+            }
+            if is_regional_indicator(next) {
+                regional_indicators += 1;
+            }
+            previous = next;
+            chars.next();
+        }
+        let end = chars.peek().map_or(text.len(), |(offset, _)| *offset);
+        Some((start, &text[start..end]))
+    })
+}
+
+fn is_extend(chr: char) -> bool {
+    matches!(chr as u32,
+        0x0300..=0x036f // Combining diacritical marks
+        | 0x0483..=0x0489
+        | 0x0591..=0x05bd
+        | 0x0610..=0x061a
+        | 0x064b..=0x065f
+        | 0x0e31
+        | 0x0e34..=0x0e3a
+        | 0x0e47..=0x0e4e
+        | 0x1ab0..=0x1aff
+        | 0x1dc0..=0x1dff
+        | 0x200d // Zero width joiner
+        | 0x20d0..=0x20ff
+        | 0xfe00..=0xfe0f // Variation selectors
+        | 0xfe20..=0xfe2f
+        | 0x1f3fb..=0x1f3ff // Emoji skin tones
+        | 0xe0020..=0xe007f // Tags
+        | 0xe0100..=0xe01ef
+    )
+}
+
+fn is_regional_indicator(chr: char) -> bool {
+    matches!(chr as u32, 0x1f1e6..=0x1f1ff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clusters(text: &str) -> Vec<&str> {
+        graphemes(text).map(|(_, cluster)| cluster).collect()
+    }
+
+    #[test]
+    fn test_graphemes() {
+        assert_eq!(clusters("abc"), ["a", "b", "c"]);
+        assert_eq!(clusters("e\u{301}t\u{e9}"), ["e\u{301}", "t", "\u{e9}"]);
+        assert_eq!(clusters("a\r\nb\n"), ["a", "\r\n", "b", "\n"]);
+        assert_eq!(clusters("👍🏽!"), ["👍🏽", "!"]);
+        assert_eq!(clusters("👨‍👩‍👧x"), ["👨‍👩‍👧", "x"]);
+        assert_eq!(clusters("🇫🇷🇩🇪"), ["🇫🇷", "🇩🇪"]);
+        assert_eq!(clusters(""), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_offsets() {
+        let offsets: Vec<usize> = graphemes("é\u{301}ab").map(|(offset, _)| offset).collect();
+        assert_eq!(offsets, [0, 4, 5]);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::keycodes::windows::key_from_code;
-use crate::rdev::{Button, EventType, Key};
+use crate::rdev::{Button, EventType, Key, Modifiers};
 use crate::windows::keyboard::Keyboard;
 use parking_lot::Mutex;
 use std::convert::TryInto;
@@ -11,11 +11,13 @@ use winapi::shared::ntdef::LONG;
 use winapi::shared::windef::HHOOK;
 use winapi::um::errhandlingapi::GetLastError;
 use winapi::um::winuser::{
-    GetForegroundWindow, GetKeyboardLayout, GetWindowThreadProcessId, KBDLLHOOKSTRUCT,
-    MAPVK_VK_TO_VSC_EX, MSLLHOOKSTRUCT, MapVirtualKeyExW, SetWindowsHookExA, VK_PACKET,
-    WH_KEYBOARD_LL, WH_MOUSE_LL, WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP,
-    WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEHWHEEL, WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_RBUTTONDOWN,
-    WM_RBUTTONUP, WM_SYSKEYDOWN, WM_SYSKEYUP, WM_XBUTTONDOWN, WM_XBUTTONUP,
+    GetAsyncKeyState, GetForegroundWindow, GetKeyState, GetKeyboardLayout,
+    GetWindowThreadProcessId, KBDLLHOOKSTRUCT, MAPVK_VK_TO_VSC_EX, MSLLHOOKSTRUCT,
+    MapVirtualKeyExW, SetWindowsHookExA, VK_CAPITAL, VK_CONTROL, VK_LCONTROL, VK_LMENU, VK_LWIN,
+    VK_NUMLOCK, VK_PACKET, VK_RMENU, VK_RWIN, VK_SHIFT, VkKeyScanExW, WH_KEYBOARD_LL, WH_MOUSE_LL,
+    WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP,
+    WM_MOUSEHWHEEL, WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_RBUTTONDOWN, WM_RBUTTONUP, WM_SYSKEYDOWN,
+    WM_SYSKEYUP, WM_XBUTTONDOWN, WM_XBUTTONUP, XBUTTON1, XBUTTON2,
 };
 
 /// Flag indicating the event was injected (programmatically generated) for keyboard hooks
@@ -61,6 +63,67 @@ pub fn get_modifier(key: Key) -> bool {
     KEYBOARD.lock().get_modifier(key)
 }
 
+// The last layout looked at, and whether it has an AltGr key.
+static ALT_GR_LAYOUT: Mutex<Option<(usize, bool)>> = Mutex::new(None);
+
+/// Whether the layout of the foreground window types characters with
+/// Ctrl+Alt, which is what its right Alt key does if it is AltGr.
+fn layout_has_alt_gr() -> bool {
+    let layout = unsafe {
+        let current_window_thread_id = GetWindowThreadProcessId(GetForegroundWindow(), null_mut());
+        GetKeyboardLayout(current_window_thread_id)
+    };
+    let mut cached = ALT_GR_LAYOUT.lock();
+    if let Some((cached_layout, has_alt_gr)) = *cached
+        && cached_layout == layout as usize
+    {
+        return has_alt_gr;
+    }
+    // The high byte is the shift state, 2 for Ctrl and 4 for Alt.
+    let has_alt_gr = (0x21..0x250).chain(['€' as u16]).any(|chr| {
+        let scan = unsafe { VkKeyScanExW(chr, layout) };
+        scan != -1 && (scan as u16 >> 8) & 6 == 6
+    });
+    *cached = Some((layout as usize, has_alt_gr));
+    has_alt_gr
+}
+
+/// The modifiers held and the lock keys on. Called from a low level hook,
+/// this is the state before the hooked event.
+///
+/// The right Alt key is Alt, and AltGr too when the layout has it or when
+/// it comes with the left Control key, as Windows sends for AltGr.
+pub fn get_modifiers() -> Modifiers {
+    let held = [
+        (VK_SHIFT, Modifiers::SHIFT),
+        (VK_CONTROL, Modifiers::CTRL),
+        (VK_LMENU, Modifiers::ALT),
+        (VK_RMENU, Modifiers::ALT),
+        (VK_LWIN, Modifiers::META),
+        (VK_RWIN, Modifiers::META),
+    ];
+    let toggled = [
+        (VK_CAPITAL, Modifiers::CAPS_LOCK),
+        (VK_NUMLOCK, Modifiers::NUM_LOCK),
+    ];
+    let is_down = |vk| unsafe { GetAsyncKeyState(vk) } as u16 & 0x8000 != 0;
+    let mut modifiers = Modifiers::empty();
+    for (vk, modifier) in held {
+        if is_down(vk) {
+            modifiers |= modifier;
+        }
+    }
+    if is_down(VK_RMENU) && (is_down(VK_LCONTROL) || layout_has_alt_gr()) {
+        modifiers |= Modifiers::ALT_GR;
+    }
+    for (vk, modifier) in toggled {
+        if unsafe { GetKeyState(vk) } & 1 != 0 {
+            modifiers |= modifier;
+        }
+    }
+    modifiers
+}
+
 pub unsafe fn get_code(lpdata: LPARAM) -> DWORD {
     unsafe {
         let kb = *(lpdata as *const KBDLLHOOKSTRUCT);
@@ -112,6 +175,14 @@ pub unsafe fn get_button_code(lpdata: LPARAM) -> WORD {
     }
 }
 
+unsafe fn x_button(lpdata: LPARAM) -> Button {
+    match unsafe { get_button_code(lpdata) } {
+        XBUTTON1 => Button::Back,
+        XBUTTON2 => Button::Forward,
+        code => Button::Unknown(code as u8),
+    }
+}
+
 pub unsafe fn convert(param: WPARAM, lpdata: LPARAM) -> (Option<EventType>, u16) {
     unsafe {
         let mut code = 0;
@@ -133,14 +204,8 @@ pub unsafe fn convert(param: WPARAM, lpdata: LPARAM) -> (Option<EventType>, u16)
                 Ok(WM_MBUTTONUP) => Some(EventType::ButtonRelease(Button::Middle)),
                 Ok(WM_RBUTTONDOWN) => Some(EventType::ButtonPress(Button::Right)),
                 Ok(WM_RBUTTONUP) => Some(EventType::ButtonRelease(Button::Right)),
-                Ok(WM_XBUTTONDOWN) => {
-                    let code = get_button_code(lpdata) as u8;
-                    Some(EventType::ButtonPress(Button::Unknown(code)))
-                }
-                Ok(WM_XBUTTONUP) => {
-                    let code = get_button_code(lpdata) as u8;
-                    Some(EventType::ButtonRelease(Button::Unknown(code)))
-                }
+                Ok(WM_XBUTTONDOWN) => Some(EventType::ButtonPress(x_button(lpdata))),
+                Ok(WM_XBUTTONUP) => Some(EventType::ButtonRelease(x_button(lpdata))),
                 Ok(WM_MOUSEMOVE) => {
                     let (x, y) = get_point(lpdata);
                     Some(EventType::MouseMove {
@@ -1,11 +1,12 @@
 use crate::{
     rdev::{Event, EventType, GrabError},
     windows::common::{
-        HookError, KEYBOARD, convert, get_scan_code, is_keyboard_injected, is_mouse_injected,
+        HookError, KEYBOARD, convert, get_modifiers, get_scan_code, is_keyboard_injected,
+        is_mouse_injected,
     },
 };
 use parking_lot::Mutex;
-use std::sync::{LazyLock, OnceLock};
+use std::sync::LazyLock;
 use std::{io::Error, ptr::null_mut, time::SystemTime};
 use tracing::error;
 
@@ -28,9 +29,10 @@ use winapi::{
 
 use std::sync::atomic::{AtomicBool, Ordering};
 
-type GrabCallback = Mutex<Box<dyn FnMut(Event) -> Option<Event> + Send>>;
+type GrabCallback = Box<dyn FnMut(Event) -> Option<Event> + Send>;
 
-static GLOBAL_CALLBACK: OnceLock<GrabCallback> = OnceLock::new();
+// Cleared when the grab ends, so that it can be started again.
+static GLOBAL_CALLBACK: Mutex<Option<GrabCallback>> = Mutex::new(None);
 static GET_KEY_UNICODE: AtomicBool = AtomicBool::new(true);
 
 static CUR_HOOK_THREAD_ID: LazyLock<Mutex<DWORD>> = LazyLock::new(|| Mutex::new(0));
@@ -76,10 +78,10 @@ unsafe fn raw_callback(
                     usb_hid: 0,
                     extra_data: f_get_extra_data(lpdata),
                     is_synthetic: f_is_injected(lpdata),
+                    modifiers: get_modifiers(),
                 };
 
-                if let Some(callback_mutex) = GLOBAL_CALLBACK.get() {
-                    let mut callback = callback_mutex.lock();
+                if let Some(callback) = GLOBAL_CALLBACK.lock().as_mut() {
                     if callback(event).is_none() {
                         // https://stackoverflow.com/questions/42756284/blocking-windows-mouse-click-using-setwindowshookex
                         // https://android.developreference.com/article/14560004/Blocking+windows+mouse+click+using+SetWindowsHookEx()
@@ -140,18 +142,22 @@ where
     let hook_keyboard;
     let mut hook_mouse = null_mut();
 
-    // Initialize callback in OnceLock
-    if GLOBAL_CALLBACK.set(Mutex::new(Box::new(callback))).is_err() {
-        error!(
-            "grab() called multiple times - this is not allowed. Only one grab can be active at a time."
-        );
-        return Err(GrabError::AlreadyGrabbing);
+    {
+        let mut global_callback = GLOBAL_CALLBACK.lock();
+        if global_callback.is_some() {
+            error!(
+                "grab() called multiple times - this is not allowed. Only one grab can be active at a time."
+            );
+            return Err(GrabError::AlreadyGrabbing);
+        }
+        *global_callback = Some(Box::new(callback));
     }
 
     unsafe {
         hook_keyboard =
             SetWindowsHookExA(WH_KEYBOARD_LL, Some(raw_callback_keyboard), null_mut(), 0);
         if hook_keyboard.is_null() {
+            *GLOBAL_CALLBACK.lock() = None;
             return Err(GrabError::KeyHookError(GetLastError()));
         }
 
@@ -165,6 +171,7 @@ where
                         Error::last_os_error()
                     );
                 }
+                *GLOBAL_CALLBACK.lock() = None;
                 return Err(GrabError::MouseHookError(GetLastError()));
             }
         }
@@ -233,6 +240,7 @@ where
         }
 
         *CUR_HOOK_THREAD_ID.lock() = 0;
+        *GLOBAL_CALLBACK.lock() = None;
     }
     Ok(())
 }
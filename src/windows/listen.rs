@@ -1,9 +1,10 @@
 use crate::{
     keycodes::windows::key_from_code,
+    listen::ListenerBuilder,
     rdev::{Button, Event, EventType, ListenError},
     windows::common::{
-        HookError, WHEEL_DELTA, convert, get_scan_code, is_keyboard_injected, is_mouse_injected,
-        set_key_hook, set_mouse_hook,
+        HookError, KEYBOARD_HOOK, MOUSE_HOOK, WHEEL_DELTA, convert, get_modifiers, get_scan_code,
+        is_keyboard_injected, is_mouse_injected, set_key_hook, set_mouse_hook,
     },
 };
 use parking_lot::Mutex;
@@ -13,7 +14,7 @@ use std::{
     os::raw::c_int,
     ptr::null_mut,
     sync::{
-        LazyLock, OnceLock,
+        LazyLock,
         atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering},
     },
     time::SystemTime,
@@ -22,20 +23,22 @@ use tracing::{debug, error, warn};
 use winapi::{
     shared::{
         hidusage::{HID_USAGE_GENERIC_KEYBOARD, HID_USAGE_GENERIC_MOUSE, HID_USAGE_PAGE_GENERIC},
-        minwindef::{LPARAM, LRESULT, UINT, WPARAM},
+        minwindef::{DWORD, FALSE, LPARAM, LRESULT, UINT, WPARAM},
         ntdef::{NTSTATUS, ULONG, USHORT},
         windef::HWND,
     },
     um::{
+        errhandlingapi::GetLastError,
         libloaderapi::GetModuleHandleA,
+        processthreadsapi::GetCurrentThreadId,
         winuser::{
-            CS_HREDRAW, CS_VREDRAW, CallNextHookEx, CreateWindowExA, DefWindowProcA,
+            CS_HREDRAW, CS_VREDRAW, CallNextHookEx, CreateWindowExA, DefWindowProcA, DestroyWindow,
             DispatchMessageA, GetMessageA, GetRawInputData, GetRawInputDeviceInfoA, HC_ACTION,
-            HRAWINPUT, MSG, PKBDLLHOOKSTRUCT, PMOUSEHOOKSTRUCT, RAWINPUT, RAWINPUTDEVICE,
-            RAWINPUTHEADER, RI_KEY_BREAK, RI_MOUSE_WHEEL, RID_INPUT, RIDEV_INPUTSINK,
-            RIDI_PREPARSEDDATA, RIM_TYPEHID, RIM_TYPEKEYBOARD, RIM_TYPEMOUSE, RegisterClassExA,
-            RegisterRawInputDevices, TranslateMessage, WM_INPUT, WNDCLASSEXA, WS_EX_NOACTIVATE,
-            WS_EX_TOOLWINDOW, WS_POPUP,
+            HRAWINPUT, MSG, PKBDLLHOOKSTRUCT, PMOUSEHOOKSTRUCT, PostThreadMessageA, RAWINPUT,
+            RAWINPUTDEVICE, RAWINPUTHEADER, RI_KEY_BREAK, RI_MOUSE_WHEEL, RID_INPUT,
+            RIDEV_INPUTSINK, RIDI_PREPARSEDDATA, RIM_TYPEHID, RIM_TYPEKEYBOARD, RIM_TYPEMOUSE,
+            RegisterClassExA, RegisterRawInputDevices, TranslateMessage, UnhookWindowsHookEx,
+            WM_INPUT, WM_QUIT, WNDCLASSEXA, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW, WS_POPUP,
         },
     },
 };
@@ -98,6 +101,10 @@ const RI_MOUSE_RIGHT_BUTTON_DOWN: u16 = 0x0004;
 const RI_MOUSE_RIGHT_BUTTON_UP: u16 = 0x0008;
 const RI_MOUSE_MIDDLE_BUTTON_DOWN: u16 = 0x0010;
 const RI_MOUSE_MIDDLE_BUTTON_UP: u16 = 0x0020;
+const RI_MOUSE_BUTTON_4_DOWN: u16 = 0x0040;
+const RI_MOUSE_BUTTON_4_UP: u16 = 0x0080;
+const RI_MOUSE_BUTTON_5_DOWN: u16 = 0x0100;
+const RI_MOUSE_BUTTON_5_UP: u16 = 0x0200;
 
 // HID Usage Page for Digitizers (touchpads, touchscreens, etc.)
 const HID_USAGE_PAGE_DIGITIZER: u16 = 0x0D;
@@ -109,9 +116,12 @@ const HID_USAGE_DIGITIZER_CONTACT_COUNT: u16 = 0x54;
 const HID_USAGE_GENERIC_X: u16 = 0x30;
 const HID_USAGE_GENERIC_Y: u16 = 0x31;
 
-type ListenCallback = Mutex<Box<dyn FnMut(Event) + Send>>;
+type ListenCallback = Box<dyn FnMut(Event) + Send>;
 
-static GLOBAL_CALLBACK: OnceLock<ListenCallback> = OnceLock::new();
+// Cleared when listen returns, so that it can be called again.
+static GLOBAL_CALLBACK: Mutex<Option<ListenCallback>> = Mutex::new(None);
+// The thread running the message loop, 0 when not listening.
+static LISTEN_THREAD_ID: Mutex<DWORD> = Mutex::new(0);
 
 // Cache for preparsed data per device (keyed by device handle)
 static PREPARSED_DATA_CACHE: LazyLock<Mutex<HashMap<usize, Vec<u8>>>> =
@@ -156,9 +166,9 @@ unsafe fn raw_callback(
                     usb_hid: 0,
                     extra_data: f_get_extra_data(lpdata),
                     is_synthetic: f_is_injected(lpdata),
+                    modifiers: get_modifiers(),
                 };
-                if let Some(callback_mutex) = GLOBAL_CALLBACK.get() {
-                    let mut callback = callback_mutex.lock();
+                if let Some(callback) = GLOBAL_CALLBACK.lock().as_mut() {
                     callback(event);
                 }
             }
@@ -279,6 +289,18 @@ unsafe fn handle_raw_mouse_input(raw: &RAWINPUT) {
         if button_flags & RI_MOUSE_MIDDLE_BUTTON_UP != 0 {
             emit_raw_event(EventType::ButtonReleaseRaw(Button::Middle));
         }
+        if button_flags & RI_MOUSE_BUTTON_4_DOWN != 0 {
+            emit_raw_event(EventType::ButtonPressRaw(Button::Back));
+        }
+        if button_flags & RI_MOUSE_BUTTON_4_UP != 0 {
+            emit_raw_event(EventType::ButtonReleaseRaw(Button::Back));
+        }
+        if button_flags & RI_MOUSE_BUTTON_5_DOWN != 0 {
+            emit_raw_event(EventType::ButtonPressRaw(Button::Forward));
+        }
+        if button_flags & RI_MOUSE_BUTTON_5_UP != 0 {
+            emit_raw_event(EventType::ButtonReleaseRaw(Button::Forward));
+        }
 
         // Emit raw movement events (relative deltas)
         let delta_x = mouse.lLastX;
@@ -547,10 +569,10 @@ unsafe fn emit_raw_event(event_type: EventType) {
         usb_hid: 0,
         extra_data: 0,
         is_synthetic: false, // Raw Input always comes from hardware
+        modifiers: get_modifiers(),
     };
 
-    if let Some(callback_mutex) = GLOBAL_CALLBACK.get() {
-        let mut callback = callback_mutex.lock();
+    if let Some(callback) = GLOBAL_CALLBACK.lock().as_mut() {
         callback(event);
     }
 }
@@ -601,32 +623,38 @@ unsafe fn create_hidden_window() -> Option<HWND> {
     }
 }
 
-/// Register for Raw Input from both mice and precision touchpads
-unsafe fn register_raw_input(hwnd: HWND) -> bool {
+/// Register for Raw Input from the device kinds `config` needs: mice,
+/// precision touchpads for scrolling, keyboards
+unsafe fn register_raw_input(hwnd: HWND, config: &ListenerBuilder) -> bool {
     unsafe {
-        let devices = [
+        let mut devices = Vec::with_capacity(3);
+        if config.mouse() {
             // Traditional mouse input
-            RAWINPUTDEVICE {
+            devices.push(RAWINPUTDEVICE {
                 usUsagePage: HID_USAGE_PAGE_GENERIC,
                 usUsage: HID_USAGE_GENERIC_MOUSE,
                 dwFlags: RIDEV_INPUTSINK, // Receive input even when not in foreground
                 hwndTarget: hwnd,
-            },
+            });
+        }
+        if config.wheel {
             // Precision touchpad input (HID digitizer)
-            RAWINPUTDEVICE {
+            devices.push(RAWINPUTDEVICE {
                 usUsagePage: HID_USAGE_PAGE_DIGITIZER,
                 usUsage: HID_USAGE_DIGITIZER_TOUCH_PAD,
                 dwFlags: RIDEV_INPUTSINK,
                 hwndTarget: hwnd,
-            },
+            });
+        }
+        if config.keys {
             // Keyboard input
-            RAWINPUTDEVICE {
+            devices.push(RAWINPUTDEVICE {
                 usUsagePage: HID_USAGE_PAGE_GENERIC,
                 usUsage: HID_USAGE_GENERIC_KEYBOARD,
                 dwFlags: RIDEV_INPUTSINK,
                 hwndTarget: hwnd,
-            },
-        ];
+            });
+        }
 
         RegisterRawInputDevices(
             devices.as_ptr(),
@@ -636,45 +664,96 @@ unsafe fn register_raw_input(hwnd: HWND) -> bool {
     }
 }
 
-pub fn listen<T>(callback: T) -> Result<(), ListenError>
+pub fn listen<T>(config: &ListenerBuilder, callback: T) -> Result<(), ListenError>
 where
     T: FnMut(Event) + Send + 'static,
 {
-    // Initialize callback in OnceLock
-    if GLOBAL_CALLBACK.set(Mutex::new(Box::new(callback))).is_err() {
-        error!(
-            "listen() called multiple times - this is not allowed. Only one listener can be active at a time."
-        );
-        return Err(ListenError::AlreadyListening);
+    {
+        let mut global_callback = GLOBAL_CALLBACK.lock();
+        if global_callback.is_some() {
+            error!(
+                "listen() called multiple times - this is not allowed. Only one listener can be active at a time."
+            );
+            return Err(ListenError::AlreadyListening);
+        }
+        *global_callback = Some(Box::new(callback));
     }
 
-    unsafe {
-        set_key_hook(raw_callback_keyboard)?;
+    let result = unsafe { run(config) };
+    unsafe { unhook() };
+    *GLOBAL_CALLBACK.lock() = None;
+    result
+}
 
-        let using_mouse = !crate::keyboard_only();
-        if using_mouse {
+unsafe fn run(config: &ListenerBuilder) -> Result<(), ListenError> {
+    unsafe {
+        if config.keys {
+            set_key_hook(raw_callback_keyboard)?;
+        }
+        if config.mouse() {
             set_mouse_hook(raw_callback_mouse)?;
+        }
 
+        let mut hidden_window = None;
+        if config.raw && (config.keys || config.mouse()) {
             // Create hidden window and register for Raw Input to capture all scroll events
             // Raw Input handles wheel events from ALL devices (traditional mice, gaming mice,
             // precision touchpads) - the hook also handles wheel for apps where Raw Input fails
             if let Some(hwnd) = create_hidden_window() {
-                if register_raw_input(hwnd) {
-                    debug!("Raw Input registered successfully for scroll events");
+                if register_raw_input(hwnd, config) {
+                    debug!("Raw Input registered successfully");
                 } else {
-                    warn!("Failed to register for Raw Input - scroll events may not work");
+                    warn!("Failed to register for Raw Input - raw events may not work");
                 }
+                hidden_window = Some(hwnd);
             } else {
                 warn!("Failed to create hidden window for Raw Input");
             }
         }
 
-        // Message loop - handles both hook messages and WM_INPUT
+        *LISTEN_THREAD_ID.lock() = GetCurrentThreadId();
+        // Message loop - handles both hook messages and WM_INPUT, until
+        // exit_listen posts WM_QUIT
         let mut msg: MSG = MaybeUninit::zeroed().assume_init();
         while GetMessageA(&mut msg, null_mut(), 0, 0) > 0 {
             TranslateMessage(&msg);
             DispatchMessageA(&msg);
         }
+        *LISTEN_THREAD_ID.lock() = 0;
+
+        if let Some(hwnd) = hidden_window {
+            DestroyWindow(hwnd);
+        }
+    }
+    Ok(())
+}
+
+unsafe fn unhook() {
+    unsafe {
+        if !KEYBOARD_HOOK.is_null() && FALSE == UnhookWindowsHookEx(KEYBOARD_HOOK) {
+            error!("Failed UnhookWindowsHookEx keyboard: {}", GetLastError());
+        }
+        KEYBOARD_HOOK = null_mut();
+        if !MOUSE_HOOK.is_null() && FALSE == UnhookWindowsHookEx(MOUSE_HOOK) {
+            error!("Failed UnhookWindowsHookEx mouse: {}", GetLastError());
+        }
+        MOUSE_HOOK = null_mut();
+    }
+}
+
+#[inline]
+pub fn is_listening() -> bool {
+    *LISTEN_THREAD_ID.lock() != 0
+}
+
+/// Stops a running `listen`, which then returns `Ok(())`.
+pub fn exit_listen() -> Result<(), ListenError> {
+    let thread_id = *LISTEN_THREAD_ID.lock();
+    if thread_id != 0 && FALSE == unsafe { PostThreadMessageA(thread_id, WM_QUIT, 0, 0) } {
+        return Err(ListenError::ExitListenError(format!(
+            "Failed to post message to exit listen, {}",
+            unsafe { GetLastError() }
+        )));
     }
     Ok(())
 }
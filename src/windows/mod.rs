@@ -11,5 +11,5 @@ pub use crate::windows::common::*;
 pub use crate::windows::display::display_size;
 pub use crate::windows::grab::{exit_grab, grab, is_grabbed, set_event_popup, set_get_key_unicode};
 pub use crate::windows::keyboard::Keyboard;
-pub use crate::windows::listen::listen;
+pub use crate::windows::listen::{exit_listen, is_listening, listen};
 pub use crate::windows::simulate::*;
@@ -18,7 +18,7 @@ use winapi::um::winuser::{
     MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_MOVE, MOUSEEVENTF_RIGHTDOWN,
     MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_VIRTUALDESK, MOUSEEVENTF_WHEEL, MOUSEEVENTF_XDOWN,
     MOUSEEVENTF_XUP, MOUSEINPUT, MapVirtualKeyExW, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN,
-    SendInput, VkKeyScanExW,
+    SendInput, VkKeyScanExW, XBUTTON1, XBUTTON2,
 };
 
 // KEYBDINPUT
@@ -144,13 +144,17 @@ pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
             Button::Left => sim_mouse_event(MOUSEEVENTF_LEFTDOWN, 0, 0, 0),
             Button::Middle => sim_mouse_event(MOUSEEVENTF_MIDDLEDOWN, 0, 0, 0),
             Button::Right => sim_mouse_event(MOUSEEVENTF_RIGHTDOWN, 0, 0, 0),
-            Button::Unknown(code) => sim_mouse_event(MOUSEEVENTF_XDOWN, 0, 0, (*code).into()),
+            Button::Back => sim_mouse_event(MOUSEEVENTF_XDOWN, XBUTTON1.into(), 0, 0),
+            Button::Forward => sim_mouse_event(MOUSEEVENTF_XDOWN, XBUTTON2.into(), 0, 0),
+            Button::Unknown(code) => sim_mouse_event(MOUSEEVENTF_XDOWN, (*code).into(), 0, 0),
         },
         EventType::ButtonRelease(button) => match button {
             Button::Left => sim_mouse_event(MOUSEEVENTF_LEFTUP, 0, 0, 0),
             Button::Middle => sim_mouse_event(MOUSEEVENTF_MIDDLEUP, 0, 0, 0),
             Button::Right => sim_mouse_event(MOUSEEVENTF_RIGHTUP, 0, 0, 0),
-            Button::Unknown(code) => sim_mouse_event(MOUSEEVENTF_XUP, 0, 0, (*code).into()),
+            Button::Back => sim_mouse_event(MOUSEEVENTF_XUP, XBUTTON1.into(), 0, 0),
+            Button::Forward => sim_mouse_event(MOUSEEVENTF_XUP, XBUTTON2.into(), 0, 0),
+            Button::Unknown(code) => sim_mouse_event(MOUSEEVENTF_XUP, (*code).into(), 0, 0),
         },
         EventType::Wheel { delta_x, delta_y } => {
             if *delta_x != 0.0 {
@@ -193,6 +197,27 @@ pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
     }
 }
 
+/// Simulates events like `simulate`. `SendInput` needs no connection, this is
+/// for the same API on every platform.
+#[derive(Debug, Default)]
+pub struct Simulator;
+
+impl Simulator {
+    pub fn new() -> Result<Simulator, SimulateError> {
+        Ok(Simulator)
+    }
+
+    pub fn simulate(&self, event_type: &EventType) -> Result<(), SimulateError> {
+        simulate(event_type)
+    }
+
+    /// Simulates the events in order, stopping at the first one that cannot
+    /// be simulated.
+    pub fn simulate_all(&self, events: &[EventType]) -> Result<(), SimulateError> {
+        events.iter().try_for_each(simulate)
+    }
+}
+
 pub fn simulate_code(
     vk: Option<u16>,
     scan: Option<u32>,
@@ -275,6 +300,20 @@ pub fn simulate_unicode(unicode: u16) -> Result<(), SimulateError> {
     sim_keyboard_event(KEYEVENTF_UNICODE | KEYEVENTF_KEYUP, 0, unicode)
 }
 
+/// Types `text` with the keys of the active layout, which only has keys for
+/// single UTF-16 units.
+pub(crate) fn type_layout(text: &str) -> Result<(), SimulateError> {
+    let mut units = text.encode_utf16();
+    match (units.next(), units.next()) {
+        (Some(unit), None) => simulate_key_unicode(unit, false),
+        _ => Err(SimulateError),
+    }
+}
+
+pub(crate) fn type_unicode(text: &str) -> Result<(), SimulateError> {
+    simulate_unistr(text)
+}
+
 #[inline]
 pub fn simulate_unistr(unistr: &str) -> Result<(), SimulateError> {
     for unicode in unistr.encode_utf16() {
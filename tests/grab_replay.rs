@@ -0,0 +1,42 @@
+// The X11 grab lets events through by faking them again through XTest.
+#![cfg(all(target_os = "linux", not(feature = "unstable_grab")))]
+use rdev::{EventType, Key, set_keyboard_extra_info, simulate, start_grab, start_listen};
+use serial_test::serial;
+use std::error::Error;
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+#[serial]
+fn test_replayed_events_are_not_synthetic() -> Result<(), Box<dyn Error>> {
+    // Wait for tester's key to go back up
+    thread::sleep(Duration::from_millis(300));
+
+    let (sender, receiver) = channel();
+    let listener = start_listen(move |event| {
+        if let EventType::KeyPress(Key::KeyS) = event.event_type {
+            let _ = sender.send(event);
+        }
+    })
+    .expect("Could not listen");
+    let grab = start_grab(Some).expect("Could not grab");
+
+    set_keyboard_extra_info(42);
+    simulate(&EventType::KeyPress(Key::KeyS))?;
+    simulate(&EventType::KeyRelease(Key::KeyS))?;
+    set_keyboard_extra_info(0);
+
+    let timeout = Duration::from_secs(1);
+    // What was simulated, then what the grab let through.
+    let simulated = receiver.recv_timeout(timeout)?;
+    assert!(simulated.is_synthetic);
+    assert_eq!(simulated.extra_data, 42);
+    let replayed = receiver.recv_timeout(timeout)?;
+    assert!(!replayed.is_synthetic);
+    assert_eq!(replayed.extra_data, 0);
+
+    grab.stop().expect("Could not stop the grab");
+    listener.stop().expect("Could not stop listening");
+    Ok(())
+}
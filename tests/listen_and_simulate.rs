@@ -1,4 +1,4 @@
-use rdev::{Button, Event, EventType, Key, listen, simulate};
+use rdev::{Button, Event, EventType, Key, Simulator, listen, simulate};
 use serial_test::serial;
 use std::error::Error;
 use std::iter::Iterator;
@@ -70,3 +70,38 @@ fn test_listen_and_simulate() -> Result<(), Box<dyn Error>> {
     let mut events = events.chain(click_events);
     sim_then_listen(&mut events)
 }
+
+#[test]
+#[serial]
+fn test_listen_and_simulator() -> Result<(), Box<dyn Error>> {
+    // The listener of the other test keeps running if it ran first.
+    let _listener = thread::spawn(move || {
+        let _ = listen(send_event);
+    });
+    let second = Duration::from_millis(1000);
+    thread::sleep(second);
+
+    let recv = EVENT_CHANNEL.1.lock()?;
+    let simulator = Simulator::new()?;
+    for event in [
+        EventType::KeyPress(Key::KeyS),
+        EventType::KeyRelease(Key::KeyS),
+    ] {
+        simulator.simulate(&event)?;
+        let recieved_event = recv.recv_timeout(second).expect("No events to recieve");
+        assert_eq!(recieved_event.event_type, event);
+    }
+
+    let batch: Vec<EventType> = (0..100)
+        .map(|pixel| EventType::MouseMove {
+            x: pixel as f64,
+            y: pixel as f64,
+        })
+        .collect();
+    simulator.simulate_all(&batch)?;
+    for event in batch {
+        let recieved_event = recv.recv_timeout(second).expect("No events to recieve");
+        assert_eq!(recieved_event.event_type, event);
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+#![cfg(target_os = "linux")]
+use rdev::{Button, Event, EventType, Key, VirtualDevice, start_listen_evdev};
+use serial_test::serial;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+static EVENT_CHANNEL: LazyLock<(Mutex<Sender<Event>>, Mutex<Receiver<Event>>)> =
+    LazyLock::new(|| {
+        let (send, recv) = channel();
+        (Mutex::new(send), Mutex::new(recv))
+    });
+
+fn send_event(event: Event) {
+    // Only keep what we injected ourselves, not what the user is doing.
+    if !event.is_synthetic {
+        return;
+    }
+    EVENT_CHANNEL
+        .0
+        .lock()
+        .expect("Failed to unlock Mutex")
+        .send(event)
+        .expect("Receiving end of EVENT_CHANNEL was closed");
+}
+
+fn uinput_available() -> bool {
+    if OpenOptions::new().write(true).open("/dev/uinput").is_err() {
+        eprintln!("/dev/uinput is not writable, skipping");
+        return false;
+    }
+    true
+}
+
+#[test]
+#[serial]
+fn test_uinput_and_listen_evdev() -> Result<(), Box<dyn Error>> {
+    if !uinput_available() {
+        return Ok(());
+    }
+    let mut device = VirtualDevice::new()?;
+    let listener = start_listen_evdev(send_event).expect("Could not listen");
+    let second = Duration::from_millis(1000);
+
+    let events = vec![
+        EventType::KeyPress(Key::ShiftLeft),
+        EventType::KeyPress(Key::KeyS),
+        EventType::KeyRelease(Key::KeyS),
+        EventType::KeyRelease(Key::ShiftLeft),
+        EventType::ButtonPress(Button::Right),
+        EventType::ButtonRelease(Button::Right),
+        EventType::Wheel {
+            delta_x: 0.0,
+            delta_y: 1.0,
+        },
+        EventType::Wheel {
+            delta_x: -1.0,
+            delta_y: 0.0,
+        },
+        EventType::MouseMoveRaw {
+            delta_x: 3,
+            delta_y: -4,
+        },
+    ];
+    let recv = EVENT_CHANNEL.1.lock()?;
+    for event in events {
+        device.send(&event)?;
+        let recieved_event = recv.recv_timeout(second).expect("No events to recieve");
+        assert_eq!(recieved_event.event_type, event);
+        // Relative motion is also reported as a MouseMove.
+        if let EventType::MouseMoveRaw { .. } = event {
+            let recieved_event = recv.recv_timeout(second).expect("No events to recieve");
+            assert!(matches!(
+                recieved_event.event_type,
+                EventType::MouseMove { .. }
+            ));
+        }
+    }
+    listener.stop().expect("Could not stop listening");
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_restart_listen_evdev() -> Result<(), Box<dyn Error>> {
+    if !uinput_available() {
+        return Ok(());
+    }
+    let mut device = VirtualDevice::new()?;
+    let second = Duration::from_millis(1000);
+    let recv = EVENT_CHANNEL.1.lock()?;
+
+    start_listen_evdev(send_event)
+        .expect("Could not listen")
+        .stop()
+        .expect("Could not stop listening");
+    let listener = start_listen_evdev(send_event).expect("Could not listen");
+    while recv.try_recv().is_ok() {}
+    let event = EventType::KeyPress(Key::KeyQ);
+    device.send(&event)?;
+    let recieved_event = recv.recv_timeout(second).expect("No events to recieve");
+    assert_eq!(recieved_event.event_type, event);
+    device.send(&EventType::KeyRelease(Key::KeyQ))?;
+    listener.stop().expect("Could not stop listening");
+    Ok(())
+}